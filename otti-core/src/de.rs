@@ -1,4 +1,4 @@
-//! Custom (de-)serialization implementations for [`serde`].
+//! Custom (de-)serialization implementations for [`serde`] and related decoding helpers.
 
 pub mod base32_string {
     //! (De-)serialization support for raw byte data as Base32 string.
@@ -18,17 +18,20 @@ pub mod base32_string {
         serializer.serialize_str(&data_encoding::BASE32_NOPAD.encode(value))
     }
 
-    /// Deserialize a Base32 string back into a byte slice.
-    pub fn deserialize<'de, D>(deserializer: D) -> Result<Vec<u8>, D::Error>
+    /// Deserialize a Base32 string back into bytes, like a plain or zeroizing byte vector.
+    pub fn deserialize<'de, D, T>(deserializer: D) -> Result<T, D::Error>
     where
         D: Deserializer<'de>,
+        T: From<Vec<u8>>,
     {
-        deserializer.deserialize_str(Base32StringVisitor)
+        deserializer
+            .deserialize_str(Base32StringVisitor)
+            .map(T::from)
     }
 
     struct Base32StringVisitor;
 
-    impl<'de> Visitor<'de> for Base32StringVisitor {
+    impl Visitor<'_> for Base32StringVisitor {
         type Value = Vec<u8>;
 
         fn expecting(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
@@ -45,3 +48,30 @@ pub mod base32_string {
         }
     }
 }
+
+/// Decode a bare Base32 secret, which is commonly entered by hand and may therefore contain
+/// whitespace, lowercase letters or padding.
+pub fn decode_secret(secret: &str) -> Result<Vec<u8>, data_encoding::DecodeError> {
+    let secret = secret
+        .chars()
+        .filter(|c| !c.is_whitespace() && *c != '=')
+        .map(|c| c.to_ascii_uppercase())
+        .collect::<String>();
+
+    data_encoding::BASE32_NOPAD.decode(secret.as_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decode_handwritten_secret() {
+        assert_eq!(b"hello".as_slice(), decode_secret("nbsw y3dp").unwrap());
+        assert_eq!(
+            b"hello!".as_slice(),
+            decode_secret("NBSWY3DPEE======").unwrap()
+        );
+        assert!(decode_secret("NBSWY3D1").is_err());
+    }
+}
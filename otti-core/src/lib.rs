@@ -10,8 +10,9 @@
 use std::{collections::BTreeMap, str::FromStr};
 
 pub use key::Key;
-pub use secrecy::ExposeSecret;
+pub use secrecy::{ExposeSecret, SecretString};
 use serde::{Deserialize, Serialize};
+pub use zeroize::Zeroizing;
 
 #[cfg(feature = "otpurl")]
 pub use self::url::ParseError;
@@ -47,6 +48,19 @@ pub struct Account {
     pub extras: BTreeMap<String, Vec<u8>>,
 }
 
+#[cfg(feature = "otpurl")]
+impl Account {
+    /// Convert the account into an `otpauth://` URL, the counterpart to parsing an account
+    /// through [`FromStr`].
+    ///
+    /// The URL contains the secret in plain (Base32 encoded) form, so it must be handled with
+    /// the same care as the account itself.
+    #[must_use]
+    pub fn to_url(&self) -> String {
+        crate::url::format(self)
+    }
+}
+
 #[cfg(feature = "otpurl")]
 impl FromStr for Account {
     type Err = crate::url::ParseError;
@@ -58,8 +72,8 @@ impl FromStr for Account {
 
 /// Base information about the OTP used. The most common are HOTP and TOTP but there are many
 /// platform specific variations in the wild, like for Steam.
-#[derive(Clone, Debug, Serialize, Deserialize)]
-#[cfg_attr(test, derive(Eq, PartialEq, PartialOrd))]
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(test, derive(PartialOrd))]
 pub enum Otp {
     /// Counter based, using a counter as base of the OTP generation.
     ///
@@ -90,8 +104,8 @@ pub enum Otp {
 }
 
 /// Algorithm used in the OTP generation to create the final code.
-#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
-#[cfg_attr(test, derive(Eq, PartialEq, PartialOrd))]
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(test, derive(PartialOrd))]
 pub enum Algorithm {
     /// SHA-1 algorithm, most common.
     Sha1,
@@ -102,8 +116,8 @@ pub enum Algorithm {
 }
 
 /// Additional metadata that is specific to **Otti** and mostly user provided.
-#[derive(Clone, Debug, Default, Serialize, Deserialize)]
-#[cfg_attr(test, derive(Eq, PartialEq, PartialOrd))]
+#[derive(Clone, Debug, Default, Eq, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(test, derive(PartialOrd))]
 pub struct Metadata {
     /// Free list of tags to classify or group accounts.
     pub tags: Vec<String>,
@@ -1,5 +1,7 @@
-use std::{collections::BTreeMap, convert::TryFrom, str::FromStr};
+use std::{collections::BTreeMap, convert::TryFrom, fmt::Write, str::FromStr};
 
+use percent_encoding::{utf8_percent_encode, AsciiSet, NON_ALPHANUMERIC};
+use secrecy::ExposeSecret;
 use serde::Deserialize;
 
 use crate::{Account, Algorithm, Key, Metadata, Otp};
@@ -92,6 +94,14 @@ impl FromStr for OtpType {
     }
 }
 
+/// Characters that are kept as-is when encoding the label or issuer of an URL. Everything else is
+/// percent-encoded.
+const COMPONENT: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'-')
+    .remove(b'.')
+    .remove(b'_')
+    .remove(b'~');
+
 #[inline(always)]
 fn default_algorithm() -> ParamsAlgorithm {
     ParamsAlgorithm(Algorithm::Sha1)
@@ -150,6 +160,48 @@ pub fn parse(value: &str) -> Result<Account, ParseError> {
     })
 }
 
+pub fn format(account: &Account) -> String {
+    let (otp_type, param) = match account.otp {
+        Otp::Hotp { counter } => ("hotp", ("counter", counter)),
+        Otp::Totp { window } => ("totp", ("period", window)),
+        Otp::Steam { period } => ("steam", ("period", period)),
+    };
+
+    let mut url = format!("otpauth://{otp_type}/");
+
+    if let Some(issuer) = account.issuer.as_deref().filter(|i| !i.is_empty()) {
+        write!(url, "{}:", utf8_percent_encode(issuer, COMPONENT)).ok();
+    }
+
+    write!(
+        url,
+        "{}?secret={}",
+        utf8_percent_encode(&account.label, COMPONENT),
+        data_encoding::BASE32_NOPAD.encode(account.secret.expose_secret())
+    )
+    .ok();
+
+    if let Some(issuer) = account.issuer.as_deref().filter(|i| !i.is_empty()) {
+        write!(url, "&issuer={}", utf8_percent_encode(issuer, COMPONENT)).ok();
+    }
+
+    write!(
+        url,
+        "&algorithm={}&digits={}&{}={}",
+        match account.algorithm {
+            Algorithm::Sha1 => "SHA1",
+            Algorithm::Sha256 => "SHA256",
+            Algorithm::Sha512 => "SHA512",
+        },
+        account.digits,
+        param.0,
+        param.1,
+    )
+    .ok();
+
+    url
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -175,4 +227,26 @@ mod tests {
 
         assert_eq!(expect, account);
     }
+
+    #[test]
+    fn account_to_string() {
+        let account = Account {
+            label: "me@example.com".to_owned(),
+            secret: Key::new(vec![72, 101, 108, 108, 111, 33, 222, 173, 190, 239]),
+            digits: 8,
+            otp: Otp::Totp { window: 60 },
+            algorithm: Algorithm::Sha256,
+            issuer: Some("Test This".to_owned()),
+            meta: Metadata::default(),
+            extras: BTreeMap::default(),
+        };
+
+        let url = format(&account);
+        assert_eq!(
+            "otpauth://totp/Test%20This:me%40example.com?secret=JBSWY3DPEHPK3PXP&issuer=Test%\
+             20This&algorithm=SHA256&digits=8&period=60",
+            url
+        );
+        assert_eq!(account, parse(&url).unwrap());
+    }
 }
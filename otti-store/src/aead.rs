@@ -1,20 +1,24 @@
 use chacha20poly1305::{
-    aead::{Aead, OsRng},
+    aead::{Aead, OsRng, Payload},
     AeadCore, AeadInPlace, KeyInit, XChaCha20Poly1305, XNonce,
 };
+use otti_core::Zeroizing;
 use typenum::Unsigned;
 
 const NONCE_SIZE: usize = <XChaCha20Poly1305 as AeadCore>::NonceSize::USIZE;
 const TAG_SIZE: usize = <XChaCha20Poly1305 as AeadCore>::TagSize::USIZE;
 
-pub fn seal(key: &[u8], data: &[u8]) -> Result<Vec<u8>, super::Error> {
+/// Encrypt the data, additionally authenticating the associated data `ad`, which is not part of
+/// the output and must be passed again when opening the data.
+pub fn seal(key: &[u8], data: &[u8], ad: &[u8]) -> Result<Vec<u8>, super::Error> {
     let cipher = XChaCha20Poly1305::new_from_slice(key).map_err(|_e| super::Error::Crypto)?;
     let nonce = XChaCha20Poly1305::generate_nonce(&mut OsRng);
 
     let mut buf = vec![0; data.len() + NONCE_SIZE + TAG_SIZE];
+    buf[NONCE_SIZE..NONCE_SIZE + data.len()].copy_from_slice(data);
 
     let tag = cipher
-        .encrypt_in_place_detached(&nonce, &[], &mut buf[NONCE_SIZE..])
+        .encrypt_in_place_detached(&nonce, ad, &mut buf[NONCE_SIZE..NONCE_SIZE + data.len()])
         .map_err(|_e| super::Error::Crypto)?;
     buf[..NONCE_SIZE].copy_from_slice(&nonce);
     buf[NONCE_SIZE + data.len()..].copy_from_slice(&tag);
@@ -22,11 +26,48 @@ pub fn seal(key: &[u8], data: &[u8]) -> Result<Vec<u8>, super::Error> {
     Ok(buf)
 }
 
-pub fn open(key: &[u8], data: &[u8]) -> Result<Vec<u8>, super::Error> {
+/// Decrypt the data, which must have been sealed with the same associated data `ad`.
+pub fn open(key: &[u8], data: &[u8], ad: &[u8]) -> Result<Zeroizing<Vec<u8>>, super::Error> {
+    if data.len() < NONCE_SIZE + TAG_SIZE {
+        return Err(super::Error::Crypto);
+    }
+
     let cipher = XChaCha20Poly1305::new_from_slice(key).map_err(|_e| super::Error::Crypto)?;
     let nonce = XNonce::from_slice(&data[..NONCE_SIZE]);
 
     cipher
-        .decrypt(nonce, &data[NONCE_SIZE..])
+        .decrypt(
+            nonce,
+            Payload {
+                msg: &data[NONCE_SIZE..],
+                aad: ad,
+            },
+        )
+        .map(Zeroizing::new)
         .map_err(|_e| super::Error::Crypto)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn roundtrip() {
+        let key = [1; 32];
+        let sealed = seal(&key, b"hello world", b"header").unwrap();
+
+        assert_eq!(
+            b"hello world".as_slice(),
+            open(&key, &sealed, b"header").unwrap().as_slice()
+        );
+    }
+
+    #[test]
+    fn tampered_associated_data() {
+        let key = [1; 32];
+        let sealed = seal(&key, b"hello world", b"header").unwrap();
+
+        assert!(open(&key, &sealed, b"HEADER").is_err());
+        assert!(open(&key, &sealed, b"").is_err());
+    }
+}
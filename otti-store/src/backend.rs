@@ -0,0 +1,250 @@
+//! Storage backends, that persist the encrypted content of a store. They're independent of the
+//! encryption and encoding, which the [`Store`](crate::Store) handles on top of them.
+
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{self, BufWriter, Write},
+    path::{Path, PathBuf},
+    sync::{Mutex, MutexGuard, PoisonError},
+};
+
+use crate::Error;
+
+/// Amount of previous stores, that are kept as backup by default.
+pub const DEFAULT_BACKUPS: usize = 3;
+
+/// Persistence for the encrypted content of a store.
+pub trait Backend {
+    /// Guard of the exclusive lock, which is released once it's dropped.
+    type Lock<'a>
+    where
+        Self: 'a;
+
+    /// Load the whole content, or `None` if nothing was saved yet.
+    fn load(&self) -> Result<Option<Vec<u8>>, Error>;
+
+    /// Replace the content. Backends must do so atomically, so the previous content stays intact
+    /// if anything fails in between.
+    fn save(&self, content: &[u8]) -> Result<(), Error>;
+
+    /// Take an exclusive lock, that serializes all modifications of the content. If supported,
+    /// the lock applies between processes as well.
+    fn lock(&self) -> Result<Self::Lock<'_>, Error>;
+
+    /// Whether any content was saved yet.
+    fn exists(&self) -> Result<bool, Error> {
+        self.load().map(|content| content.is_some())
+    }
+}
+
+/// Store file on the local file system, that keeps backups of previous versions next to it.
+#[derive(Clone, Debug)]
+pub struct FileBackend {
+    path: PathBuf,
+    backups: usize,
+}
+
+impl FileBackend {
+    /// Store file at the given location, with the default amount of backups.
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: path.into(),
+            backups: DEFAULT_BACKUPS,
+        }
+    }
+
+    /// Set the amount of previous versions, that are kept as backup whenever the content is
+    /// saved. A value of 0 disables backups.
+    #[must_use]
+    pub fn with_backups(mut self, count: usize) -> Self {
+        self.backups = count;
+        self
+    }
+
+    /// Location of the store file.
+    #[must_use]
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Location of the backup with the given index, where 1 is the most recent one.
+    #[must_use]
+    pub fn backup_path(&self, index: usize) -> PathBuf {
+        self.sibling(&index.to_string())
+    }
+
+    /// List all existing backups, from the most recent to the oldest one.
+    pub fn backups(&self) -> Result<Vec<PathBuf>, Error> {
+        let mut backups = Vec::new();
+
+        for index in 1.. {
+            let path = self.backup_path(index);
+            if !path.try_exists()? {
+                break;
+            }
+            backups.push(path);
+        }
+
+        Ok(backups)
+    }
+
+    /// Restore the content from the backup with the given index. The current content becomes the
+    /// most recent backup in turn, so a restore can be undone again.
+    pub fn restore(&self, index: usize) -> Result<(), Error> {
+        let backup = fs::read(self.backup_path(index))?;
+        self.save(&backup)
+    }
+
+    /// Directory, that contains the store.
+    fn parent(&self) -> &Path {
+        match self.path.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent,
+            _ => Path::new("."),
+        }
+    }
+
+    /// File next to the store, with the given extension appended to the store's file name.
+    fn sibling(&self, extension: &str) -> PathBuf {
+        let mut path = self.path.clone().into_os_string();
+        path.push(".");
+        path.push(extension);
+        path.into()
+    }
+
+    /// Shift all backups by one, dropping the oldest one, and copy the current store into the
+    /// most recent backup slot.
+    fn rotate_backups(&self) -> Result<(), Error> {
+        if self.backups == 0 || !self.path.try_exists()? {
+            return Ok(());
+        }
+
+        for index in (1..self.backups).rev() {
+            let from = self.backup_path(index);
+            if from.try_exists()? {
+                fs::rename(from, self.backup_path(index + 1))?;
+            }
+        }
+
+        fs::copy(&self.path, self.backup_path(1))?;
+        Ok(())
+    }
+}
+
+impl Backend for FileBackend {
+    type Lock<'a> = File;
+
+    fn load(&self) -> Result<Option<Vec<u8>>, Error> {
+        match fs::read(&self.path) {
+            Ok(content) => Ok(Some(content)),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// The content is written to a temporary file next to the store first, which is then renamed
+    /// over the store once it's fully persisted.
+    fn save(&self, content: &[u8]) -> Result<(), Error> {
+        let parent = self.parent();
+        fs::create_dir_all(parent)?;
+
+        let temp = self.sibling("tmp");
+
+        let result = File::create(&temp)
+            .map_err(Into::into)
+            .and_then(|file| {
+                let mut file = BufWriter::new(file);
+                file.write_all(content)?;
+                file.into_inner()
+                    .map_err(io::IntoInnerError::into_error)?
+                    .sync_all()?;
+                Ok(())
+            })
+            .and_then(|()| self.rotate_backups());
+
+        if let Err(e) = result {
+            fs::remove_file(&temp).ok();
+            return Err(e);
+        }
+
+        fs::rename(&temp, &self.path)?;
+        sync_dir(parent)
+    }
+
+    /// As the store file itself is replaced on each save, a separate lock file next to it is
+    /// used.
+    fn lock(&self) -> Result<File, Error> {
+        fs::create_dir_all(self.parent())?;
+
+        let file = OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(self.sibling("lock"))?;
+        file.lock()?;
+
+        Ok(file)
+    }
+
+    fn exists(&self) -> Result<bool, Error> {
+        self.path.try_exists().map_err(Into::into)
+    }
+}
+
+/// Persist the directory entries, so a rename inside the directory survives a crash.
+#[cfg(unix)]
+fn sync_dir(dir: &Path) -> Result<(), Error> {
+    File::open(dir)?.sync_all().map_err(Into::into)
+}
+
+#[cfg(not(unix))]
+#[allow(clippy::unnecessary_wraps)]
+fn sync_dir(_dir: &Path) -> Result<(), Error> {
+    Ok(())
+}
+
+/// Content kept in memory only, for tests or to embed stores into other storage.
+#[derive(Debug, Default)]
+pub struct MemoryBackend {
+    content: Mutex<Option<Vec<u8>>>,
+    lock: Mutex<()>,
+}
+
+impl MemoryBackend {
+    /// Backend, that starts out with the given content.
+    #[must_use]
+    pub fn with_content(content: Vec<u8>) -> Self {
+        Self {
+            content: Mutex::new(Some(content)),
+            lock: Mutex::default(),
+        }
+    }
+
+    /// Take out the current content.
+    #[must_use]
+    pub fn into_content(self) -> Option<Vec<u8>> {
+        self.content
+            .into_inner()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl Backend for MemoryBackend {
+    type Lock<'a> = MutexGuard<'a, ()>;
+
+    fn load(&self) -> Result<Option<Vec<u8>>, Error> {
+        Ok(self
+            .content
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone())
+    }
+
+    fn save(&self, content: &[u8]) -> Result<(), Error> {
+        *self.content.lock().unwrap_or_else(PoisonError::into_inner) = Some(content.to_vec());
+        Ok(())
+    }
+
+    fn lock(&self) -> Result<Self::Lock<'_>, Error> {
+        Ok(self.lock.lock().unwrap_or_else(PoisonError::into_inner))
+    }
+}
@@ -0,0 +1,87 @@
+//! Growable buffer for decrypted content, like the encoded and compressed accounts.
+
+use std::io::{self, Write};
+
+use otti_core::Zeroizing;
+
+/// Capacity of the first allocation, to avoid growing several times for small stores.
+const MIN_CAPACITY: usize = 1024;
+
+/// Byte buffer, that zeroizes its content when dropped. Unlike a plain [`Vec`], that leaves its
+/// previous allocation behind whenever it grows, the old allocation is zeroized as well.
+#[derive(Default)]
+pub struct SecretBuffer(Zeroizing<Vec<u8>>);
+
+impl SecretBuffer {
+    /// Take the content, which is still zeroized when dropped.
+    #[must_use]
+    pub fn into_inner(self) -> Zeroizing<Vec<u8>> {
+        self.0
+    }
+}
+
+impl Write for SecretBuffer {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let required = self.0.len() + buf.len();
+
+        if required > self.0.capacity() {
+            let capacity = required.max(self.0.capacity() * 2).max(MIN_CAPACITY);
+            let mut grown = Vec::with_capacity(capacity);
+            grown.extend_from_slice(&self.0);
+
+            // Replacing the buffer drops and therefore zeroizes the previous allocation.
+            self.0 = Zeroizing::new(grown);
+        }
+
+        self.0.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_alloc;
+
+    #[test]
+    fn grow_in_place() {
+        let mut buf = SecretBuffer::default();
+        buf.write_all(&[1; 10]).unwrap();
+        let first = buf.0.as_ptr();
+
+        buf.write_all(&[2; MIN_CAPACITY - 10]).unwrap();
+        assert_eq!(
+            first,
+            buf.0.as_ptr(),
+            "no growth needed within the capacity"
+        );
+
+        buf.write_all(&[3; 10]).unwrap();
+        assert!(buf.0.capacity() >= 2 * MIN_CAPACITY);
+
+        let content = buf.into_inner();
+        assert_eq!(MIN_CAPACITY + 10, content.len());
+        assert_eq!([1; 10], content[..10]);
+        assert_eq!([3; 10], content[MIN_CAPACITY..]);
+    }
+
+    #[test]
+    fn zeroize_abandoned_allocation() {
+        let mut buf = SecretBuffer::default();
+        buf.write_all(&[1; MIN_CAPACITY]).unwrap();
+        let first = buf.0.as_ptr();
+
+        assert!(test_alloc::freed_zeroed(first, || {
+            buf.write_all(&[2; 10]).unwrap();
+        }));
+        assert_ne!(first, buf.0.as_ptr());
+
+        let content = buf.into_inner();
+        let ptr = content.as_ptr();
+        assert!(test_alloc::freed_zeroed(ptr, || drop(content)));
+    }
+}
@@ -0,0 +1,264 @@
+//! Log of the latest changes to the accounts, that is kept encrypted inside the store. It allows to
+//! undo changes without relying on external backups.
+
+use std::time::SystemTime;
+
+use otti_core::{Account, ExposeSecret, Key};
+use serde::{Deserialize, Serialize};
+
+/// Maximum amount of entries in the history, after which the oldest ones are dropped.
+pub const HISTORY_LIMIT: usize = 50;
+
+/// All changes, that were made to the accounts when the store was sealed once.
+#[derive(Serialize, Deserialize)]
+pub struct Entry {
+    /// Point in time, when the changes were sealed.
+    pub time: SystemTime,
+    /// Changes to the individual accounts.
+    pub changes: Vec<Change>,
+}
+
+/// Single change to an account. Accounts are identified by their secret, so changing it counts as
+/// removing the account and adding a new one.
+#[derive(Serialize, Deserialize)]
+pub enum Change {
+    /// The account was newly added.
+    Added(Account),
+    /// The account was removed.
+    Removed(Account),
+    /// Any of the account's fields changed, other than the secret.
+    Modified {
+        /// The account before the change.
+        before: Account,
+        /// The account after the change.
+        after: Account,
+    },
+}
+
+/// Add an entry for the changes between the `before` and `after` accounts, unless they're the
+/// same. The oldest entries are dropped, once the history is full.
+pub(crate) fn record(history: &mut Vec<Entry>, before: &[Account], after: &[Account]) {
+    let changes = diff(before, after);
+    if changes.is_empty() {
+        return;
+    }
+
+    history.push(Entry {
+        time: SystemTime::now(),
+        changes,
+    });
+
+    if history.len() > HISTORY_LIMIT {
+        history.drain(..history.len() - HISTORY_LIMIT);
+    }
+}
+
+/// Revert all changes of the entry, in reverse order. Accounts that were removed are added back
+/// at the end of the list.
+pub(crate) fn revert(accounts: &mut Vec<Account>, entry: &Entry) {
+    for change in entry.changes.iter().rev() {
+        match change {
+            Change::Added(account) => {
+                if let Some(index) = position(accounts, account) {
+                    accounts.remove(index);
+                }
+            }
+            Change::Removed(account) => accounts.push(copy(account)),
+            Change::Modified { before, after } => match position(accounts, after) {
+                Some(index) => accounts[index] = copy(before),
+                None => accounts.push(copy(before)),
+            },
+        }
+    }
+}
+
+/// Reconstruct the accounts as of the latest change, that both histories share, like the ones of
+/// two copies of a store that were changed independently. The `accounts` are the current ones of
+/// the `local` history. Entries are matched by the time they were recorded, which is kept as is
+/// in every copy. Returns `None` if the histories have no change in common.
+pub fn common_base(
+    accounts: &[Account],
+    local: &[Entry],
+    remote: &[Entry],
+) -> Option<Vec<Account>> {
+    let common = local
+        .iter()
+        .rposition(|l| remote.iter().any(|r| r.time == l.time))?;
+
+    let mut base = accounts.iter().map(copy).collect();
+    for entry in local[common + 1..].iter().rev() {
+        revert(&mut base, entry);
+    }
+
+    Some(base)
+}
+
+fn diff(before: &[Account], after: &[Account]) -> Vec<Change> {
+    let mut changes = Vec::new();
+
+    for account in after {
+        match before.iter().find(|b| same_secret(b, account)) {
+            None => changes.push(Change::Added(copy(account))),
+            Some(previous) if !same_fields(previous, account) => {
+                changes.push(Change::Modified {
+                    before: copy(previous),
+                    after: copy(account),
+                });
+            }
+            Some(_) => {}
+        }
+    }
+
+    for account in before {
+        if !after.iter().any(|a| same_secret(a, account)) {
+            changes.push(Change::Removed(copy(account)));
+        }
+    }
+
+    changes
+}
+
+fn position(accounts: &[Account], account: &Account) -> Option<usize> {
+    accounts.iter().position(|a| same_secret(a, account))
+}
+
+fn same_secret(a: &Account, b: &Account) -> bool {
+    a.secret.expose_secret() == b.secret.expose_secret()
+}
+
+fn same_fields(a: &Account, b: &Account) -> bool {
+    a.label == b.label
+        && a.digits == b.digits
+        && a.otp == b.otp
+        && a.algorithm == b.algorithm
+        && a.issuer == b.issuer
+        && a.meta == b.meta
+        && a.extras == b.extras
+}
+
+/// Copy of the entry, as entries can't be cloned for the same reason as accounts.
+pub(crate) fn copy_entry(entry: &Entry) -> Entry {
+    Entry {
+        time: entry.time,
+        changes: entry
+            .changes
+            .iter()
+            .map(|change| match change {
+                Change::Added(account) => Change::Added(copy(account)),
+                Change::Removed(account) => Change::Removed(copy(account)),
+                Change::Modified { before, after } => Change::Modified {
+                    before: copy(before),
+                    after: copy(after),
+                },
+            })
+            .collect(),
+    }
+}
+
+/// Copy of the account, as accounts can't be cloned to keep their secrets from being duplicated
+/// by accident.
+pub(crate) fn copy(account: &Account) -> Account {
+    Account {
+        label: account.label.clone(),
+        secret: Key::new(account.secret.expose_secret().clone()),
+        digits: account.digits,
+        otp: account.otp.clone(),
+        algorithm: account.algorithm,
+        issuer: account.issuer.clone(),
+        meta: account.meta.clone(),
+        extras: account.extras.clone(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::BTreeMap, time::Duration};
+
+    use otti_core::{Algorithm, Metadata, Otp};
+
+    use super::*;
+
+    fn account(secret: u8, label: &str) -> Account {
+        Account {
+            label: label.to_owned(),
+            secret: Key::new(vec![secret; 10]),
+            digits: 6,
+            otp: Otp::Totp { window: 30 },
+            algorithm: Algorithm::Sha1,
+            issuer: None,
+            meta: Metadata::default(),
+            extras: BTreeMap::new(),
+        }
+    }
+
+    fn labels(accounts: &[Account]) -> Vec<&str> {
+        accounts.iter().map(|a| a.label.as_str()).collect()
+    }
+
+    #[test]
+    fn record_and_revert() {
+        let mut history = Vec::new();
+        let mut accounts = vec![account(1, "a"), account(2, "b")];
+
+        let changed = vec![account(1, "a2"), account(3, "c")];
+        record(&mut history, &accounts, &changed);
+        record(&mut history, &changed, &changed);
+        assert_eq!(1, history.len());
+        assert_eq!(3, history[0].changes.len());
+
+        accounts = changed;
+        revert(&mut accounts, &history.pop().unwrap());
+        assert_eq!(vec!["a", "b"], labels(&accounts));
+    }
+
+    #[test]
+    fn find_common_base() {
+        let at = |history: &mut Vec<Entry>, secs| {
+            history.last_mut().unwrap().time = SystemTime::UNIX_EPOCH + Duration::from_secs(secs);
+        };
+
+        let mut local = Vec::new();
+        record(&mut local, &[], &[account(1, "a"), account(2, "b")]);
+        at(&mut local, 1);
+        let mut remote = local.iter().map(copy_entry).collect::<Vec<_>>();
+
+        record(
+            &mut local,
+            &[account(1, "a"), account(2, "b")],
+            &[account(1, "a")],
+        );
+        at(&mut local, 2);
+        record(&mut local, &[account(1, "a")], &[account(1, "a2")]);
+        at(&mut local, 3);
+        record(
+            &mut remote,
+            &[account(1, "a"), account(2, "b")],
+            &[account(1, "a"), account(2, "b"), account(3, "c")],
+        );
+        at(&mut remote, 4);
+
+        let base = common_base(&[account(1, "a2")], &local, &remote).unwrap();
+        assert_eq!(vec!["a", "b"], labels(&base));
+
+        assert!(common_base(&[account(1, "a2")], &local[1..], &remote).is_none());
+        assert!(common_base(&[], &[], &[]).is_none());
+    }
+
+    #[test]
+    fn drop_oldest_entries() {
+        let mut history = Vec::new();
+        let mut accounts = Vec::new();
+
+        for i in 0..=u8::try_from(HISTORY_LIMIT).unwrap() {
+            let changed = vec![account(i, "a")];
+            record(&mut history, &accounts, &changed);
+            accounts = changed;
+        }
+
+        assert_eq!(HISTORY_LIMIT, history.len());
+        let Change::Added(first) = &history[0].changes[0] else {
+            panic!("expected an added account");
+        };
+        assert_eq!(&vec![1; 10], first.secret.expose_secret());
+    }
+}
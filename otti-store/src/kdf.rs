@@ -1,5 +1,24 @@
 use argon2::{password_hash::rand_core::RngCore, Argon2};
 use chacha20poly1305::aead::OsRng;
+use otti_core::Zeroizing;
+use serde::{Deserialize, Serialize};
+
+/// Key derivation function, that turns the user's password into the encryption key.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Algorithm {
+    Argon2i,
+    Argon2id,
+}
+
+impl Algorithm {
+    /// Unique identifier of the algorithm, used to authenticate it.
+    pub fn id(self) -> u8 {
+        match self {
+            Self::Argon2i => 1,
+            Self::Argon2id => 2,
+        }
+    }
+}
 
 pub struct Password<'a>(&'a [u8]);
 
@@ -40,16 +59,22 @@ impl AsRef<[u8]> for Salt {
 }
 
 pub fn derive_key(
+    algorithm: Algorithm,
     password: &Password<'_>,
     salt: &Salt,
     iterations: u32,
     memory: u32,
     size: usize,
-) -> Result<Vec<u8>, super::Error> {
-    let mut key = vec![0; size];
+) -> Result<Zeroizing<Vec<u8>>, super::Error> {
+    let mut key = Zeroizing::new(vec![0; size]);
+
+    let algorithm = match algorithm {
+        Algorithm::Argon2i => argon2::Algorithm::Argon2i,
+        Algorithm::Argon2id => argon2::Algorithm::Argon2id,
+    };
 
     Argon2::new(
-        argon2::Algorithm::Argon2i,
+        algorithm,
         argon2::Version::V0x13,
         argon2::Params::new(memory, iterations, 1, Some(size))
             .map_err(|_e| super::Error::Crypto)?,
@@ -59,3 +84,26 @@ pub fn derive_key(
 
     Ok(key)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_alloc;
+
+    #[test]
+    fn zeroize_derived_key() {
+        let key = derive_key(
+            Algorithm::Argon2id,
+            &Password::from_slice(b"test"),
+            &Salt::default(),
+            1,
+            8,
+            32,
+        )
+        .unwrap();
+        assert!(key.iter().any(|&b| b != 0));
+
+        let ptr = key.as_ptr();
+        assert!(test_alloc::freed_zeroed(ptr, || drop(key)));
+    }
+}
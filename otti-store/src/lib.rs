@@ -2,31 +2,59 @@
 //!
 //! The storage component for **Otti** manages saving of accounts in a secure manner so that they
 //! may only be accessed with the user defined password.
+//!
+//! The accounts are encrypted with a random master key, which is in turn wrapped by one or more
+//! slots. Each slot can unlock the store on its own, be it with a password, a key file or a
+//! recovery key. Slots can be added and removed without re-encrypting the accounts.
+//!
+//! Shared stores for teams wrap the master key for the public keys of several recipients instead,
+//! so each person unlocks the store with their own identity.
 
 #![deny(rust_2018_idioms, clippy::all, clippy::pedantic)]
 #![allow(clippy::missing_errors_doc)]
 
 mod aead;
+mod backend;
+mod buffer;
+mod history;
 mod kdf;
+mod recipient;
+mod slot;
+#[cfg(test)]
+mod test_alloc;
+mod verify;
 
 use std::{
     convert::TryFrom,
     fmt::{self, Display},
-    fs::{self, File},
-    io::{prelude::*, BufReader, BufWriter},
-    path::PathBuf,
+    io::{self, prelude::*},
+    ops::RangeInclusive,
+    path::{Path, PathBuf},
 };
 
+use blake2::{Blake2b, Digest};
 use directories::ProjectDirs;
 use flate2::{
     write::{ZlibDecoder, ZlibEncoder},
     Compression,
 };
-use otti_core::{Account, ExposeSecret};
+use otti_core::{Account, ExposeSecret, Zeroizing};
 pub use secrecy::{Secret, SecretString};
 use serde::{Deserialize, Serialize};
+use typenum::U16;
 
-use self::kdf::{Password, Salt};
+pub use self::{
+    backend::{Backend, FileBackend, MemoryBackend, DEFAULT_BACKUPS},
+    buffer::SecretBuffer,
+    history::{common_base, Change, Entry, HISTORY_LIMIT},
+    recipient::{Identity, Recipient},
+    slot::{Credential, SlotKind},
+    verify::{Check, Outcome, Report},
+};
+use self::{
+    kdf::{Password, Salt},
+    slot::Slot,
+};
 
 /// Errors that can occur when sealing or opening an otti store.
 #[derive(Debug, thiserror::Error)]
@@ -41,6 +69,9 @@ pub enum Error {
     /// Failed to find the home directory of the executing user.
     #[error("failed to find the home folder")]
     HomefolderNotFound,
+    /// The name of a vault is empty or contains characters that aren't allowed in file names.
+    #[error("invalid vault name `{0}`")]
+    InvalidVaultName(String),
     /// An I/O related error happened.
     #[error("I/O bound error")]
     Io(#[from] std::io::Error),
@@ -53,23 +84,61 @@ pub enum Error {
     /// A cryptographic error occurred.
     #[error("cryptographic error")]
     Crypto,
-    /// The given password to open a store was invalid.
-    #[error("password is invalid")]
-    InvalidPassword,
+    /// The given credential didn't unlock any of the store's slots.
+    #[error("{0} is invalid")]
+    InvalidCredential(SlotKind),
+    /// The recovery key isn't in the format, that it was generated in.
+    #[error("recovery key is malformed")]
+    InvalidRecoveryKey,
+    /// The slot with the given index doesn't exist in the store.
+    #[error("slot {0} doesn't exist")]
+    UnknownSlot(usize),
+    /// The only remaining slot of a store can't be removed, as it would become inaccessible.
+    #[error("the last slot of a store can't be removed")]
+    LastSlot,
+    /// The identity isn't in the format of an age X25519 identity.
+    #[error("identity is malformed")]
+    InvalidIdentity,
+    /// The recipient isn't in the format of an age X25519 recipient.
+    #[error("invalid recipient `{0}`")]
+    InvalidRecipient(String),
+    /// None of the store's recipients belongs to the identity.
+    #[error("the identity isn't a recipient of the store")]
+    UnknownIdentity,
+    /// The recipient is already part of the store.
+    #[error("`{0}` is already a recipient of the store")]
+    DuplicateRecipient(Recipient),
+    /// The recipient isn't part of the store.
+    #[error("`{0}` isn't a recipient of the store")]
+    UnknownRecipient(Recipient),
+    /// The store was modified by someone else, since it was opened.
+    #[error("the store was modified by another process in the meantime")]
+    Modified,
+    /// More changes were requested to be undone, than the history contains.
+    #[error("the history contains only {0} changes")]
+    HistoryExhausted(usize),
 }
 
 /// Different versions of the otti store. This enum must be extended and according conversion
 /// implemented, whenever the store format has been changed in a breaking manner.
 #[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
 pub enum Version {
-    /// The current and only format version of the otti store.
+    /// Initial format version, where only the encrypted data is authenticated, but not the KDF
+    /// parameters in front of it.
     V1,
+    /// Format version, that additionally authenticates the version and KDF parameters.
+    V2,
+    /// The current format version, where a random master key encrypts the data and is wrapped by
+    /// one or more unlock slots. Stores in older versions are migrated to it, when they're opened.
+    V3,
 }
 
 impl Display for Version {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         f.write_str(match self {
             Self::V1 => "v1",
+            Self::V2 => "v2",
+            Self::V3 => "v3",
         })
     }
 }
@@ -78,6 +147,8 @@ impl From<Version> for u16 {
     fn from(v: Version) -> Self {
         match v {
             Version::V1 => 1,
+            Version::V2 => 2,
+            Version::V3 => 3,
         }
     }
 }
@@ -88,102 +159,772 @@ impl TryFrom<u16> for Version {
     fn try_from(value: u16) -> Result<Self, Self::Error> {
         match value {
             1 => Ok(Self::V1),
+            2 => Ok(Self::V2),
+            3 => Ok(Self::V3),
             _ => Err(Error::UnknownVersion(value)),
         }
     }
 }
 
+/// Content of a store in [`Version::V1`].
 #[derive(Serialize, Deserialize)]
-struct EncryptedFile {
+struct EncryptedFileV1 {
+    salt: Vec<u8>,
+    iterations: u32,
+    memory: u32,
+    data: Vec<u8>,
+}
+
+/// Content of a store in [`Version::V2`].
+#[derive(Serialize, Deserialize)]
+struct EncryptedFileV2 {
+    algorithm: kdf::Algorithm,
     salt: Vec<u8>,
     iterations: u32,
     memory: u32,
     data: Vec<u8>,
 }
 
-/// Try to open the Otti store with the given password.
-pub fn open(password: &SecretString) -> Result<Vec<Account>, Error> {
-    let file = File::open(filepath()?)?;
-    let mut file = BufReader::new(file);
+impl EncryptedFileV2 {
+    /// Associated data for the encryption, that binds the format version and all KDF parameters
+    /// to the encrypted data. Any modification of them makes the decryption fail.
+    fn associated_data(&self) -> Vec<u8> {
+        let mut ad = Vec::with_capacity(2 + 1 + self.salt.len() + 4 + 4);
+        ad.extend_from_slice(&u16::from(Version::V2).to_le_bytes());
+        ad.push(self.algorithm.id());
+        ad.extend_from_slice(&self.salt);
+        ad.extend_from_slice(&self.iterations.to_le_bytes());
+        ad.extend_from_slice(&self.memory.to_le_bytes());
+        ad
+    }
+}
+
+impl From<EncryptedFileV1> for EncryptedFileV2 {
+    fn from(v1: EncryptedFileV1) -> Self {
+        Self {
+            algorithm: kdf::Algorithm::Argon2i,
+            salt: v1.salt,
+            iterations: v1.iterations,
+            memory: v1.memory,
+            data: v1.data,
+        }
+    }
+}
+
+/// Content of a store in the current [`Version::V3`].
+#[derive(Serialize, Deserialize)]
+struct EncryptedFile {
+    slots: Vec<Slot>,
+    data: Vec<u8>,
+    /// Encrypted history of changes to the accounts. It's left out while empty, so stores without
+    /// a history stay readable by earlier releases.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    history: Vec<u8>,
+}
+
+impl EncryptedFile {
+    /// Associated data for the encryption of the accounts, that binds the format version to them.
+    /// The slots authenticate their own parameters when unwrapping the master key.
+    fn associated_data() -> [u8; 2] {
+        u16::from(Version::V3).to_le_bytes()
+    }
+
+    /// Associated data for the encryption of the history, which differs from the one of the
+    /// accounts, so the two can't be swapped.
+    fn history_associated_data() -> [u8; 9] {
+        let mut ad = [0; 9];
+        ad[..2].copy_from_slice(&Self::associated_data());
+        ad[2..].copy_from_slice(b"history");
+        ad
+    }
+}
+
+/// Marker for the state of a store on disk, to detect whether it was modified by someone else in
+/// the meantime. It's a checksum over the whole store file, which changes with any modification.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Generation([u8; 16]);
+
+impl Generation {
+    fn of(content: &[u8]) -> Self {
+        Self(Blake2b::<U16>::digest(content).into())
+    }
+}
+
+/// A store, whose master key was recovered through one of its slots.
+struct Unlocked {
+    file: EncryptedFile,
+    /// Generation of the store file, that was unlocked.
+    generation: Generation,
+    master: Zeroizing<Vec<u8>>,
+    /// Index of the slot, that unlocked the store.
+    slot: usize,
+    accounts: Vec<Account>,
+    /// Whether the store was migrated from an older version, and isn't written in the current
+    /// version yet.
+    migrated: bool,
+}
+
+/// Handle to a single Otti store. By default, it's a file on disk, which is either the default
+/// store, a named vault or a store at an arbitrary location. Other storage can be used through a
+/// custom [`Backend`].
+#[derive(Clone, Debug)]
+pub struct Store<B = FileBackend> {
+    backend: B,
+    cost: Cost,
+    rehash: bool,
+}
+
+/// Cost parameters for the Argon2id key derivation, that turns the password into the encryption
+/// key. Higher values make brute-force attacks on the password more expensive, but slow down
+/// unlocking the store as well.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Cost {
+    /// Amount of passes over the memory.
+    pub iterations: u32,
+    /// Memory size in KiB.
+    pub memory: u32,
+}
+
+impl Cost {
+    /// High cost for sensitive data on fast devices.
+    pub const HIGH: Self = Self {
+        iterations: 4,
+        memory: 256 * 1024,
+    };
+    /// Bounds of the iterations, that any regular store stays within.
+    pub const ITERATIONS: RangeInclusive<u32> = 1..=1 << 16;
+    /// Minimum cost as recommended by OWASP, for slow devices.
+    pub const LOW: Self = Self {
+        iterations: 2,
+        memory: 19 * 1024,
+    };
+    /// Bounds of the memory size in KiB, that any regular store stays within.
+    pub const MEMORY: RangeInclusive<u32> = 8..=4 * 1024 * 1024;
+    /// Default cost, that takes well below a second on most devices.
+    pub const MODERATE: Self = Self {
+        iterations: 3,
+        memory: 64 * 1024,
+    };
+}
+
+impl Default for Cost {
+    fn default() -> Self {
+        Self::MODERATE
+    }
+}
+
+impl Store {
+    /// Default store in the user's data directory.
+    pub fn new() -> Result<Self, Error> {
+        Ok(Self::at(data_dir()?.join("store.otti")))
+    }
+
+    /// Named vault, that is kept next to the default store in the user's data directory.
+    pub fn vault(name: &str) -> Result<Self, Error> {
+        if name.is_empty()
+            || name.starts_with('.')
+            || name.contains(|c: char| std::path::is_separator(c) || c.is_control())
+        {
+            return Err(Error::InvalidVaultName(name.to_owned()));
+        }
+
+        Ok(Self::at(
+            data_dir()?.join("vaults").join(format!("{name}.otti")),
+        ))
+    }
+
+    /// Store at the given file location.
+    pub fn at(path: impl Into<PathBuf>) -> Self {
+        Self::with_backend(FileBackend::new(path))
+    }
+
+    /// Set the amount of previous stores, that are kept as backup whenever the store is sealed.
+    /// A value of 0 disables backups.
+    #[must_use]
+    pub fn with_backups(mut self, count: usize) -> Self {
+        self.backend = self.backend.with_backups(count);
+        self
+    }
+
+    /// Location of the store file.
+    #[must_use]
+    pub fn path(&self) -> &Path {
+        self.backend.path()
+    }
 
-    let version = read_version(&mut file)?;
-    if version != Version::V1 {
-        return Err(Error::UnsupportedVersion(version));
+    /// Location of the backup with the given index, where 1 is the most recent one.
+    #[must_use]
+    pub fn backup_path(&self, index: usize) -> PathBuf {
+        self.backend.backup_path(index)
     }
 
-    let encrypted = rmp_serde::from_read::<_, EncryptedFile>(&mut file)?;
-    let data = decrypt(&encrypted, password)?;
-    let data = decompress(&data)?;
+    /// List all existing backups of the store, from the most recent to the oldest one.
+    pub fn backups(&self) -> Result<Vec<PathBuf>, Error> {
+        self.backend.backups()
+    }
 
-    rmp_serde::from_slice(&data).map_err(Into::into)
+    /// Restore the store from the backup with the given index. The current store becomes the most
+    /// recent backup in turn, so a restore can be undone again.
+    pub fn restore(&self, index: usize) -> Result<(), Error> {
+        let _lock = self.backend.lock()?;
+        self.backend.restore(index)
+    }
 }
 
-/// Seal the given list of accounts with the provided password.
-pub fn seal(accounts: &[Account], password: &SecretString) -> Result<(), Error> {
-    let data = rmp_serde::to_vec(accounts)?;
-    let data = compress(&data)?;
-    let encrypted = encrypt(&data, password)?;
-    let path = filepath()?;
+impl<B: Backend> Store<B> {
+    /// Store, that persists its content in the given backend.
+    pub fn with_backend(backend: B) -> Self {
+        Self {
+            backend,
+            cost: Cost::default(),
+            rehash: true,
+        }
+    }
+
+    /// Backend, that persists the store's content.
+    pub fn backend(&self) -> &B {
+        &self.backend
+    }
+
+    /// Set the key derivation cost for sealing the store. Stores that were sealed with a lower
+    /// cost are re-sealed with this cost, after they're opened successfully.
+    #[must_use]
+    pub fn with_cost(mut self, cost: Cost) -> Self {
+        self.cost = cost;
+        self
+    }
+
+    /// Enable or disable the re-sealing of stores with outdated key derivation parameters or
+    /// older format versions, after they're opened successfully. Enabled by default.
+    #[must_use]
+    pub fn with_rehash(mut self, rehash: bool) -> Self {
+        self.rehash = rehash;
+        self
+    }
+
+    /// Try to open the Otti store with the given credential.
+    ///
+    /// If the store was sealed in an older format or the slot was sealed with weaker key
+    /// derivation parameters than configured, it's re-sealed right away.
+    pub fn open(&self, credential: &Credential) -> Result<Vec<Account>, Error> {
+        self.open_tracked(credential).map(|(accounts, _)| accounts)
+    }
+
+    /// Open the store like [`Store::open`], but additionally return its current generation. It
+    /// allows to seal the accounts again with [`Store::seal_tracked`], without overwriting changes
+    /// that were made by someone else in the meantime.
+    pub fn open_tracked(
+        &self,
+        credential: &Credential,
+    ) -> Result<(Vec<Account>, Generation), Error> {
+        let mut unlocked = unlock(&self.load()?, credential, self.cost)?;
+
+        if self.rehash
+            && (unlocked.migrated || unlocked.file.slots[unlocked.slot].is_weaker(self.cost))
+        {
+            // Failing to upgrade is not fatal, as the store stays usable with the old parameters.
+            if let Ok(generation) = self.upgrade(&mut unlocked, credential) {
+                unlocked.generation = generation;
+            }
+        }
+
+        Ok((unlocked.accounts, unlocked.generation))
+    }
+
+    /// Seal the given list of accounts, with the master key unlocked by the credential. If the
+    /// store doesn't exist yet, it's created with the credential as its only slot.
+    ///
+    /// Any changes, that were made to the accounts since they were opened, are overwritten. Use
+    /// [`Store::seal_tracked`] to prevent that.
+    pub fn seal(&self, accounts: &[Account], credential: &Credential) -> Result<(), Error> {
+        let _lock = self.backend.lock()?;
+
+        if self.exists()? {
+            self.reseal(accounts, credential)?;
+        } else {
+            self.persist(&new_file(accounts, credential, self.cost)?)?;
+        }
+
+        Ok(())
+    }
+
+    /// Seal the given list of accounts, but only if the store is still in the same generation,
+    /// that it was opened in. Otherwise, it was modified by someone else in the meantime and
+    /// [`Error::Modified`] is returned. Returns the new generation of the store.
+    pub fn seal_tracked(
+        &self,
+        accounts: &[Account],
+        credential: &Credential,
+        generation: Generation,
+    ) -> Result<Generation, Error> {
+        let _lock = self.backend.lock()?;
+
+        if self.generation()? != generation {
+            return Err(Error::Modified);
+        }
+
+        self.reseal(accounts, credential)
+    }
+
+    /// Create a new store with a fresh master key and the credential as its only slot. Any
+    /// existing store is replaced, including all of its slots.
+    pub fn create(&self, accounts: &[Account], credential: &Credential) -> Result<(), Error> {
+        let _lock = self.backend.lock()?;
+
+        self.persist(&new_file(accounts, credential, self.cost)?)?;
+        Ok(())
+    }
+
+    /// Check the store step by step, to find out whether it's damaged or only the credential is
+    /// wrong. Only failing to read the store is returned as error, everything else is part of the
+    /// report.
+    pub fn verify(&self, credential: &Credential) -> Result<Report, Error> {
+        Ok(verify::verify(&self.load()?, credential))
+    }
+
+    /// Current generation of the store, without unlocking it.
+    pub fn generation(&self) -> Result<Generation, Error> {
+        Ok(Generation::of(&self.load()?))
+    }
+
+    /// List the kinds of all slots of the store, without unlocking it. Stores in older formats
+    /// have only a single password slot.
+    pub fn slots(&self) -> Result<Vec<SlotKind>, Error> {
+        let content = self.load()?;
+        let mut file = content.as_slice();
+
+        Ok(match read_version(&mut file)? {
+            Version::V1 | Version::V2 => vec![SlotKind::Password],
+            Version::V3 => rmp_serde::from_read::<_, EncryptedFile>(&mut file)?
+                .slots
+                .iter()
+                .map(|slot| slot.kind)
+                .collect(),
+        })
+    }
+
+    /// Add a new slot for the credential `new`, after unlocking the store with `credential`.
+    /// Returns the index of the new slot.
+    pub fn add_slot(&self, credential: &Credential, new: &Credential) -> Result<usize, Error> {
+        let _lock = self.backend.lock()?;
+        let mut unlocked = unlock(&self.load()?, credential, self.cost)?;
+        let slot = Slot::new(&unlocked.master, new, self.cost)?;
+        unlocked.file.slots.push(slot);
+
+        self.persist(&unlocked.file)?;
+        Ok(unlocked.file.slots.len() - 1)
+    }
+
+    /// Replace the slot, that the store is unlocked with by `credential`, with a new slot for the
+    /// credential `new`.
+    pub fn replace_slot(&self, credential: &Credential, new: &Credential) -> Result<(), Error> {
+        let _lock = self.backend.lock()?;
+        let mut unlocked = unlock(&self.load()?, credential, self.cost)?;
+        unlocked.file.slots[unlocked.slot] = Slot::new(&unlocked.master, new, self.cost)?;
+
+        self.persist(&unlocked.file)?;
+        Ok(())
+    }
+
+    /// Remove the slot with the given index, after unlocking the store with `credential`.
+    pub fn remove_slot(&self, credential: &Credential, index: usize) -> Result<(), Error> {
+        let _lock = self.backend.lock()?;
+        let mut unlocked = unlock(&self.load()?, credential, self.cost)?;
+
+        if index >= unlocked.file.slots.len() {
+            return Err(Error::UnknownSlot(index));
+        }
+        if unlocked.file.slots.len() == 1 {
+            return Err(Error::LastSlot);
+        }
+
+        unlocked.file.slots.remove(index);
+        self.persist(&unlocked.file)?;
+        Ok(())
+    }
+
+    /// List the recorded changes to the accounts, from the oldest to the most recent one.
+    pub fn history(&self, credential: &Credential) -> Result<Vec<Entry>, Error> {
+        let unlocked = unlock(&self.load()?, credential, self.cost)?;
+        decrypt_history(&unlocked.file, &unlocked.master)
+    }
+
+    /// Revert the last `count` changes from the history. The revert is recorded as a new change
+    /// itself, so it can be undone in turn. Returns the reverted entries, from the most recent to
+    /// the oldest one.
+    pub fn undo(&self, credential: &Credential, count: usize) -> Result<Vec<Entry>, Error> {
+        let _lock = self.backend.lock()?;
+        let mut unlocked = unlock(&self.load()?, credential, self.cost)?;
+        let mut history = decrypt_history(&unlocked.file, &unlocked.master)?;
+
+        if count > history.len() {
+            return Err(Error::HistoryExhausted(history.len()));
+        }
+
+        let reverted = history[history.len() - count..]
+            .iter()
+            .rev()
+            .map(history::copy_entry)
+            .collect::<Vec<_>>();
+
+        let mut accounts = unlocked.accounts.iter().map(history::copy).collect();
+        for entry in &reverted {
+            history::revert(&mut accounts, entry);
+        }
+        history::record(&mut history, &unlocked.accounts, &accounts);
+
+        unlocked.file.data = encrypt(&accounts, &unlocked.master)?;
+        unlocked.file.history = encrypt_history(&history, &unlocked.master)?;
+
+        self.persist(&unlocked.file)?;
+        Ok(reverted)
+    }
+
+    /// List the recipients, that the store is shared with.
+    pub fn recipients(&self) -> Result<Vec<Recipient>, Error> {
+        let content = self.load()?;
+        let mut file = content.as_slice();
+
+        match read_version(&mut file)? {
+            Version::V1 | Version::V2 => Ok(Vec::new()),
+            Version::V3 => rmp_serde::from_read::<_, EncryptedFile>(&mut file)?
+                .slots
+                .iter()
+                .filter_map(|slot| slot.recipient().transpose())
+                .collect(),
+        }
+    }
+
+    /// Share the store with a new recipient, after unlocking it with `credential`. Returns the
+    /// index of the new slot.
+    pub fn add_recipient(
+        &self,
+        credential: &Credential,
+        recipient: &Recipient,
+    ) -> Result<usize, Error> {
+        let _lock = self.backend.lock()?;
+        let mut unlocked = unlock(&self.load()?, credential, self.cost)?;
+
+        for slot in &unlocked.file.slots {
+            if slot.recipient()?.as_ref() == Some(recipient) {
+                return Err(Error::DuplicateRecipient(*recipient));
+            }
+        }
+
+        let slot = Slot::for_recipient(&unlocked.master, recipient)?;
+        unlocked.file.slots.push(slot);
+
+        self.persist(&unlocked.file)?;
+        Ok(unlocked.file.slots.len() - 1)
+    }
+
+    /// Remove a recipient from the store, after unlocking it with `credential`.
+    ///
+    /// A revoked recipient may still know the master key from before. Therefore, if all remaining
+    /// slots are recipients, the master key is replaced and the accounts are re-encrypted, which is
+    /// signaled by returning `true`. Other kinds of slots can't be wrapped again without their
+    /// credential, so the master key is kept as is if any of them exists.
+    pub fn revoke_recipient(
+        &self,
+        credential: &Credential,
+        recipient: &Recipient,
+    ) -> Result<bool, Error> {
+        let _lock = self.backend.lock()?;
+        let mut unlocked = unlock(&self.load()?, credential, self.cost)?;
+
+        let count = unlocked.file.slots.len();
+        let mut slots = Vec::with_capacity(count);
+        for slot in unlocked.file.slots.drain(..) {
+            if slot.recipient()?.as_ref() != Some(recipient) {
+                slots.push(slot);
+            }
+        }
+
+        if slots.len() == count {
+            return Err(Error::UnknownRecipient(*recipient));
+        }
+        if slots.is_empty() {
+            return Err(Error::LastSlot);
+        }
+
+        let rotate = slots.iter().all(|slot| slot.kind == SlotKind::Recipient);
+        if rotate {
+            let master = slot::generate_master_key();
+            let mut rotated = Vec::with_capacity(slots.len());
+            for slot in &slots {
+                if let Some(recipient) = slot.recipient()? {
+                    rotated.push(Slot::for_recipient(&master, &recipient)?);
+                }
+            }
+
+            let history = decrypt_history(&unlocked.file, &unlocked.master)?;
+            unlocked.file.data = encrypt(&unlocked.accounts, &master)?;
+            unlocked.file.history = encrypt_history(&history, &master)?;
+            slots = rotated;
+        }
+
+        unlocked.file.slots = slots;
+        self.persist(&unlocked.file)?;
+
+        Ok(rotate)
+    }
+
+    /// Write the upgraded slot or migrated store back to disk, unless the store was modified in
+    /// the meantime. The upgrade is left for next time in that case.
+    fn upgrade(
+        &self,
+        unlocked: &mut Unlocked,
+        credential: &Credential,
+    ) -> Result<Generation, Error> {
+        // Migrated stores already got a new slot with the configured cost.
+        if !unlocked.migrated {
+            unlocked.file.slots[unlocked.slot] =
+                Slot::new(&unlocked.master, credential, self.cost)?;
+        }
+
+        let _lock = self.backend.lock()?;
+
+        if self.generation()? != unlocked.generation {
+            return Err(Error::Modified);
+        }
+
+        self.persist(&unlocked.file)
+    }
+
+    /// Encrypt the accounts again with the master key, that the credential unlocks. The caller
+    /// must hold the lock.
+    /// The changes to the accounts are recorded in the history.
+    fn reseal(&self, accounts: &[Account], credential: &Credential) -> Result<Generation, Error> {
+        let mut unlocked = unlock(&self.load()?, credential, self.cost)?;
+        let mut history = decrypt_history(&unlocked.file, &unlocked.master)?;
+        history::record(&mut history, &unlocked.accounts, accounts);
+
+        unlocked.file.data = encrypt(accounts, &unlocked.master)?;
+        unlocked.file.history = encrypt_history(&history, &unlocked.master)?;
+
+        self.persist(&unlocked.file)
+    }
 
-    if let Some(parent) = path.parent() {
-        fs::create_dir_all(parent)?;
+    /// Load the store's content, which must exist already.
+    fn load(&self) -> Result<Vec<u8>, Error> {
+        self.backend
+            .load()?
+            .ok_or_else(|| io::Error::from(io::ErrorKind::NotFound).into())
     }
 
-    let file = File::create(path)?;
-    let mut file = BufWriter::new(file);
+    /// Save the store content in the current version, and return its new generation.
+    fn persist(&self, file: &EncryptedFile) -> Result<Generation, Error> {
+        let content = encode(file)?;
 
-    write_version(&mut file, Version::V1)?;
-    rmp_serde::encode::write(&mut file, &encrypted)?;
+        self.backend.save(&content)?;
+        Ok(Generation::of(&content))
+    }
+
+    /// Test whether this store already exists in the current system.
+    pub fn exists(&self) -> Result<bool, Error> {
+        self.backend.exists()
+    }
+}
+
+/// Open a store from any reader, like [`Store::open`], but without persisting anything.
+/// Therefore, stores in older formats or with outdated key derivation parameters are left as is.
+pub fn open_from(mut rd: impl Read, credential: &Credential) -> Result<Vec<Account>, Error> {
+    let mut content = Vec::new();
+    rd.read_to_end(&mut content)?;
 
-    Ok(())
+    unlock(&content, credential, Cost::default()).map(|unlocked| unlocked.accounts)
 }
 
-/// Test whether an otti datastore already exists in the current system.
-pub fn exists() -> Result<bool, Error> {
-    filepath().map(|fp| fp.exists())
+/// Seal the accounts into any writer, as new store with a fresh master key and the credential as
+/// its only slot.
+pub fn seal_to(
+    mut wr: impl Write,
+    accounts: &[Account],
+    credential: &Credential,
+    cost: Cost,
+) -> Result<(), Error> {
+    wr.write_all(&encode(&new_file(accounts, credential, cost)?)?)
+        .map_err(Into::into)
 }
 
-fn filepath() -> Result<PathBuf, Error> {
+/// Recover the master key through the first slot, that the credential unlocks, and decrypt the
+/// accounts with it.
+fn unlock(content: &[u8], credential: &Credential, cost: Cost) -> Result<Unlocked, Error> {
+    let generation = Generation::of(content);
+    let mut file = content;
+
+    let (legacy, ad) = match read_version(&mut file)? {
+        Version::V1 => (
+            rmp_serde::from_read::<_, EncryptedFileV1>(&mut file)?.into(),
+            Vec::new(),
+        ),
+        Version::V2 => {
+            let legacy = rmp_serde::from_read::<_, EncryptedFileV2>(&mut file)?;
+            let ad = legacy.associated_data();
+            (legacy, ad)
+        }
+        Version::V3 => {
+            let file = rmp_serde::from_read::<_, EncryptedFile>(&mut file)?;
+            return unlock_slots(file, generation, credential);
+        }
+    };
+
+    // Older versions only had a single password.
+    let Credential::Password(password) = credential else {
+        return Err(Error::InvalidCredential(credential.kind()));
+    };
+
+    let data = decrypt(&legacy, &ad, password)?;
+    let accounts = rmp_serde::from_slice::<Vec<Account>>(&decompress(&data)?)?;
+    let master = slot::generate_master_key();
+
+    Ok(Unlocked {
+        file: EncryptedFile {
+            slots: vec![Slot::new(&master, credential, cost)?],
+            data: encrypt(&accounts, &master)?,
+            history: Vec::new(),
+        },
+        generation,
+        master,
+        slot: 0,
+        accounts,
+        migrated: true,
+    })
+}
+
+/// Store content with a fresh master key and the credential as its only slot.
+fn new_file(
+    accounts: &[Account],
+    credential: &Credential,
+    cost: Cost,
+) -> Result<EncryptedFile, Error> {
+    let master = slot::generate_master_key();
+
+    Ok(EncryptedFile {
+        slots: vec![Slot::new(&master, credential, cost)?],
+        data: encrypt(accounts, &master)?,
+        history: Vec::new(),
+    })
+}
+
+/// Encode the store content in the current version.
+fn encode(file: &EncryptedFile) -> Result<Vec<u8>, Error> {
+    let mut content = Vec::new();
+    write_version(&mut content, Version::V3)?;
+    rmp_serde::encode::write(&mut content, file)?;
+
+    Ok(content)
+}
+
+fn data_dir() -> Result<PathBuf, Error> {
     Ok(ProjectDirs::from("rocks", "dnaka91", "otti")
         .ok_or(Error::HomefolderNotFound)?
         .data_dir()
-        .join("store.otti"))
+        .to_owned())
 }
 
-fn decompress(data: &[u8]) -> Result<Vec<u8>, Error> {
-    let mut wr = ZlibDecoder::new(Vec::new());
+fn decompress(data: &[u8]) -> Result<Zeroizing<Vec<u8>>, Error> {
+    let mut wr = ZlibDecoder::new(SecretBuffer::default());
     wr.write_all(data)?;
 
-    wr.finish().map_err(Into::into)
+    Ok(wr.finish()?.into_inner())
 }
 
-fn compress(data: &[u8]) -> Result<Vec<u8>, Error> {
-    let mut wr = ZlibEncoder::new(Vec::new(), Compression::best());
+fn compress(data: &[u8]) -> Result<Zeroizing<Vec<u8>>, Error> {
+    let mut wr = ZlibEncoder::new(SecretBuffer::default(), Compression::best());
     wr.write_all(data)?;
 
-    wr.finish().map_err(Into::into)
+    Ok(wr.finish()?.into_inner())
 }
 
-fn decrypt(encrypted: &EncryptedFile, password: &SecretString) -> Result<Vec<u8>, Error> {
+fn decrypt(
+    encrypted: &EncryptedFileV2,
+    ad: &[u8],
+    password: &SecretString,
+) -> Result<Zeroizing<Vec<u8>>, Error> {
     let password = Password::from_slice(password.expose_secret().as_bytes());
     let salt = Salt::from_slice(&encrypted.salt)?;
-    let key = kdf::derive_key(&password, &salt, encrypted.iterations, encrypted.memory, 32)?;
+    let key = kdf::derive_key(
+        encrypted.algorithm,
+        &password,
+        &salt,
+        encrypted.iterations,
+        encrypted.memory,
+        32,
+    )?;
 
-    aead::open(&key, &encrypted.data).map_err(|_e| Error::InvalidPassword)
+    aead::open(&key, &encrypted.data, ad).map_err(|_e| Error::InvalidCredential(SlotKind::Password))
 }
 
-fn encrypt(data: &[u8], password: &SecretString) -> Result<EncryptedFile, Error> {
-    let password = Password::from_slice(password.expose_secret().as_bytes());
-    let salt = Salt::default();
-    let key = kdf::derive_key(&password, &salt, 3, 1 << 16, 32)?;
+fn encrypt(accounts: &[Account], master: &[u8]) -> Result<Vec<u8>, Error> {
+    let mut data = SecretBuffer::default();
+    rmp_serde::encode::write(&mut data, accounts)?;
+    let data = compress(&data.into_inner())?;
+
+    aead::seal(master, &data, &EncryptedFile::associated_data())
+}
 
-    let data = aead::seal(&key, data)?;
+fn decrypt_history(file: &EncryptedFile, master: &[u8]) -> Result<Vec<Entry>, Error> {
+    if file.history.is_empty() {
+        return Ok(Vec::new());
+    }
 
-    Ok(EncryptedFile {
-        salt: salt.as_ref().to_owned(),
-        iterations: 3,
-        memory: 1 << 16,
-        data,
+    let data = aead::open(
+        master,
+        &file.history,
+        &EncryptedFile::history_associated_data(),
+    )?;
+    rmp_serde::from_slice(&decompress(&data)?).map_err(Into::into)
+}
+
+fn encrypt_history(history: &[Entry], master: &[u8]) -> Result<Vec<u8>, Error> {
+    if history.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let mut data = SecretBuffer::default();
+    rmp_serde::encode::write(&mut data, history)?;
+    let data = compress(&data.into_inner())?;
+
+    aead::seal(master, &data, &EncryptedFile::history_associated_data())
+}
+
+fn unlock_slots(
+    file: EncryptedFile,
+    generation: Generation,
+    credential: &Credential,
+) -> Result<Unlocked, Error> {
+    let mut unlocked = None;
+
+    for (index, slot) in file.slots.iter().enumerate() {
+        if slot.kind != credential.kind() {
+            continue;
+        }
+
+        match slot.unlock(credential) {
+            Ok(master) => {
+                unlocked = Some((index, master));
+                break;
+            }
+            Err(Error::InvalidCredential(_)) => {}
+            Err(e) => return Err(e),
+        }
+    }
+
+    let (slot, master) = unlocked.ok_or(match credential {
+        Credential::Identity(_) => Error::UnknownIdentity,
+        _ => Error::InvalidCredential(credential.kind()),
+    })?;
+    let data = aead::open(&master, &file.data, &EncryptedFile::associated_data())?;
+    let accounts = rmp_serde::from_slice(&decompress(&data)?)?;
+
+    Ok(Unlocked {
+        file,
+        generation,
+        master,
+        slot,
+        accounts,
+        migrated: false,
     })
 }
 
@@ -198,3 +939,556 @@ fn write_version(wr: &mut impl Write, version: Version) -> Result<(), Error> {
     wr.write_all(&u16::from(version).to_le_bytes())
         .map_err(Into::into)
 }
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::BTreeMap,
+        fs::{self, File},
+    };
+
+    use otti_core::Key;
+
+    use super::*;
+
+    /// Very low cost, to keep the tests fast.
+    const TEST_COST: Cost = Cost {
+        iterations: 1,
+        memory: 1 << 10,
+    };
+
+    fn temp_dir(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("otti-store-{name}-{}", std::process::id()))
+    }
+
+    fn accounts() -> Vec<Account> {
+        vec![Account {
+            label: "Entry 1".to_owned(),
+            secret: Key::new(vec![1; 10]),
+            digits: 6,
+            otp: otti_core::Otp::Totp { window: 30 },
+            algorithm: otti_core::Algorithm::Sha1,
+            issuer: Some("Provider 1".to_owned()),
+            meta: otti_core::Metadata::default(),
+            extras: BTreeMap::new(),
+        }]
+    }
+
+    fn write(store: &Store, content: &str) {
+        store.backend.save(content.as_bytes()).unwrap();
+    }
+
+    fn urls(accounts: &[Account]) -> Vec<String> {
+        accounts.iter().map(Account::to_url).collect()
+    }
+
+    #[test]
+    fn rotate_and_restore_backups() {
+        let dir = temp_dir("backups");
+        let store = Store::at(dir.join("store.otti")).with_backups(2);
+
+        for content in ["1", "2", "3", "4"] {
+            write(&store, content);
+        }
+
+        assert_eq!("4", fs::read_to_string(store.path()).unwrap());
+        assert_eq!(2, store.backups().unwrap().len());
+        assert_eq!("3", fs::read_to_string(store.backup_path(1)).unwrap());
+        assert_eq!("2", fs::read_to_string(store.backup_path(2)).unwrap());
+
+        store.restore(2).unwrap();
+
+        assert_eq!("2", fs::read_to_string(store.path()).unwrap());
+        assert_eq!("4", fs::read_to_string(store.backup_path(1)).unwrap());
+        assert_eq!("3", fs::read_to_string(store.backup_path(2)).unwrap());
+        assert!(!dir.join("store.otti.tmp").exists());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    fn password() -> Credential {
+        Credential::Password(SecretString::new("123".to_owned()))
+    }
+
+    fn read_file<B: Backend>(store: &Store<B>) -> EncryptedFile {
+        let content = store.load().unwrap();
+        let mut file = content.as_slice();
+        assert_eq!(Version::V3, read_version(&mut file).unwrap());
+        rmp_serde::from_read(&mut file).unwrap()
+    }
+
+    #[test]
+    fn authenticate_kdf_params() {
+        let dir = temp_dir("kdf-params");
+        let store = Store::at(dir.join("store.otti"))
+            .with_backups(0)
+            .with_cost(TEST_COST);
+
+        store.seal(&accounts(), &password()).unwrap();
+        assert_eq!(urls(&accounts()), urls(&store.open(&password()).unwrap()));
+
+        // Force a cheaper key derivation, which must be rejected.
+        let mut file = read_file(&store);
+        file.slots[0].params.as_mut().unwrap().memory = 8;
+        store.persist(&file).unwrap();
+
+        assert!(matches!(
+            store.open(&password()),
+            Err(Error::InvalidCredential(SlotKind::Password))
+        ));
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    /// Save the accounts as a [`Version::V1`] store.
+    fn save_v1(store: &Store) {
+        let salt = Salt::default();
+        let key = kdf::derive_key(
+            kdf::Algorithm::Argon2i,
+            &Password::from_slice(b"123"),
+            &salt,
+            1,
+            1 << 10,
+            32,
+        )
+        .unwrap();
+        let data = compress(&rmp_serde::to_vec(&accounts()).unwrap()).unwrap();
+        let v1 = EncryptedFileV1 {
+            salt: salt.as_ref().to_owned(),
+            iterations: 1,
+            memory: 1 << 10,
+            data: aead::seal(&key, &data, &[]).unwrap(),
+        };
+
+        let mut content = Vec::new();
+        write_version(&mut content, Version::V1).unwrap();
+        rmp_serde::encode::write(&mut content, &v1).unwrap();
+        store.backend.save(&content).unwrap();
+    }
+
+    #[test]
+    fn migrate_v1() {
+        let dir = temp_dir("migrate-v1");
+        let store = Store::at(dir.join("store.otti"))
+            .with_backups(0)
+            .with_cost(TEST_COST);
+
+        save_v1(&store);
+
+        assert_eq!(vec![SlotKind::Password], store.slots().unwrap());
+        assert_eq!(urls(&accounts()), urls(&store.open(&password()).unwrap()));
+
+        assert_eq!(1, read_file(&store).slots.len());
+        assert_eq!(urls(&accounts()), urls(&store.open(&password()).unwrap()));
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    /// Save the accounts as a [`Version::V2`] store, optionally changing the file after sealing.
+    fn save_v2(store: &Store, tamper: impl FnOnce(&mut EncryptedFileV2)) {
+        let salt = Salt::default();
+        let key = kdf::derive_key(
+            kdf::Algorithm::Argon2id,
+            &Password::from_slice(b"123"),
+            &salt,
+            1,
+            1 << 10,
+            32,
+        )
+        .unwrap();
+        let data = compress(&rmp_serde::to_vec(&accounts()).unwrap()).unwrap();
+        let mut v2 = EncryptedFileV2 {
+            algorithm: kdf::Algorithm::Argon2id,
+            salt: salt.as_ref().to_owned(),
+            iterations: 1,
+            memory: 1 << 10,
+            data: Vec::new(),
+        };
+        v2.data = aead::seal(&key, &data, &v2.associated_data()).unwrap();
+        tamper(&mut v2);
+
+        let mut content = Vec::new();
+        write_version(&mut content, Version::V2).unwrap();
+        rmp_serde::encode::write(&mut content, &v2).unwrap();
+        store.backend.save(&content).unwrap();
+    }
+
+    #[test]
+    fn migrate_v2() {
+        let dir = temp_dir("migrate-v2");
+        let store = Store::at(dir.join("store.otti"))
+            .with_backups(0)
+            .with_cost(TEST_COST);
+
+        save_v2(&store, |_| {});
+
+        assert_eq!(vec![SlotKind::Password], store.slots().unwrap());
+        assert_eq!(urls(&accounts()), urls(&store.open(&password()).unwrap()));
+
+        let file = store.backend.load().unwrap().unwrap();
+        assert_eq!(Version::V3, read_version(&mut file.as_slice()).unwrap());
+        assert_eq!(1, read_file(&store).slots.len());
+        assert_eq!(urls(&accounts()), urls(&store.open(&password()).unwrap()));
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn authenticate_v2_kdf_params() {
+        let dir = temp_dir("kdf-params-v2");
+        let store = Store::at(dir.join("store.otti"))
+            .with_backups(0)
+            .with_cost(TEST_COST);
+
+        for tamper in [
+            (|v2: &mut EncryptedFileV2| v2.iterations = 2) as fn(&mut EncryptedFileV2),
+            |v2| v2.memory = 8,
+        ] {
+            save_v2(&store, tamper);
+            let content = store.backend.load().unwrap();
+
+            assert!(matches!(
+                store.open(&password()),
+                Err(Error::InvalidCredential(SlotKind::Password))
+            ));
+            assert_eq!(content, store.backend.load().unwrap(), "not migrated");
+        }
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn rehash_weaker_params() {
+        let dir = temp_dir("rehash");
+        let store = Store::at(dir.join("store.otti"))
+            .with_backups(0)
+            .with_cost(TEST_COST);
+
+        store.seal(&accounts(), &password()).unwrap();
+
+        let stronger = Cost {
+            iterations: 2,
+            ..TEST_COST
+        };
+        let read_params = || {
+            let params = read_file(&store).slots[0].params.unwrap();
+            (params.algorithm, params.iterations)
+        };
+
+        store
+            .clone()
+            .with_rehash(false)
+            .with_cost(stronger)
+            .open(&password())
+            .unwrap();
+        assert_eq!((kdf::Algorithm::Argon2id, 1), read_params());
+
+        store.clone().with_cost(stronger).open(&password()).unwrap();
+        assert_eq!((kdf::Algorithm::Argon2id, 2), read_params());
+
+        store.open(&password()).unwrap();
+        assert_eq!((kdf::Algorithm::Argon2id, 2), read_params());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn unlock_with_any_slot() {
+        let dir = temp_dir("slots");
+        let store = Store::at(dir.join("store.otti"))
+            .with_backups(0)
+            .with_cost(TEST_COST);
+
+        store.seal(&accounts(), &password()).unwrap();
+        let data = read_file(&store).data;
+
+        let key_file = Credential::generate_key_file();
+        let recovery = Credential::generate_recovery_key();
+        assert_eq!(1, store.add_slot(&password(), &key_file).unwrap());
+        assert_eq!(2, store.add_slot(&key_file, &recovery).unwrap());
+
+        assert_eq!(
+            vec![SlotKind::Password, SlotKind::KeyFile, SlotKind::RecoveryKey],
+            store.slots().unwrap()
+        );
+        // Slots are managed without re-encrypting the accounts.
+        assert_eq!(data, read_file(&store).data);
+
+        for credential in [&password(), &key_file, &recovery] {
+            assert_eq!(urls(&accounts()), urls(&store.open(credential).unwrap()));
+        }
+
+        assert!(matches!(
+            store.open(&Credential::generate_key_file()),
+            Err(Error::InvalidCredential(SlotKind::KeyFile))
+        ));
+
+        store.remove_slot(&recovery, 0).unwrap();
+        store.remove_slot(&recovery, 0).unwrap();
+        assert!(matches!(
+            store.open(&password()),
+            Err(Error::InvalidCredential(SlotKind::Password))
+        ));
+        assert!(matches!(
+            store.remove_slot(&recovery, 1),
+            Err(Error::UnknownSlot(1))
+        ));
+        assert!(matches!(
+            store.remove_slot(&recovery, 0),
+            Err(Error::LastSlot)
+        ));
+
+        store.seal(&accounts()[..0], &recovery).unwrap();
+        assert!(store.open(&recovery).unwrap().is_empty());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn share_with_recipients() {
+        let dir = temp_dir("recipients");
+        let store = Store::at(dir.join("store.otti"))
+            .with_backups(0)
+            .with_cost(TEST_COST);
+
+        let alice = Credential::Identity(Identity::generate());
+        let bob = Identity::generate();
+        let carol = Identity::generate();
+
+        store.create(&accounts(), &alice).unwrap();
+        store.add_recipient(&alice, &bob.recipient()).unwrap();
+        store.add_recipient(&alice, &carol.recipient()).unwrap();
+        assert!(matches!(
+            store.add_recipient(&alice, &bob.recipient()),
+            Err(Error::DuplicateRecipient(_))
+        ));
+        assert_eq!(3, store.recipients().unwrap().len());
+
+        let bob = Credential::Identity(bob);
+        let carol = Credential::Identity(carol);
+        assert_eq!(urls(&accounts()), urls(&store.open(&bob).unwrap()));
+
+        // Only recipients are left, so the master key is replaced.
+        let data = read_file(&store).data;
+        let Credential::Identity(identity) = &bob else {
+            unreachable!();
+        };
+        assert!(store
+            .revoke_recipient(&carol, &identity.recipient())
+            .unwrap());
+        assert_ne!(data, read_file(&store).data);
+
+        assert!(matches!(store.open(&bob), Err(Error::UnknownIdentity)));
+        assert_eq!(urls(&accounts()), urls(&store.open(&alice).unwrap()));
+        assert_eq!(urls(&accounts()), urls(&store.open(&carol).unwrap()));
+
+        // With a password slot, the master key must stay the same.
+        store.add_slot(&alice, &password()).unwrap();
+        let Credential::Identity(identity) = &carol else {
+            unreachable!();
+        };
+        assert!(!store
+            .revoke_recipient(&password(), &identity.recipient())
+            .unwrap());
+        assert_eq!(
+            vec![SlotKind::Recipient, SlotKind::Password],
+            store.slots().unwrap()
+        );
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn refuse_concurrent_modifications() {
+        let dir = temp_dir("concurrent");
+        let store = Store::at(dir.join("store.otti"))
+            .with_backups(0)
+            .with_cost(TEST_COST);
+
+        store.create(&accounts(), &password()).unwrap();
+
+        let (first, generation) = store.open_tracked(&password()).unwrap();
+        let (second, stale) = store.open_tracked(&password()).unwrap();
+        assert_eq!(generation, stale);
+
+        let generation = store.seal_tracked(&first, &password(), generation).unwrap();
+        assert_eq!(generation, store.generation().unwrap());
+        assert!(matches!(
+            store.seal_tracked(&second, &password(), stale),
+            Err(Error::Modified)
+        ));
+
+        store
+            .add_slot(&password(), &Credential::generate_recovery_key())
+            .unwrap();
+        assert!(matches!(
+            store.seal_tracked(&first, &password(), generation),
+            Err(Error::Modified)
+        ));
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn lock_between_processes() {
+        let dir = temp_dir("lock");
+        let store = Store::at(dir.join("store.otti"));
+
+        let lock = store.backend.lock().unwrap();
+        let other = File::open(dir.join("store.otti.lock")).unwrap();
+        assert!(matches!(
+            other.try_lock(),
+            Err(fs::TryLockError::WouldBlock)
+        ));
+
+        drop(lock);
+        other.try_lock().unwrap();
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn verify_damage() {
+        let dir = temp_dir("verify");
+        let store = Store::at(dir.join("store.otti"))
+            .with_backups(0)
+            .with_cost(TEST_COST);
+
+        store.create(&accounts(), &password()).unwrap();
+        assert!(store.verify(&password()).unwrap().is_ok());
+
+        let report = store
+            .verify(&Credential::Password(SecretString::new("wrong".to_owned())))
+            .unwrap();
+        assert!(!report.is_ok());
+        assert!(!report.is_damaged());
+
+        let mut file = read_file(&store);
+        let last = file.data.len() - 1;
+        file.data[last] ^= 1;
+        store.persist(&file).unwrap();
+
+        let report = store.verify(&password()).unwrap();
+        assert!(report.is_damaged());
+        assert!(matches!(
+            report.checks[4],
+            (Check::Authentication, Outcome::Failed(_))
+        ));
+        assert_eq!((Check::Payload, Outcome::Skipped), report.checks[5]);
+
+        write(&store, "garbage");
+        let report = store.verify(&password()).unwrap();
+        assert!(matches!(
+            report.checks[0],
+            (Check::Header, Outcome::Failed(_))
+        ));
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn verify_legacy_version() {
+        let dir = temp_dir("verify-legacy");
+        let store = Store::at(dir.join("store.otti"))
+            .with_backups(0)
+            .with_cost(TEST_COST);
+
+        save_v1(&store);
+        assert!(store.verify(&password()).unwrap().is_ok());
+
+        let report = store.verify(&Credential::generate_recovery_key()).unwrap();
+        assert_eq!(
+            (
+                Check::Unlock,
+                Outcome::Failed("only a password can unlock a v1 store".to_owned())
+            ),
+            report.checks[3]
+        );
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn memory_backend() {
+        let store = Store::with_backend(MemoryBackend::default()).with_cost(TEST_COST);
+        assert!(!store.exists().unwrap());
+
+        store.seal(&accounts(), &password()).unwrap();
+        let (opened, generation) = store.open_tracked(&password()).unwrap();
+        assert_eq!(urls(&accounts()), urls(&opened));
+
+        let recovery = Credential::generate_recovery_key();
+        store.add_slot(&password(), &recovery).unwrap();
+        assert!(matches!(
+            store.seal_tracked(&opened, &password(), generation),
+            Err(Error::Modified)
+        ));
+        assert_eq!(2, read_file(&store).slots.len());
+
+        let content = store.backend.into_content().unwrap();
+        assert_eq!(
+            urls(&accounts()),
+            urls(&open_from(content.as_slice(), &recovery).unwrap())
+        );
+    }
+
+    #[test]
+    fn undo_changes() {
+        let store = Store::with_backend(MemoryBackend::default()).with_cost(TEST_COST);
+        let alice = Credential::Identity(Identity::generate());
+        let bob = Identity::generate();
+
+        store.create(&accounts(), &alice).unwrap();
+        store.add_recipient(&alice, &bob.recipient()).unwrap();
+        assert!(store.history(&alice).unwrap().is_empty());
+        assert!(read_file(&store).history.is_empty());
+
+        let mut changed = accounts();
+        changed[0].label = "Entry 2".to_owned();
+        store.seal(&changed, &alice).unwrap();
+        store.seal(&[], &alice).unwrap();
+
+        let history = store.history(&alice).unwrap();
+        assert_eq!(2, history.len());
+        assert!(matches!(history[0].changes[..], [Change::Modified { .. }]));
+        assert!(matches!(history[1].changes[..], [Change::Removed(_)]));
+
+        // The history is re-encrypted along with the accounts, when the master key is replaced.
+        assert!(store.revoke_recipient(&alice, &bob.recipient()).unwrap());
+
+        let reverted = store.undo(&alice, 2).unwrap();
+        assert!(matches!(reverted[0].changes[..], [Change::Removed(_)]));
+        assert_eq!(urls(&accounts()), urls(&store.open(&alice).unwrap()));
+
+        // The undo is recorded as well and can be undone in turn.
+        let history = store.history(&alice).unwrap();
+        assert_eq!(3, history.len());
+        assert!(matches!(history[2].changes[..], [Change::Added(_)]));
+
+        store.undo(&alice, 1).unwrap();
+        assert!(store.open(&alice).unwrap().is_empty());
+        assert_eq!(4, store.history(&alice).unwrap().len());
+        assert!(matches!(
+            store.undo(&alice, 5),
+            Err(Error::HistoryExhausted(4))
+        ));
+    }
+
+    #[test]
+    fn seal_to_and_open_from() {
+        let mut content = Vec::new();
+        seal_to(&mut content, &accounts(), &password(), TEST_COST).unwrap();
+
+        assert_eq!(
+            urls(&accounts()),
+            urls(&open_from(content.as_slice(), &password()).unwrap())
+        );
+        assert!(matches!(
+            open_from(&content[..content.len() - 1], &password()),
+            Err(Error::Crypto | Error::Decode(_))
+        ));
+
+        let store = Store::with_backend(MemoryBackend::with_content(content));
+        assert_eq!(vec![SlotKind::Password], store.slots().unwrap());
+    }
+}
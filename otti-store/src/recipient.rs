@@ -0,0 +1,225 @@
+//! Public-key recipients, that allow several people to unlock the same store, each with their own
+//! identity. Keys and the wrapping of the master key follow the X25519 recipient type of the
+//! [age](https://age-encryption.org/v1) format, so existing age identities can be used as well.
+
+use std::{
+    fmt::{self, Display},
+    str::FromStr,
+};
+
+use bech32::{Bech32, Hrp};
+use chacha20poly1305::{
+    aead::{Aead, OsRng},
+    ChaCha20Poly1305, KeyInit, Nonce,
+};
+use hkdf::Hkdf;
+use otti_core::Zeroizing;
+use secrecy::SecretString;
+use sha2::Sha256;
+use x25519_dalek::{EphemeralSecret, PublicKey, StaticSecret};
+
+use crate::Error;
+
+/// Human readable prefix of encoded identities (upper-cased when encoded).
+const IDENTITY_HRP: Hrp = Hrp::parse_unchecked("age-secret-key-");
+/// Human readable prefix of encoded recipients.
+const RECIPIENT_HRP: Hrp = Hrp::parse_unchecked("age");
+/// Label of the key derivation, that turns the shared secret into the wrapping key.
+const INFO: &[u8] = b"age-encryption.org/v1/X25519";
+/// Size of X25519 keys in bytes.
+const KEY_SIZE: usize = 32;
+
+/// Private key of a single person, that unlocks all slots wrapped for its [`Recipient`].
+pub struct Identity(StaticSecret);
+
+impl Identity {
+    /// Generate a new random identity.
+    #[must_use]
+    pub fn generate() -> Self {
+        Self(StaticSecret::random_from_rng(OsRng))
+    }
+
+    /// Parse the first identity from the content of an identity file, as created by
+    /// `age-keygen` or `otti recipients keygen`. Empty lines and comments are skipped.
+    pub fn from_file(content: &str) -> Result<Self, Error> {
+        content
+            .lines()
+            .map(str::trim)
+            .find(|line| !line.is_empty() && !line.starts_with('#'))
+            .ok_or(Error::InvalidIdentity)?
+            .parse()
+    }
+
+    /// Public recipient of this identity, that others can wrap the store's master key for.
+    #[must_use]
+    pub fn recipient(&self) -> Recipient {
+        Recipient(PublicKey::from(&self.0))
+    }
+
+    /// Encode the identity in the same format, that [`Identity::from_file`] reads.
+    #[must_use]
+    pub fn to_secret_string(&self) -> SecretString {
+        SecretString::new(
+            bech32::encode_upper::<Bech32>(IDENTITY_HRP, self.0.as_bytes()).unwrap_or_default(),
+        )
+    }
+
+    /// Unwrap a master key, that was wrapped for this identity's recipient with the given
+    /// ephemeral share.
+    pub(crate) fn unwrap(&self, share: &[u8], body: &[u8]) -> Result<Zeroizing<Vec<u8>>, Error> {
+        let share = PublicKey::from(to_key(share).ok_or(Error::Crypto)?);
+        let shared = self.0.diffie_hellman(&share);
+        if !shared.was_contributory() {
+            return Err(Error::Crypto);
+        }
+
+        let key = wrap_key(shared.as_bytes(), &share, &self.recipient().0);
+
+        ChaCha20Poly1305::new(key.as_ref().into())
+            .decrypt(&Nonce::default(), body)
+            .map(Zeroizing::new)
+            .map_err(|_e| Error::Crypto)
+    }
+}
+
+impl FromStr for Identity {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (hrp, data) = bech32::decode(s).map_err(|_e| Error::InvalidIdentity)?;
+        if hrp != IDENTITY_HRP {
+            return Err(Error::InvalidIdentity);
+        }
+
+        to_key(&data)
+            .map(|key| Self(StaticSecret::from(key)))
+            .ok_or(Error::InvalidIdentity)
+    }
+}
+
+/// Public key of a person, that the store's master key can be wrapped for.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Recipient(PublicKey);
+
+impl Recipient {
+    /// Recipient from its raw key bytes, as kept in a slot.
+    pub(crate) fn from_bytes(bytes: &[u8]) -> Result<Self, Error> {
+        to_key(bytes)
+            .map(|key| Self(PublicKey::from(key)))
+            .ok_or(Error::Crypto)
+    }
+
+    pub(crate) fn as_bytes(&self) -> &[u8] {
+        self.0.as_bytes()
+    }
+
+    /// Wrap the master key for this recipient, returning the ephemeral share and the wrapped key.
+    pub(crate) fn wrap(&self, master: &[u8]) -> Result<(Vec<u8>, Vec<u8>), Error> {
+        let secret = EphemeralSecret::random_from_rng(OsRng);
+        let share = PublicKey::from(&secret);
+        let shared = secret.diffie_hellman(&self.0);
+        if !shared.was_contributory() {
+            return Err(Error::Crypto);
+        }
+
+        // The zero nonce is safe, as each wrapping key is derived from a fresh ephemeral secret.
+        let key = wrap_key(shared.as_bytes(), &share, &self.0);
+        let body = ChaCha20Poly1305::new(key.as_ref().into())
+            .encrypt(&Nonce::default(), master)
+            .map_err(|_e| Error::Crypto)?;
+
+        Ok((share.as_bytes().to_vec(), body))
+    }
+}
+
+impl Display for Recipient {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        bech32::encode_lower_to_fmt::<Bech32, _>(f, RECIPIENT_HRP, self.0.as_bytes())
+            .map_err(|_e| fmt::Error)
+    }
+}
+
+impl FromStr for Recipient {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || Error::InvalidRecipient(s.to_owned());
+
+        let (hrp, data) = bech32::decode(s).map_err(|_e| invalid())?;
+        if hrp != RECIPIENT_HRP {
+            return Err(invalid());
+        }
+
+        to_key(&data)
+            .map(|key| Self(PublicKey::from(key)))
+            .ok_or_else(invalid)
+    }
+}
+
+fn to_key(bytes: &[u8]) -> Option<[u8; KEY_SIZE]> {
+    bytes.try_into().ok()
+}
+
+/// Derive the key, that wraps the master key, from the shared secret of both parties.
+fn wrap_key(shared: &[u8], share: &PublicKey, recipient: &PublicKey) -> Zeroizing<[u8; KEY_SIZE]> {
+    let mut salt = [0; 2 * KEY_SIZE];
+    salt[..KEY_SIZE].copy_from_slice(share.as_bytes());
+    salt[KEY_SIZE..].copy_from_slice(recipient.as_bytes());
+
+    let mut key = Zeroizing::new([0; KEY_SIZE]);
+    Hkdf::<Sha256>::new(Some(&salt), shared)
+        .expand(INFO, key.as_mut())
+        .unwrap_or_default();
+
+    key
+}
+
+#[cfg(test)]
+mod tests {
+    use otti_core::ExposeSecret;
+
+    use super::*;
+
+    /// Identity from the bytes `1..=32`, and a master key of all 9s wrapped for its recipient with
+    /// an ephemeral secret of all 7s, as produced by an independent age implementation.
+    const IDENTITY: &str =
+        "AGE-SECRET-KEY-1QYPQXPQ9QCRSSZG2PVXQ6RS0ZQG3YYC5Z5TPWXQERGD3C8G7RUSQGPQYEE";
+    const RECIPIENT: &str = "age1q73he0q5yzfu3d64msd3p6rvksnrwjk3d2598mgtmlqt9wrdr37q2vrn72";
+    const SHARE: [u8; 32] = [
+        19, 190, 79, 234, 234, 242, 4, 199, 253, 51, 88, 252, 156, 0, 114, 24, 129, 209, 116, 39,
+        129, 40, 34, 126, 198, 116, 243, 127, 127, 233, 123, 109,
+    ];
+    const BODY: [u8; 48] = [
+        191, 33, 18, 249, 61, 97, 235, 100, 37, 172, 52, 112, 145, 186, 103, 22, 58, 38, 233, 116,
+        14, 176, 25, 236, 78, 149, 63, 65, 24, 152, 67, 159, 115, 91, 195, 221, 101, 147, 239, 76,
+        29, 216, 32, 236, 146, 149, 26, 221,
+    ];
+
+    #[test]
+    fn age_compatible() {
+        let identity =
+            Identity::from_file(&format!("# created by age-keygen\n\n{IDENTITY}\n")).unwrap();
+
+        assert_eq!(RECIPIENT, identity.recipient().to_string());
+        assert_eq!(IDENTITY, identity.to_secret_string().expose_secret());
+        assert_eq!(vec![9; 32], *identity.unwrap(&SHARE, &BODY).unwrap());
+    }
+
+    #[test]
+    fn roundtrip() {
+        let identity = Identity::generate();
+        let recipient = identity
+            .recipient()
+            .to_string()
+            .parse::<Recipient>()
+            .unwrap();
+        let (share, body) = recipient.wrap(&[5; 32]).unwrap();
+
+        assert_eq!(vec![5; 32], *identity.unwrap(&share, &body).unwrap());
+        assert!(Identity::generate().unwrap(&share, &body).is_err());
+        assert!(matches!(
+            IDENTITY.parse::<Recipient>(),
+            Err(Error::InvalidRecipient(_))
+        ));
+    }
+}
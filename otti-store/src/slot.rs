@@ -0,0 +1,321 @@
+//! Unlock slots, that each wrap the randomly generated master key of a store with a different
+//! credential. Any single slot is enough to recover the master key, which in turn encrypts the
+//! accounts.
+
+use std::fmt::{self, Display};
+
+use argon2::password_hash::rand_core::RngCore;
+use blake2::{Blake2b, Digest};
+use chacha20poly1305::aead::OsRng;
+use data_encoding::BASE32_NOPAD;
+use otti_core::{ExposeSecret, Zeroizing};
+use secrecy::{Secret, SecretString, Zeroize};
+use serde::{Deserialize, Serialize};
+use typenum::U32;
+
+use crate::{
+    aead,
+    kdf::{self, Password, Salt},
+    recipient::{Identity, Recipient},
+    Cost, Error, Version,
+};
+
+/// Size of the master key, as well as the keys that wrap it, in bytes.
+pub const KEY_SIZE: usize = 32;
+/// Amount of random bytes in a recovery key.
+const RECOVERY_KEY_SIZE: usize = 20;
+/// Amount of random bytes in a generated key file.
+const KEY_FILE_SIZE: usize = 64;
+/// Amount of characters in each group of a printed recovery key.
+const RECOVERY_GROUP_SIZE: usize = 4;
+
+/// Secret, that unlocks one of the slots of a store.
+pub enum Credential {
+    /// User chosen password, which is stretched with Argon2id.
+    Password(SecretString),
+    /// Content of a key file, usually random bytes.
+    KeyFile(Secret<Vec<u8>>),
+    /// Printable recovery key, as generated by [`Credential::generate_recovery_key`].
+    RecoveryKey(SecretString),
+    /// Private key of a person, whose recipient the master key was wrapped for.
+    Identity(Identity),
+}
+
+impl Credential {
+    /// Generate a new random recovery key, formatted as groups of base32 characters.
+    #[must_use]
+    pub fn generate_recovery_key() -> Self {
+        let mut key = [0; RECOVERY_KEY_SIZE];
+        OsRng.fill_bytes(&mut key);
+
+        let encoded = BASE32_NOPAD.encode(&key);
+        let grouped = encoded
+            .as_bytes()
+            .chunks(RECOVERY_GROUP_SIZE)
+            .map(|group| std::str::from_utf8(group).unwrap_or_default())
+            .collect::<Vec<_>>()
+            .join("-");
+
+        Self::RecoveryKey(SecretString::new(grouped))
+    }
+
+    /// Generate the content for a new random key file.
+    #[must_use]
+    pub fn generate_key_file() -> Self {
+        let mut key = vec![0; KEY_FILE_SIZE];
+        OsRng.fill_bytes(&mut key);
+
+        Self::KeyFile(Secret::new(key))
+    }
+
+    /// Kind of slot, that this credential can unlock.
+    #[must_use]
+    pub fn kind(&self) -> SlotKind {
+        match self {
+            Self::Password(_) => SlotKind::Password,
+            Self::KeyFile(_) => SlotKind::KeyFile,
+            Self::RecoveryKey(_) => SlotKind::RecoveryKey,
+            Self::Identity(_) => SlotKind::Recipient,
+        }
+    }
+}
+
+/// The kinds of slots, depending on the credential they're unlocked with.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SlotKind {
+    Password,
+    KeyFile,
+    RecoveryKey,
+    Recipient,
+}
+
+impl SlotKind {
+    /// Unique identifier of the kind, used to authenticate it.
+    fn id(self) -> u8 {
+        match self {
+            Self::Password => 1,
+            Self::KeyFile => 2,
+            Self::RecoveryKey => 3,
+            Self::Recipient => 4,
+        }
+    }
+}
+
+impl Display for SlotKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Password => "password",
+            Self::KeyFile => "key file",
+            Self::RecoveryKey => "recovery key",
+            Self::Recipient => "recipient",
+        })
+    }
+}
+
+/// Key derivation parameters of a password slot.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct Params {
+    pub algorithm: kdf::Algorithm,
+    pub iterations: u32,
+    pub memory: u32,
+}
+
+/// A single slot, that holds the master key wrapped with a key derived from a credential.
+///
+/// Recipient slots keep the ephemeral share of the key agreement in place of the salt.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Slot {
+    pub kind: SlotKind,
+    pub salt: Vec<u8>,
+    /// Key derivation parameters, only present for password slots.
+    pub params: Option<Params>,
+    pub key: Vec<u8>,
+    /// Public key, that the master key is wrapped for, only present for recipient slots.
+    #[serde(default)]
+    pub recipient: Option<Vec<u8>>,
+}
+
+impl Slot {
+    /// Create a new slot, that wraps the master key with the given credential.
+    pub fn new(master: &[u8], credential: &Credential, cost: Cost) -> Result<Self, Error> {
+        if let Credential::Identity(identity) = credential {
+            return Self::for_recipient(master, &identity.recipient());
+        }
+
+        let salt = Salt::default();
+        let mut slot = Self {
+            kind: credential.kind(),
+            salt: salt.as_ref().to_owned(),
+            params: matches!(credential, Credential::Password(_)).then_some(Params {
+                algorithm: kdf::Algorithm::Argon2id,
+                iterations: cost.iterations,
+                memory: cost.memory,
+            }),
+            key: Vec::new(),
+            recipient: None,
+        };
+
+        let key = slot.derive_key(credential)?;
+        slot.key = aead::seal(&key, master, &slot.associated_data())?;
+
+        Ok(slot)
+    }
+
+    /// Create a new slot, that wraps the master key for the public key of a recipient.
+    pub fn for_recipient(master: &[u8], recipient: &Recipient) -> Result<Self, Error> {
+        let (share, key) = recipient.wrap(master)?;
+
+        Ok(Self {
+            kind: SlotKind::Recipient,
+            salt: share,
+            params: None,
+            key,
+            recipient: Some(recipient.as_bytes().to_vec()),
+        })
+    }
+
+    /// Recipient, that this slot is wrapped for, if it's a recipient slot.
+    pub fn recipient(&self) -> Result<Option<Recipient>, Error> {
+        self.recipient
+            .as_deref()
+            .map(Recipient::from_bytes)
+            .transpose()
+    }
+
+    /// Try to unwrap the master key with the given credential.
+    pub fn unlock(&self, credential: &Credential) -> Result<Zeroizing<Vec<u8>>, Error> {
+        if self.kind != credential.kind() {
+            return Err(Error::InvalidCredential(credential.kind()));
+        }
+
+        if let Credential::Identity(identity) = credential {
+            if self.recipient()? != Some(identity.recipient()) {
+                return Err(Error::InvalidCredential(self.kind));
+            }
+
+            return identity
+                .unwrap(&self.salt, &self.key)
+                .map_err(|_e| Error::InvalidCredential(self.kind));
+        }
+
+        let key = self.derive_key(credential)?;
+        aead::open(&key, &self.key, &self.associated_data())
+            .map_err(|_e| Error::InvalidCredential(self.kind))
+    }
+
+    /// Whether the key derivation parameters are weaker than the given cost, and should therefore
+    /// be upgraded. Only password slots use a configurable key derivation.
+    pub fn is_weaker(&self, cost: Cost) -> bool {
+        self.params.is_some_and(|params| {
+            params.algorithm != kdf::Algorithm::Argon2id
+                || params.iterations < cost.iterations
+                || params.memory < cost.memory
+        })
+    }
+
+    /// Associated data for wrapping the master key, that binds the format version, slot kind and
+    /// all key derivation parameters to it.
+    fn associated_data(&self) -> Vec<u8> {
+        let mut ad = Vec::with_capacity(2 + 1 + self.salt.len() + 1 + 4 + 4);
+        ad.extend_from_slice(&u16::from(Version::V3).to_le_bytes());
+        ad.push(self.kind.id());
+        ad.extend_from_slice(&self.salt);
+
+        if let Some(params) = self.params {
+            ad.push(params.algorithm.id());
+            ad.extend_from_slice(&params.iterations.to_le_bytes());
+            ad.extend_from_slice(&params.memory.to_le_bytes());
+        }
+
+        ad
+    }
+
+    /// Derive the key, that wraps the master key, from the credential.
+    ///
+    /// Passwords are stretched with Argon2, while key files and recovery keys already have enough
+    /// entropy and are only hashed together with the salt.
+    fn derive_key(&self, credential: &Credential) -> Result<Zeroizing<Vec<u8>>, Error> {
+        let salt = Salt::from_slice(&self.salt)?;
+
+        match (credential, self.params) {
+            (Credential::Password(password), Some(params)) => kdf::derive_key(
+                params.algorithm,
+                &Password::from_slice(password.expose_secret().as_bytes()),
+                &salt,
+                params.iterations,
+                params.memory,
+                KEY_SIZE,
+            ),
+            (Credential::KeyFile(content), None) => {
+                Ok(hash_key(self.kind, &salt, content.expose_secret()))
+            }
+            (Credential::RecoveryKey(key), None) => {
+                let key = decode_recovery_key(key.expose_secret())?;
+                Ok(hash_key(self.kind, &salt, &key))
+            }
+            _ => Err(Error::Crypto),
+        }
+    }
+}
+
+/// Generate a new random master key.
+pub fn generate_master_key() -> Zeroizing<Vec<u8>> {
+    let mut key = Zeroizing::new(vec![0; KEY_SIZE]);
+    OsRng.fill_bytes(&mut key);
+    key
+}
+
+fn hash_key(kind: SlotKind, salt: &Salt, secret: &[u8]) -> Zeroizing<Vec<u8>> {
+    let mut hash = Blake2b::<U32>::new()
+        .chain_update(b"otti slot")
+        .chain_update([kind.id()])
+        .chain_update(salt)
+        .chain_update(secret)
+        .finalize();
+    let key = Zeroizing::new(hash.to_vec());
+
+    hash.zeroize();
+    key
+}
+
+/// Decode a recovery key, ignoring the group separators, whitespace and letter case, as these are
+/// easily mixed up when typing the key in.
+fn decode_recovery_key(key: &str) -> Result<Zeroizing<Vec<u8>>, Error> {
+    let key = Zeroizing::new(
+        key.chars()
+            .filter(|c| *c != '-' && !c.is_whitespace())
+            .map(|c| c.to_ascii_uppercase())
+            .collect::<String>(),
+    );
+
+    BASE32_NOPAD
+        .decode(key.as_bytes())
+        .ok()
+        .map(Zeroizing::new)
+        .filter(|key| key.len() == RECOVERY_KEY_SIZE)
+        .ok_or(Error::InvalidRecoveryKey)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn recovery_key_format() {
+        let Credential::RecoveryKey(key) = Credential::generate_recovery_key() else {
+            unreachable!();
+        };
+        let key = key.expose_secret();
+
+        assert_eq!(8, key.split('-').count());
+        assert!(key.split('-').all(|group| group.len() == 4));
+        assert_eq!(
+            decode_recovery_key(key).unwrap(),
+            decode_recovery_key(&key.to_lowercase().replace('-', " ")).unwrap()
+        );
+        assert!(matches!(
+            decode_recovery_key("ABCD-EFGH"),
+            Err(Error::InvalidRecoveryKey)
+        ));
+    }
+}
@@ -0,0 +1,78 @@
+//! Global allocator for tests, that inspects a watched allocation right before it's freed. It
+//! allows to check, that secrets are cleared from memory without reading it after the release.
+
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    ptr, slice,
+    sync::{
+        atomic::{AtomicPtr, AtomicU8, Ordering},
+        Mutex, PoisonError,
+    },
+};
+
+#[global_allocator]
+static ALLOCATOR: Watcher = Watcher;
+
+/// Allocation, that is inspected once it's freed.
+static WATCHED: AtomicPtr<u8> = AtomicPtr::new(ptr::null_mut());
+/// Outcome of the inspection, one of the `STATE_*` values.
+static STATE: AtomicU8 = AtomicU8::new(STATE_PENDING);
+
+const STATE_PENDING: u8 = 0;
+const STATE_ZEROED: u8 = 1;
+const STATE_DIRTY: u8 = 2;
+
+struct Watcher;
+
+unsafe impl GlobalAlloc for Watcher {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        if WATCHED
+            .compare_exchange(ptr, ptr::null_mut(), Ordering::SeqCst, Ordering::SeqCst)
+            .is_ok()
+        {
+            // The allocation is still valid at this point, as it's only released below.
+            let zeroed = slice::from_raw_parts(ptr, layout.size())
+                .iter()
+                .all(|&b| b == 0);
+            STATE.store(
+                if zeroed { STATE_ZEROED } else { STATE_DIRTY },
+                Ordering::SeqCst,
+            );
+        }
+
+        System.dealloc(ptr, layout);
+    }
+}
+
+/// Run the function, which must free the allocation at `ptr`, and tell whether the whole
+/// allocation only contained zeros at the time it was freed.
+///
+/// # Panics
+///
+/// If the function doesn't free the allocation.
+pub fn freed_zeroed(ptr: *const u8, f: impl FnOnce()) -> bool {
+    static LOCK: Mutex<()> = Mutex::new(());
+    let _guard = LOCK.lock().unwrap_or_else(PoisonError::into_inner);
+
+    STATE.store(STATE_PENDING, Ordering::SeqCst);
+    WATCHED.store(ptr.cast_mut(), Ordering::SeqCst);
+    f();
+    WATCHED.store(ptr::null_mut(), Ordering::SeqCst);
+
+    match STATE.load(Ordering::SeqCst) {
+        STATE_ZEROED => true,
+        STATE_DIRTY => false,
+        _ => panic!("the watched allocation wasn't freed"),
+    }
+}
+
+#[test]
+fn detect_leftover_content() {
+    let plain = vec![1_u8; 16];
+    let ptr = plain.as_ptr();
+    assert!(!freed_zeroed(ptr, || drop(plain)));
+}
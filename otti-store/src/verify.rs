@@ -0,0 +1,289 @@
+//! Step by step verification of a store file, that tells apart a damaged store from a wrong
+//! credential. Opening a store only reports the first error, which is often the same for both.
+
+use std::fmt::{self, Display};
+
+use otti_core::{Account, Zeroizing};
+
+use crate::{
+    aead, decompress, decrypt, read_version,
+    slot::{Credential, Slot, SlotKind},
+    Cost, EncryptedFile, EncryptedFileV1, EncryptedFileV2, Version,
+};
+
+/// Expected size of salts and ephemeral shares in bytes.
+const SALT_SIZE: usize = argon2::RECOMMENDED_SALT_LEN;
+/// Expected size of a wrapped master key, including nonce and authentication tag.
+const WRAPPED_KEY_SIZE: usize = 24 + 32 + 16;
+/// Expected size of a master key, that is wrapped for a recipient, including its tag.
+const RECIPIENT_KEY_SIZE: usize = 32 + 16;
+/// Expected size of X25519 keys in bytes.
+const X25519_SIZE: usize = 32;
+
+/// The single checks of a verification, in the order they're performed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Check {
+    /// The file starts with a known version.
+    Header,
+    /// The content after the version decodes as the structure of that version.
+    Envelope,
+    /// All key derivation parameters and key sizes are within sane bounds.
+    Params,
+    /// The credential recovers the key. A failure can be a wrong credential as well as damage.
+    Unlock,
+    /// The encrypted accounts pass authentication with the recovered key.
+    Authentication,
+    /// The decrypted accounts decompress and decode.
+    Payload,
+}
+
+impl Display for Check {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.pad(match self {
+            Self::Header => "version header",
+            Self::Envelope => "envelope",
+            Self::Params => "key parameters",
+            Self::Unlock => "unlock",
+            Self::Authentication => "authentication",
+            Self::Payload => "payload",
+        })
+    }
+}
+
+/// Result of a single check.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Outcome {
+    /// The check passed, with some details about what was found.
+    Passed(String),
+    /// The check failed, with a description of the problem.
+    Failed(String),
+    /// The check wasn't performed, as an earlier one already failed.
+    Skipped,
+}
+
+/// Report about all checks of a store.
+#[derive(Clone, Debug)]
+pub struct Report {
+    pub checks: Vec<(Check, Outcome)>,
+}
+
+impl Report {
+    /// Whether all checks passed.
+    #[must_use]
+    pub fn is_ok(&self) -> bool {
+        self.checks
+            .iter()
+            .all(|(_, outcome)| matches!(outcome, Outcome::Passed(_)))
+    }
+
+    /// Whether the store is certainly damaged. A failed unlock alone may also be caused by a
+    /// wrong credential.
+    #[must_use]
+    pub fn is_damaged(&self) -> bool {
+        self.checks.iter().any(|(check, outcome)| {
+            *check != Check::Unlock && matches!(outcome, Outcome::Failed(_))
+        })
+    }
+
+    /// Run the check, unless any earlier one didn't pass.
+    fn run(&mut self, check: Check, f: impl FnOnce() -> Result<String, String>) {
+        let outcome = match self.checks.last() {
+            Some((_, Outcome::Passed(_))) | None => match f() {
+                Ok(details) => Outcome::Passed(details),
+                Err(reason) => Outcome::Failed(reason),
+            },
+            Some(_) => Outcome::Skipped,
+        };
+
+        self.checks.push((check, outcome));
+    }
+}
+
+/// Decoded content of a store, in any of the versions.
+enum Envelope {
+    /// Store of a version before slots, converted to the latest of those versions.
+    Legacy {
+        version: Version,
+        file: EncryptedFileV2,
+        ad: Vec<u8>,
+    },
+    Current(EncryptedFile),
+}
+
+/// Verify the raw content of a store file with the given credential.
+pub fn verify(content: &[u8], credential: &Credential) -> Report {
+    let mut report = Report { checks: Vec::new() };
+    let mut rd = content;
+    let mut version = None;
+    let mut envelope = None;
+    let mut payload = None;
+
+    report.run(Check::Header, || {
+        let v = read_version(&mut rd).map_err(|e| e.to_string())?;
+        version = Some(v);
+        Ok(format!("format {v}"))
+    });
+
+    report.run(Check::Envelope, || {
+        let decoded =
+            match version {
+                Some(version @ Version::V1) => rmp_serde::from_read::<_, EncryptedFileV1>(&mut rd)
+                    .map(|file| Envelope::Legacy {
+                        version,
+                        file: file.into(),
+                        ad: Vec::new(),
+                    }),
+                Some(version @ Version::V2) => rmp_serde::from_read::<_, EncryptedFileV2>(&mut rd)
+                    .map(|file| Envelope::Legacy {
+                        version,
+                        ad: file.associated_data(),
+                        file,
+                    }),
+                _ => rmp_serde::from_read::<_, EncryptedFile>(&mut rd).map(Envelope::Current),
+            }
+            .map_err(|e| format!("failed to decode: {e}"))?;
+
+        let details = match &decoded {
+            Envelope::Legacy { .. } => "single password".to_owned(),
+            Envelope::Current(file) => format!("{} slots", file.slots.len()),
+        };
+        envelope = Some(decoded);
+        Ok(details)
+    });
+
+    report.run(Check::Params, || match &envelope {
+        Some(Envelope::Legacy { file, .. }) => {
+            check_params(file.salt.len(), file.iterations, file.memory)
+                .map(|()| format!("{:?}, {} KiB", file.algorithm, file.memory))
+                .map_err(|problem| format!("password: {problem}"))
+        }
+        Some(Envelope::Current(file)) => check_slots(&file.slots),
+        None => Err("missing envelope".to_owned()),
+    });
+
+    let mut master = None;
+    report.run(Check::Unlock, || match &envelope {
+        // Legacy stores derive the key from the password directly, so a wrong password and
+        // damaged accounts fail in the same way.
+        Some(Envelope::Legacy { version, file, ad }) => {
+            let Credential::Password(password) = credential else {
+                return Err(format!("only a password can unlock a {version} store"));
+            };
+            let data = decrypt(file, ad, password)
+                .map_err(|_e| "either the password is wrong or the store is damaged".to_owned())?;
+
+            payload = Some(data);
+            Ok("password accepted".to_owned())
+        }
+        Some(Envelope::Current(file)) => {
+            let (index, key) = unlock(&file.slots, credential)?;
+            master = Some(key);
+            Ok(format!(
+                "slot {} accepted the {}",
+                index + 1,
+                credential.kind()
+            ))
+        }
+        None => Err("missing envelope".to_owned()),
+    });
+
+    report.run(Check::Authentication, || match (&envelope, &master) {
+        (Some(Envelope::Current(file)), Some(master)) => {
+            let data = aead::open(master, &file.data, &EncryptedFile::associated_data())
+                .map_err(|_e| "the accounts are corrupted".to_owned())?;
+
+            payload = Some(data);
+            Ok("accounts authenticated".to_owned())
+        }
+        (Some(Envelope::Legacy { .. }), _) => Ok("authenticated while unlocking".to_owned()),
+        _ => Err("missing master key".to_owned()),
+    });
+
+    report.run(Check::Payload, || {
+        let data = payload.as_deref().map_or(&[][..], Vec::as_slice);
+        let data = decompress(data).map_err(|e| format!("failed to decompress: {e}"))?;
+        let accounts = rmp_serde::from_slice::<Vec<Account>>(&data)
+            .map_err(|e| format!("failed to decode accounts: {e}"))?;
+
+        Ok(format!("{} accounts", accounts.len()))
+    });
+
+    report
+}
+
+fn check_slots(slots: &[Slot]) -> Result<String, String> {
+    if slots.is_empty() {
+        return Err("no slots, so the store can't be unlocked".to_owned());
+    }
+
+    for (index, slot) in slots.iter().enumerate() {
+        check_slot(slot)
+            .map_err(|problem| format!("slot {} ({}): {problem}", index + 1, slot.kind))?;
+    }
+
+    Ok(format!("{} slots within bounds", slots.len()))
+}
+
+fn check_slot(slot: &Slot) -> Result<(), String> {
+    match (slot.kind, slot.params) {
+        (SlotKind::Password, Some(params)) => {
+            check_params(slot.salt.len(), params.iterations, params.memory)?;
+        }
+        (SlotKind::Password, None) => return Err("missing key derivation parameters".to_owned()),
+        (_, Some(_)) => return Err("unexpected key derivation parameters".to_owned()),
+        (SlotKind::Recipient, None) => {
+            check_size("ephemeral share", slot.salt.len(), X25519_SIZE)?;
+            check_size("wrapped key", slot.key.len(), RECIPIENT_KEY_SIZE)?;
+            check_size(
+                "recipient",
+                slot.recipient.as_ref().map_or(0, Vec::len),
+                X25519_SIZE,
+            )?;
+            return Ok(());
+        }
+        (SlotKind::KeyFile | SlotKind::RecoveryKey, None) => {
+            check_size("salt", slot.salt.len(), SALT_SIZE)?;
+        }
+    }
+
+    check_size("wrapped key", slot.key.len(), WRAPPED_KEY_SIZE)
+}
+
+fn check_params(salt: usize, iterations: u32, memory: u32) -> Result<(), String> {
+    check_size("salt", salt, SALT_SIZE)?;
+
+    if !Cost::ITERATIONS.contains(&iterations) {
+        return Err(format!("{iterations} iterations are out of bounds"));
+    }
+    if !Cost::MEMORY.contains(&memory) {
+        return Err(format!("{memory} KiB of memory are out of bounds"));
+    }
+
+    Ok(())
+}
+
+fn check_size(name: &str, actual: usize, expected: usize) -> Result<(), String> {
+    if actual == expected {
+        Ok(())
+    } else {
+        Err(format!("{name} has {actual} bytes instead of {expected}"))
+    }
+}
+
+/// Recover the master key through the first slot, that accepts the credential.
+fn unlock(slots: &[Slot], credential: &Credential) -> Result<(usize, Zeroizing<Vec<u8>>), String> {
+    let kind = credential.kind();
+
+    slots
+        .iter()
+        .enumerate()
+        .filter(|(_, slot)| slot.kind == kind)
+        .find_map(|(index, slot)| slot.unlock(credential).ok().map(|master| (index, master)))
+        .ok_or_else(|| {
+            if slots.iter().any(|slot| slot.kind == kind) {
+                format!("no slot accepts the {kind}, either it's wrong or the slot is damaged")
+            } else {
+                format!("the store has no {kind} slot")
+            }
+        })
+}
@@ -10,7 +10,7 @@ use aes_gcm::{
     AeadInPlace, Aes256Gcm, KeyInit,
 };
 pub use bytes::{Buf, BufMut};
-use otti_core::{ExposeSecret, Key};
+use otti_core::{ExposeSecret, Key, SecretString, Zeroizing};
 #[cfg(not(test))]
 use rand::prelude::*;
 use scrypt::Params as ScryptParams;
@@ -140,7 +140,7 @@ impl From<Entry> for otti_core::Account {
 
         Self {
             label: e.name,
-            secret: Key::new(info.secret),
+            secret: Key::new(info.secret.to_vec()),
             digits: info.digits,
             otp,
             algorithm: info.algo.into(),
@@ -159,7 +159,7 @@ impl From<Entry> for otti_core::Account {
 impl From<&otti_core::Account> for Entry {
     fn from(a: &otti_core::Account) -> Self {
         let info = OtpInfo {
-            secret: a.secret.expose_secret().clone(),
+            secret: Zeroizing::new(a.secret.expose_secret().clone()),
             algo: a.algorithm.into(),
             digits: a.digits,
         };
@@ -217,7 +217,7 @@ enum EntryType {
 #[derive(Debug, Serialize, Deserialize)]
 struct OtpInfo {
     #[serde(with = "otti_core::de::base32_string")]
-    secret: Vec<u8>,
+    secret: Zeroizing<Vec<u8>>,
     algo: Algorithm,
     digits: u8,
 }
@@ -399,11 +399,11 @@ fn random_array<U: ArrayLength<u8>>() -> GenericArray<u8, U> {
 
 pub fn load(
     data: &mut impl Buf,
-    password: Option<impl AsRef<[u8]>>,
+    password: Option<&SecretString>,
 ) -> Result<Vec<otti_core::Account>, Error> {
     let vault = match password {
         Some(pw) => {
-            let buf = decrypt(data, pw)?;
+            let buf = decrypt(data, pw.expose_secret())?;
             serde_json::from_slice::<Vault>(&buf)?
         }
         None => serde_json::from_reader::<_, ExportPlain>(data.reader()).map(|e| e.db)?,
@@ -415,7 +415,7 @@ pub fn load(
 pub fn save(
     buf: &mut impl BufMut,
     data: &[otti_core::Account],
-    password: Option<impl AsRef<[u8]>>,
+    password: Option<&SecretString>,
 ) -> Result<(), Error> {
     let vault = Vault {
         version: VaultVersion::V2,
@@ -425,7 +425,7 @@ pub fn save(
     match password {
         Some(pw) => {
             let json = serde_json::to_vec(&vault)?;
-            encrypt(buf, &json, pw)
+            encrypt(buf, &json, pw.expose_secret())
         }
         None => {
             let json = serde_json::to_vec(&ExportPlain {
@@ -451,23 +451,32 @@ mod tests {
     #[test]
     fn roundtrip_plain() {
         let file = include_bytes!("../import/aegis-export-plain.json");
-        let accounts = load(&mut &file[..], None::<&str>).unwrap();
+        let accounts = load(&mut &file[..], None).unwrap();
 
         let mut file = Vec::new();
-        save(&mut file, &accounts, None::<&str>).unwrap();
+        save(&mut file, &accounts, None).unwrap();
 
-        load(&mut file.as_slice(), None::<&str>).unwrap();
+        load(&mut file.as_slice(), None).unwrap();
     }
 
     #[test]
     fn roundtrip_encrypted() {
         let file = include_bytes!("../import/aegis-export.json");
-        let accounts = load(&mut &file[..], Some("123")).unwrap();
+        let accounts = load(&mut &file[..], Some(&SecretString::new("123".to_owned()))).unwrap();
 
         let mut file = Vec::new();
-        save(&mut file, &accounts, Some("abc")).unwrap();
-
-        load(&mut file.as_slice(), Some("abc")).unwrap();
+        save(
+            &mut file,
+            &accounts,
+            Some(&SecretString::new("abc".to_owned())),
+        )
+        .unwrap();
+
+        load(
+            &mut file.as_slice(),
+            Some(&SecretString::new("abc".to_owned())),
+        )
+        .unwrap();
     }
 
     #[test]
@@ -490,7 +499,7 @@ mod tests {
             },
         }];
 
-        save(&mut export, &data, None::<&str>).unwrap();
+        save(&mut export, &data, None).unwrap();
 
         let output = serde_json::from_slice::<serde_json::Value>(&export).unwrap();
         let expected = json! {{
@@ -543,7 +552,12 @@ mod tests {
             },
         }];
 
-        save(&mut export, &data, Some("123")).unwrap();
+        save(
+            &mut export,
+            &data,
+            Some(&SecretString::new("123".to_owned())),
+        )
+        .unwrap();
 
         let output = serde_json::from_slice::<serde_json::Value>(&export).unwrap();
         let expected = json! {{
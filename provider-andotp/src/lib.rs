@@ -14,7 +14,7 @@ use aes_gcm::{
 };
 pub use bytes::{Buf, BufMut};
 use hmac::Hmac;
-use otti_core::{ExposeSecret, Key};
+use otti_core::{ExposeSecret, Key, SecretString, Zeroizing};
 use rand::prelude::*;
 use serde::{Deserialize, Serialize};
 use sha1::Sha1;
@@ -34,7 +34,7 @@ pub enum Error {
 #[derive(Debug, Serialize, Deserialize)]
 struct Account {
     #[serde(with = "otti_core::de::base32_string")]
-    secret: Vec<u8>,
+    secret: Zeroizing<Vec<u8>>,
     issuer: String,
     label: String,
     #[serde(default)]
@@ -59,7 +59,7 @@ impl From<Account> for otti_core::Account {
     fn from(a: Account) -> Self {
         Self {
             label: a.label,
-            secret: Key::new(a.secret),
+            secret: Key::new(a.secret.to_vec()),
             digits: a.digits,
             otp: match a.ty {
                 OtpType::Hotp => otti_core::Otp::Hotp { counter: a.counter },
@@ -77,7 +77,7 @@ impl From<Account> for otti_core::Account {
 impl From<&otti_core::Account> for Account {
     fn from(a: &otti_core::Account) -> Self {
         Self {
-            secret: a.secret.expose_secret().clone(),
+            secret: Zeroizing::new(a.secret.expose_secret().clone()),
             issuer: a.issuer.clone().unwrap_or_default(),
             label: a.label.clone(),
             digits: a.digits,
@@ -215,10 +215,10 @@ fn random_array<U: ArrayLength<u8>>() -> GenericArray<u8, U> {
 
 pub fn load(
     data: &mut impl Buf,
-    password: Option<impl AsRef<[u8]>>,
+    password: Option<&SecretString>,
 ) -> Result<Vec<otti_core::Account>, Error> {
     let json = match password {
-        Some(pw) => decrypt(data, pw)?,
+        Some(pw) => decrypt(data, pw.expose_secret())?,
         None => {
             let mut buf = vec![0_u8; data.remaining()];
             data.copy_to_slice(&mut buf);
@@ -235,12 +235,12 @@ pub fn load(
 pub fn save(
     buf: &mut impl BufMut,
     data: &[otti_core::Account],
-    password: Option<impl AsRef<[u8]>>,
+    password: Option<&SecretString>,
 ) -> Result<(), Error> {
     let json = serde_json::to_vec(&data.iter().map(Into::into).collect::<Vec<Account>>())?;
 
     match password {
-        Some(pw) => encrypt(buf, &json, pw),
+        Some(pw) => encrypt(buf, &json, pw.expose_secret()),
         None => {
             buf.put(json.as_ref());
             Ok(())
@@ -260,23 +260,32 @@ mod tests {
     #[test]
     fn roundtrip_plain() {
         let file = include_bytes!("../import/otp_accounts.json");
-        let accounts = load(&mut &file[..], None::<&str>).unwrap();
+        let accounts = load(&mut &file[..], None).unwrap();
 
         let mut file = Vec::new();
-        save(&mut file, &accounts, None::<&str>).unwrap();
+        save(&mut file, &accounts, None).unwrap();
 
-        load(&mut file.as_slice(), None::<&str>).unwrap();
+        load(&mut file.as_slice(), None).unwrap();
     }
 
     #[test]
     fn roundtrip_encrypted() {
         let file = include_bytes!("../import/otp_accounts.json.aes");
-        let accounts = load(&mut &file[..], Some("123")).unwrap();
+        let accounts = load(&mut &file[..], Some(&SecretString::new("123".to_owned()))).unwrap();
 
         let mut file = Vec::new();
-        save(&mut file, &accounts, Some("abc")).unwrap();
-
-        load(&mut file.as_slice(), Some("abc")).unwrap();
+        save(
+            &mut file,
+            &accounts,
+            Some(&SecretString::new("abc".to_owned())),
+        )
+        .unwrap();
+
+        load(
+            &mut file.as_slice(),
+            Some(&SecretString::new("abc".to_owned())),
+        )
+        .unwrap();
     }
 
     #[test]
@@ -295,7 +304,7 @@ mod tests {
             extras: BTreeMap::new(),
         }];
 
-        save(&mut export, &data, None::<&str>).unwrap();
+        save(&mut export, &data, None).unwrap();
 
         let output = serde_json::from_slice::<serde_json::Value>(&export).unwrap();
         let expected = json! {[{
@@ -328,7 +337,12 @@ mod tests {
             extras: BTreeMap::new(),
         }];
 
-        save(&mut export, &data, Some("123")).unwrap();
+        save(
+            &mut export,
+            &data,
+            Some(&SecretString::new("123".to_owned())),
+        )
+        .unwrap();
 
         let expected = &[
             0, 2, 34, 224, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
@@ -13,7 +13,7 @@ use aes::{
 };
 use bytes::{Buf, BufMut};
 use hmac::Hmac;
-use otti_core::{ExposeSecret, Key};
+use otti_core::{ExposeSecret, Key, SecretString, Zeroizing};
 use rand::{Rng, RngCore};
 use serde::{Deserialize, Serialize};
 use serde_repr::{Deserialize_repr, Serialize_repr};
@@ -55,7 +55,7 @@ struct Authenticator {
     issuer: String,
     username: String,
     #[serde(with = "otti_core::de::base32_string")]
-    secret: Vec<u8>,
+    secret: Zeroizing<Vec<u8>>,
     algorithm: Algorithm,
     digits: u8,
     period: u64,
@@ -80,7 +80,7 @@ impl<'a> TryFrom<AuthenticatorWithCategories<'a>> for otti_core::Account {
 
         Ok(Self {
             label: a.username,
-            secret: Key::new(a.secret),
+            secret: Key::new(a.secret.to_vec()),
             digits: a.digits,
             otp: match a.ty {
                 OtpType::Hotp => otti_core::Otp::Hotp { counter: a.counter },
@@ -109,7 +109,7 @@ impl From<&otti_core::Account> for Authenticator {
             icon: None,
             issuer: a.issuer.clone().unwrap_or_default(),
             username: a.label.clone(),
-            secret: a.secret.expose_secret().clone(),
+            secret: Zeroizing::new(a.secret.expose_secret().clone()),
             algorithm: a.algorithm.into(),
             digits: a.digits,
             period: match a.otp {
@@ -180,7 +180,7 @@ struct Category {
 struct AuthenticatorCategory {
     category_id: String,
     #[serde(with = "otti_core::de::base32_string")]
-    authenticator_secret: Vec<u8>,
+    authenticator_secret: Zeroizing<Vec<u8>>,
     ranking: u64,
 }
 
@@ -270,7 +270,7 @@ fn random_array<U: ArrayLength<u8>>() -> GenericArray<u8, U> {
 
 pub fn load(
     data: &mut impl Buf,
-    password: Option<impl AsRef<[u8]>>,
+    password: Option<&SecretString>,
 ) -> Result<Vec<otti_core::Account>, Error> {
     let Backup {
         authenticators,
@@ -279,7 +279,7 @@ pub fn load(
         ..
     } = match password {
         Some(pw) => {
-            let buf = decrypt(data, pw)?;
+            let buf = decrypt(data, pw.expose_secret())?;
             serde_json::from_slice::<Backup>(&buf)?
         }
         None => serde_json::from_reader::<_, Backup>(data.reader())?,
@@ -302,7 +302,7 @@ pub fn load(
 pub fn save(
     buf: &mut impl BufMut,
     data: &[otti_core::Account],
-    password: Option<impl AsRef<[u8]>>,
+    password: Option<&SecretString>,
 ) -> Result<(), Error> {
     let json = serde_json::to_vec(&Backup {
         authenticators: data.iter().map(Into::into).collect(),
@@ -312,7 +312,7 @@ pub fn save(
     })?;
 
     match password {
-        Some(pw) => encrypt(buf, &json, pw),
+        Some(pw) => encrypt(buf, &json, pw.expose_secret()),
         None => {
             buf.put(json.as_ref());
             Ok(())
@@ -331,23 +331,32 @@ mod tests {
     #[test]
     fn roundtrip_plain() {
         let file = include_bytes!("../import/backup.json");
-        let accounts = load(&mut &file[..], None::<&str>).unwrap();
+        let accounts = load(&mut &file[..], None).unwrap();
 
         let mut file = Vec::new();
-        save(&mut file, &accounts, None::<&str>).unwrap();
+        save(&mut file, &accounts, None).unwrap();
 
-        load(&mut file.as_slice(), None::<&str>).unwrap();
+        load(&mut file.as_slice(), None).unwrap();
     }
 
     #[test]
     fn roundtrip_encrypted() {
         let file = include_bytes!("../import/backup.authpro");
-        let accounts = load(&mut &file[..], Some("123")).unwrap();
+        let accounts = load(&mut &file[..], Some(&SecretString::new("123".to_owned()))).unwrap();
 
         let mut file = Vec::new();
-        save(&mut file, &accounts, Some("abc")).unwrap();
-
-        load(&mut file.as_slice(), Some("abc")).unwrap();
+        save(
+            &mut file,
+            &accounts,
+            Some(&SecretString::new("abc".to_owned())),
+        )
+        .unwrap();
+
+        load(
+            &mut file.as_slice(),
+            Some(&SecretString::new("abc".to_owned())),
+        )
+        .unwrap();
     }
 
     #[test]
@@ -368,7 +377,7 @@ mod tests {
             },
         }];
 
-        save(&mut export, &data, None::<&str>).unwrap();
+        save(&mut export, &data, None).unwrap();
 
         let output = serde_json::from_slice::<serde_json::Value>(&export).unwrap();
         let expected = json! {{
@@ -410,7 +419,12 @@ mod tests {
             },
         }];
 
-        save(&mut export, &data, Some("123")).unwrap();
+        save(
+            &mut export,
+            &data,
+            Some(&SecretString::new("123".to_owned())),
+        )
+        .unwrap();
 
         let expected = &[
             65, 117, 116, 104, 101, 110, 116, 105, 99, 97, 116, 111, 114, 80, 114, 111, 0, 0, 0, 0,
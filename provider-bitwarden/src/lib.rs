@@ -0,0 +1,365 @@
+//! # Otti - Provider `Bitwarden`
+//!
+//! Import/Export component that allows to transform between the Otti accounts and the
+//! unencrypted JSON export of the [`Bitwarden`](https://bitwarden.com) password manager.
+//!
+//! Only login items that carry a TOTP value are considered. Bitwarden stores these as bare Base32
+//! secret, as `otpauth://` URL or as `steam://` URL for Steam accounts.
+
+#![deny(rust_2018_idioms, clippy::all, clippy::pedantic)]
+#![allow(clippy::missing_errors_doc)]
+
+use std::collections::BTreeMap;
+
+pub use bytes::{Buf, BufMut};
+use otti_core::{ExposeSecret, Key, SecretString, Zeroizing};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("JSON (de-)serialization failed")]
+    Json(#[from] serde_json::Error),
+    #[error("encrypted Bitwarden exports are not supported, use the unencrypted JSON format")]
+    Encrypted,
+    #[error("the TOTP secret of item `{0}` is not valid Base32")]
+    InvalidSecret(String, #[source] data_encoding::DecodeError),
+    #[error("the TOTP URL of item `{0}` is not valid")]
+    InvalidUrl(String, #[source] otti_core::ParseError),
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Export {
+    encrypted: bool,
+    #[serde(default)]
+    folders: Vec<Folder>,
+    items: Vec<Item>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Folder {
+    id: String,
+    name: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Item {
+    #[serde(default)]
+    folder_id: Option<String>,
+    #[serde(rename = "type")]
+    ty: u8,
+    name: String,
+    notes: Option<String>,
+    #[serde(default)]
+    favorite: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    login: Option<Login>,
+}
+
+/// Item type of a login entry. All other item types (like cards or secure notes) can't hold TOTP
+/// data.
+const ITEM_TYPE_LOGIN: u8 = 1;
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Login {
+    username: Option<String>,
+    password: Option<String>,
+    totp: Option<Zeroizing<String>>,
+}
+
+const EXTRA_NOTES: &str = "bitwarden/notes";
+
+/// Prefix of the Steam specific TOTP format, followed by the Base32 encoded secret.
+const STEAM_PREFIX: &str = "steam://";
+/// Default period of TOTPs in Bitwarden, if not defined otherwise.
+const DEFAULT_PERIOD: u64 = 30;
+/// Default amount of digits for bare TOTP secrets.
+const DEFAULT_DIGITS: u8 = 6;
+/// Default amount of digits for Steam TOTPs.
+const DEFAULT_STEAM_DIGITS: u8 = 5;
+
+impl Item {
+    fn into_account(self, folders: &[Folder]) -> Result<Option<otti_core::Account>, Error> {
+        if self.ty != ITEM_TYPE_LOGIN {
+            return Ok(None);
+        }
+
+        let Some(Login {
+            username,
+            totp: Some(totp),
+            ..
+        }) = self.login
+        else {
+            return Ok(None);
+        };
+
+        let mut account = parse_totp(&self.name, totp.trim())?;
+
+        if let Some(username) = username.filter(|u| !u.is_empty()) {
+            account.label = username;
+        }
+        if !self.name.is_empty() {
+            account.issuer = Some(self.name);
+        }
+
+        if let Some(folder) = self
+            .folder_id
+            .and_then(|id| folders.iter().find(|f| f.id == id))
+        {
+            account.meta.tags.push(folder.name.clone());
+        }
+
+        if let Some(notes) = self.notes.filter(|n| !n.is_empty()) {
+            account
+                .extras
+                .insert(EXTRA_NOTES.to_owned(), notes.into_bytes());
+        }
+
+        Ok(Some(account))
+    }
+}
+
+impl From<&otti_core::Account> for Item {
+    fn from(a: &otti_core::Account) -> Self {
+        let totp = match a.otp {
+            otti_core::Otp::Steam { .. } => format!(
+                "{STEAM_PREFIX}{}",
+                data_encoding::BASE32_NOPAD.encode(a.secret.expose_secret())
+            ),
+            otti_core::Otp::Hotp { .. } | otti_core::Otp::Totp { .. } => a.to_url(),
+        };
+
+        Self {
+            folder_id: None,
+            ty: ITEM_TYPE_LOGIN,
+            name: a
+                .issuer
+                .clone()
+                .filter(|i| !i.is_empty())
+                .unwrap_or_else(|| a.label.clone()),
+            notes: a
+                .extras
+                .get(EXTRA_NOTES)
+                .cloned()
+                .and_then(|v| String::from_utf8(v).ok()),
+            favorite: false,
+            login: Some(Login {
+                username: Some(a.label.clone()),
+                password: None,
+                totp: Some(Zeroizing::new(totp)),
+            }),
+        }
+    }
+}
+
+/// Parse any of the TOTP formats that Bitwarden supports. The `name` of the item is only used for
+/// error reporting.
+fn parse_totp(name: &str, totp: &str) -> Result<otti_core::Account, Error> {
+    if totp
+        .get(..10)
+        .is_some_and(|p| p.eq_ignore_ascii_case("otpauth://"))
+    {
+        return totp
+            .parse()
+            .map_err(|e| Error::InvalidUrl(name.to_owned(), e));
+    }
+
+    let (secret, otp, digits) = match totp.strip_prefix(STEAM_PREFIX) {
+        Some(secret) => (
+            secret,
+            otti_core::Otp::Steam {
+                period: DEFAULT_PERIOD,
+            },
+            DEFAULT_STEAM_DIGITS,
+        ),
+        None => (
+            totp,
+            otti_core::Otp::Totp {
+                window: DEFAULT_PERIOD,
+            },
+            DEFAULT_DIGITS,
+        ),
+    };
+
+    Ok(otti_core::Account {
+        label: String::new(),
+        secret: Key::new(
+            otti_core::de::decode_secret(secret)
+                .map_err(|e| Error::InvalidSecret(name.to_owned(), e))?,
+        ),
+        digits,
+        otp,
+        algorithm: otti_core::Algorithm::Sha1,
+        issuer: None,
+        meta: otti_core::Metadata::default(),
+        extras: BTreeMap::new(),
+    })
+}
+
+pub fn load(
+    data: &mut impl Buf,
+    password: Option<&SecretString>,
+) -> Result<Vec<otti_core::Account>, Error> {
+    if password.is_some() {
+        return Err(Error::Encrypted);
+    }
+
+    let export = serde_json::from_reader::<_, Export>(data.reader())?;
+    if export.encrypted {
+        return Err(Error::Encrypted);
+    }
+
+    export
+        .items
+        .into_iter()
+        .filter_map(|item| item.into_account(&export.folders).transpose())
+        .collect()
+}
+
+pub fn save(
+    buf: &mut impl BufMut,
+    data: &[otti_core::Account],
+    password: Option<&SecretString>,
+) -> Result<(), Error> {
+    if password.is_some() {
+        return Err(Error::Encrypted);
+    }
+
+    let json = serde_json::to_vec(&Export {
+        encrypted: false,
+        folders: vec![],
+        items: data.iter().map(Into::into).collect(),
+    })?;
+
+    buf.put(json.as_ref());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use pretty_assertions::assert_eq;
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn roundtrip_plain() {
+        let file = include_bytes!("../import/bitwarden-export.json");
+        let accounts = load(&mut &file[..], None).unwrap();
+
+        let mut file = Vec::new();
+        save(&mut file, &accounts, None).unwrap();
+
+        load(&mut file.as_slice(), None).unwrap();
+    }
+
+    #[test]
+    fn import_plain() {
+        let file = include_bytes!("../import/bitwarden-export.json");
+        let accounts = load(&mut &file[..], None).unwrap();
+
+        assert_eq!(3, accounts.len());
+        assert!(accounts
+            .iter()
+            .all(|a| a.secret.expose_secret() == b"1234567890"));
+
+        assert_eq!(Some("Sample Issuer"), accounts[0].issuer.as_deref());
+        assert_eq!("Sample TOTP", accounts[0].label);
+        assert_eq!(8, accounts[0].digits);
+        assert!(matches!(
+            accounts[0].otp,
+            otti_core::Otp::Totp { window: 60 }
+        ));
+        assert_eq!(vec!["Work".to_owned()], accounts[0].meta.tags);
+
+        assert_eq!(Some("Bare Secret"), accounts[1].issuer.as_deref());
+        assert_eq!("Sample Bare", accounts[1].label);
+        assert_eq!(6, accounts[1].digits);
+        assert!(matches!(
+            accounts[1].otp,
+            otti_core::Otp::Totp { window: 30 }
+        ));
+
+        assert_eq!(Some("Steam"), accounts[2].issuer.as_deref());
+        assert_eq!("Sample Steam", accounts[2].label);
+        assert_eq!(5, accounts[2].digits);
+        assert!(matches!(
+            accounts[2].otp,
+            otti_core::Otp::Steam { period: 30 }
+        ));
+    }
+
+    #[test]
+    fn import_encrypted() {
+        let file = br#"{"encrypted": true, "passwordProtected": true, "items": []}"#;
+        let result = load(&mut &file[..], None);
+
+        assert!(matches!(result, Err(Error::Encrypted)));
+    }
+
+    #[test]
+    fn export_plain() {
+        let mut export = Vec::new();
+        let data = [
+            otti_core::Account {
+                label: "Entry 1".to_owned(),
+                secret: Key::new(vec![0; 10]),
+                digits: 6,
+                otp: otti_core::Otp::Totp { window: 30 },
+                algorithm: otti_core::Algorithm::Sha1,
+                issuer: Some("Provider 1".to_owned()),
+                meta: otti_core::Metadata {
+                    tags: vec!["Tag 1".to_owned()],
+                },
+                extras: BTreeMap::from([("bitwarden/notes".to_owned(), b"test".to_vec())]),
+            },
+            otti_core::Account {
+                label: "Entry 2".to_owned(),
+                secret: Key::new(vec![0; 10]),
+                digits: 5,
+                otp: otti_core::Otp::Steam { period: 30 },
+                algorithm: otti_core::Algorithm::Sha1,
+                issuer: None,
+                meta: otti_core::Metadata::default(),
+                extras: BTreeMap::new(),
+            },
+        ];
+
+        save(&mut export, &data, None).unwrap();
+
+        let output = serde_json::from_slice::<serde_json::Value>(&export).unwrap();
+        let expected = json! {{
+            "encrypted": false,
+            "folders": [],
+            "items": [{
+                "folderId": null,
+                "type": 1,
+                "name": "Provider 1",
+                "notes": "test",
+                "favorite": false,
+                "login": {
+                    "username": "Entry 1",
+                    "password": null,
+                    "totp": "otpauth://totp/Provider%201:Entry%201?secret=AAAAAAAAAAAAAAAA&\
+                              issuer=Provider%201&algorithm=SHA1&digits=6&period=30"
+                }
+            }, {
+                "folderId": null,
+                "type": 1,
+                "name": "Entry 2",
+                "notes": null,
+                "favorite": false,
+                "login": {
+                    "username": "Entry 2",
+                    "password": null,
+                    "totp": "steam://AAAAAAAAAAAAAAAA"
+                }
+            }]
+        }};
+
+        assert_eq!(expected, output);
+    }
+}
@@ -0,0 +1,493 @@
+//! # Otti - Provider CSV
+//!
+//! Import/Export component that allows to transform between the Otti accounts and CSV files, as
+//! they are commonly maintained in spreadsheets.
+//!
+//! Columns are identified by the names in the header row. The names for each account field can be
+//! changed with [`Columns`], and all columns except for the secret are optional. Rows that fail to
+//! parse don't abort the import, but are reported together with their row number.
+
+#![deny(rust_2018_idioms, clippy::all, clippy::pedantic)]
+#![allow(clippy::missing_errors_doc)]
+
+use std::{collections::BTreeMap, fmt, str::FromStr};
+
+pub use bytes::{Buf, BufMut};
+use otti_core::{ExposeSecret, Key, SecretString};
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("CSV files can't be encrypted, omit the password")]
+    Encrypted,
+    #[error("the header row has no `{0}` column for the OTP secret")]
+    MissingColumn(String),
+    #[error("CSV (de-)serialization failed")]
+    Csv(#[from] csv::Error),
+    #[error("I/O bound error")]
+    Io(#[from] std::io::Error),
+}
+
+/// A single row of the CSV file, that couldn't be parsed into an account.
+#[derive(Debug, thiserror::Error)]
+#[error("the row at line {line} is not a valid account")]
+pub struct RowError {
+    /// Line of the file, where the row starts. The header is on line 1, and blank lines count as
+    /// well, so it matches the row numbers of spreadsheet applications, unless earlier rows
+    /// contain line breaks in quoted values.
+    pub line: u64,
+    /// The reason why the row failed to parse.
+    #[source]
+    pub source: InvalidRow,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum InvalidRow {
+    #[error("the row is malformed")]
+    Csv(#[from] csv::Error),
+    #[error("the secret is missing")]
+    MissingSecret,
+    #[error("the secret is not valid Base32")]
+    InvalidSecret(#[from] data_encoding::DecodeError),
+    #[error("the value `{1}` is not a valid {0}")]
+    InvalidValue(Field, String),
+}
+
+/// Accounts that were loaded from a CSV file, together with the rows that failed to load.
+pub struct Import {
+    pub accounts: Vec<otti_core::Account>,
+    pub errors: Vec<RowError>,
+}
+
+/// Account fields, that can be mapped to a CSV column.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Field {
+    Issuer,
+    Label,
+    Secret,
+    Digits,
+    Period,
+    Algorithm,
+    Type,
+    Counter,
+    Tags,
+}
+
+impl Field {
+    /// All fields, in the order they're written during export.
+    pub const ALL: [Self; 9] = [
+        Self::Issuer,
+        Self::Label,
+        Self::Secret,
+        Self::Digits,
+        Self::Period,
+        Self::Algorithm,
+        Self::Type,
+        Self::Counter,
+        Self::Tags,
+    ];
+
+    /// Name of the field, which is the default column name as well.
+    #[must_use]
+    pub const fn name(self) -> &'static str {
+        match self {
+            Self::Issuer => "issuer",
+            Self::Label => "label",
+            Self::Secret => "secret",
+            Self::Digits => "digits",
+            Self::Period => "period",
+            Self::Algorithm => "algorithm",
+            Self::Type => "type",
+            Self::Counter => "counter",
+            Self::Tags => "tags",
+        }
+    }
+}
+
+impl fmt::Display for Field {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+#[error(
+    "unknown field `{0}`, expected one of issuer, label, secret, digits, period, algorithm, type, \
+     counter or tags"
+)]
+pub struct UnknownField(String);
+
+impl FromStr for Field {
+    type Err = UnknownField;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .into_iter()
+            .find(|f| f.name().eq_ignore_ascii_case(s))
+            .ok_or_else(|| UnknownField(s.to_owned()))
+    }
+}
+
+/// Mapping from account fields to the column names in the CSV header. By default, each column is
+/// named after its [`Field`].
+#[derive(Clone, Debug)]
+pub struct Columns([String; Field::ALL.len()]);
+
+impl Default for Columns {
+    fn default() -> Self {
+        Self(Field::ALL.map(|f| f.name().to_owned()))
+    }
+}
+
+impl Columns {
+    /// Use a different column name for the given field.
+    pub fn set(&mut self, field: Field, name: impl Into<String>) {
+        self.0[field as usize] = name.into();
+    }
+
+    /// Get the column name of the given field.
+    #[must_use]
+    pub fn get(&self, field: Field) -> &str {
+        &self.0[field as usize]
+    }
+}
+
+/// Separator between multiple tags in the tags column.
+const TAG_SEPARATOR: char = ';';
+/// Default period of TOTPs, if not defined otherwise.
+const DEFAULT_PERIOD: u64 = 30;
+/// Default amount of digits for TOTPs and HOTPs.
+const DEFAULT_DIGITS: u8 = 6;
+/// Default amount of digits for Steam TOTPs.
+const DEFAULT_STEAM_DIGITS: u8 = 5;
+
+/// A single record, together with the positions of the mapped columns.
+struct Row<'a> {
+    record: &'a csv::StringRecord,
+    indices: &'a [Option<usize>; Field::ALL.len()],
+}
+
+impl Row<'_> {
+    /// Get the value of a field, if its column exists and the value isn't empty.
+    fn get(&self, field: Field) -> Option<&str> {
+        self.indices[field as usize]
+            .and_then(|i| self.record.get(i))
+            .filter(|v| !v.is_empty())
+    }
+
+    fn parse<T: FromStr>(&self, field: Field) -> Result<Option<T>, InvalidRow> {
+        self.get(field)
+            .map(|v| {
+                v.parse()
+                    .map_err(|_e| InvalidRow::InvalidValue(field, v.to_owned()))
+            })
+            .transpose()
+    }
+
+    fn into_account(self) -> Result<otti_core::Account, InvalidRow> {
+        let secret = otti_core::de::decode_secret(
+            self.get(Field::Secret).ok_or(InvalidRow::MissingSecret)?,
+        )?;
+        let period = self.parse(Field::Period)?.unwrap_or(DEFAULT_PERIOD);
+
+        let otp = match self.get(Field::Type) {
+            None => otti_core::Otp::Totp { window: period },
+            Some(ty) if ty.eq_ignore_ascii_case("totp") => otti_core::Otp::Totp { window: period },
+            Some(ty) if ty.eq_ignore_ascii_case("hotp") => otti_core::Otp::Hotp {
+                counter: self.parse(Field::Counter)?.unwrap_or_default(),
+            },
+            Some(ty) if ty.eq_ignore_ascii_case("steam") => otti_core::Otp::Steam { period },
+            Some(ty) => return Err(InvalidRow::InvalidValue(Field::Type, ty.to_owned())),
+        };
+
+        let digits = self.parse(Field::Digits)?.unwrap_or(match otp {
+            otti_core::Otp::Steam { .. } => DEFAULT_STEAM_DIGITS,
+            otti_core::Otp::Hotp { .. } | otti_core::Otp::Totp { .. } => DEFAULT_DIGITS,
+        });
+
+        let algorithm = match self.get(Field::Algorithm) {
+            None => otti_core::Algorithm::Sha1,
+            Some(a) if a.eq_ignore_ascii_case("sha1") => otti_core::Algorithm::Sha1,
+            Some(a) if a.eq_ignore_ascii_case("sha256") => otti_core::Algorithm::Sha256,
+            Some(a) if a.eq_ignore_ascii_case("sha512") => otti_core::Algorithm::Sha512,
+            Some(a) => return Err(InvalidRow::InvalidValue(Field::Algorithm, a.to_owned())),
+        };
+
+        Ok(otti_core::Account {
+            label: self.get(Field::Label).unwrap_or_default().to_owned(),
+            secret: Key::new(secret),
+            digits,
+            otp,
+            algorithm,
+            issuer: self.get(Field::Issuer).map(ToOwned::to_owned),
+            meta: otti_core::Metadata {
+                tags: self
+                    .get(Field::Tags)
+                    .into_iter()
+                    .flat_map(|t| t.split(TAG_SEPARATOR))
+                    .map(str::trim)
+                    .filter(|t| !t.is_empty())
+                    .map(ToOwned::to_owned)
+                    .collect(),
+            },
+            extras: BTreeMap::new(),
+        })
+    }
+}
+
+pub fn load(
+    data: &mut impl Buf,
+    password: Option<&SecretString>,
+    columns: &Columns,
+) -> Result<Import, Error> {
+    if password.is_some() {
+        return Err(Error::Encrypted);
+    }
+
+    let mut reader = csv::ReaderBuilder::new()
+        .trim(csv::Trim::All)
+        .from_reader(data.reader());
+
+    let headers = reader.headers()?.clone();
+    let indices = Field::ALL.map(|f| headers.iter().position(|h| h == columns.get(f)));
+
+    if indices[Field::Secret as usize].is_none() {
+        return Err(Error::MissingColumn(columns.get(Field::Secret).to_owned()));
+    }
+
+    let mut import = Import {
+        accounts: Vec::new(),
+        errors: Vec::new(),
+    };
+
+    let mut line = 1;
+
+    for record in reader.records() {
+        let position = match &record {
+            Ok(record) => record.position(),
+            Err(e) => e.position(),
+        };
+        line = position.map_or(line + 1, csv::Position::line);
+
+        let result = record.map_err(Into::into).and_then(|record| {
+            Row {
+                record: &record,
+                indices: &indices,
+            }
+            .into_account()
+        });
+
+        match result {
+            Ok(account) => import.accounts.push(account),
+            Err(source) => import.errors.push(RowError { line, source }),
+        }
+    }
+
+    Ok(import)
+}
+
+pub fn save(
+    buf: &mut impl BufMut,
+    data: &[otti_core::Account],
+    password: Option<&SecretString>,
+    columns: &Columns,
+) -> Result<(), Error> {
+    if password.is_some() {
+        return Err(Error::Encrypted);
+    }
+
+    let mut writer = csv::Writer::from_writer(buf.writer());
+    writer.write_record(Field::ALL.map(|f| columns.get(f)))?;
+
+    for a in data {
+        let (ty, period, counter) = match a.otp {
+            otti_core::Otp::Totp { window } => ("totp", window.to_string(), String::new()),
+            otti_core::Otp::Hotp { counter } => ("hotp", String::new(), counter.to_string()),
+            otti_core::Otp::Steam { period } => ("steam", period.to_string(), String::new()),
+        };
+
+        writer.write_record([
+            a.issuer.as_deref().unwrap_or_default(),
+            &a.label,
+            &data_encoding::BASE32_NOPAD.encode(a.secret.expose_secret()),
+            &a.digits.to_string(),
+            &period,
+            match a.algorithm {
+                otti_core::Algorithm::Sha1 => "SHA1",
+                otti_core::Algorithm::Sha256 => "SHA256",
+                otti_core::Algorithm::Sha512 => "SHA512",
+            },
+            ty,
+            &counter,
+            &a.meta.tags.join(&TAG_SEPARATOR.to_string()),
+        ])?;
+    }
+
+    writer.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    fn columns() -> Columns {
+        let mut columns = Columns::default();
+        columns.set(Field::Issuer, "Service");
+        columns.set(Field::Label, "Account");
+        columns.set(Field::Secret, "Seed");
+        columns.set(Field::Digits, "Digits");
+        columns.set(Field::Period, "Interval");
+        columns.set(Field::Algorithm, "Hash");
+        columns.set(Field::Type, "Kind");
+        columns.set(Field::Counter, "Counter");
+        columns.set(Field::Tags, "Groups");
+        columns
+    }
+
+    #[test]
+    fn roundtrip_plain() {
+        let file = include_bytes!("../import/accounts.csv");
+        let import = load(&mut &file[..], None, &columns()).unwrap();
+
+        let mut file = Vec::new();
+        save(&mut file, &import.accounts, None, &Columns::default()).unwrap();
+
+        let roundtrip = load(&mut file.as_slice(), None, &Columns::default()).unwrap();
+        assert_eq!(
+            import
+                .accounts
+                .iter()
+                .map(otti_core::Account::to_url)
+                .collect::<Vec<_>>(),
+            roundtrip
+                .accounts
+                .iter()
+                .map(otti_core::Account::to_url)
+                .collect::<Vec<_>>()
+        );
+        assert!(roundtrip.errors.is_empty());
+    }
+
+    #[test]
+    fn import_plain() {
+        let file = include_bytes!("../import/accounts.csv");
+        let import = load(&mut &file[..], None, &columns()).unwrap();
+
+        assert_eq!(3, import.accounts.len());
+        assert!(import
+            .accounts
+            .iter()
+            .all(|a| a.secret.expose_secret() == b"1234567890"));
+
+        assert_eq!(Some("Sample Issuer"), import.accounts[0].issuer.as_deref());
+        assert_eq!("Sample TOTP", import.accounts[0].label);
+        assert_eq!(8, import.accounts[0].digits);
+        assert!(matches!(
+            import.accounts[0].otp,
+            otti_core::Otp::Totp { window: 60 }
+        ));
+        assert!(matches!(
+            import.accounts[0].algorithm,
+            otti_core::Algorithm::Sha256
+        ));
+        assert_eq!(
+            vec!["Work".to_owned(), "Servers".to_owned()],
+            import.accounts[0].meta.tags
+        );
+
+        assert_eq!(6, import.accounts[1].digits);
+        assert!(matches!(
+            import.accounts[1].otp,
+            otti_core::Otp::Hotp { counter: 5 }
+        ));
+
+        assert_eq!(5, import.accounts[2].digits);
+        assert!(matches!(
+            import.accounts[2].otp,
+            otti_core::Otp::Steam { period: 30 }
+        ));
+
+        assert_eq!(
+            vec![5, 6, 7],
+            import.errors.iter().map(|e| e.line).collect::<Vec<_>>()
+        );
+        assert!(matches!(
+            import.errors[0].source,
+            InvalidRow::InvalidSecret(_)
+        ));
+        assert!(matches!(
+            import.errors[1].source,
+            InvalidRow::InvalidValue(Field::Digits, _)
+        ));
+        assert!(matches!(
+            import.errors[2].source,
+            InvalidRow::InvalidValue(Field::Type, _)
+        ));
+    }
+
+    #[test]
+    fn report_lines_of_invalid_rows() {
+        let file = b"issuer,label,secret\n\nFirst,\"Multi\nLine\",GEZDGNBVGY3TQOJQ\n\
+                     Second,Bad Secret,not-base32!\nThird,Too Short\n";
+        let import = load(&mut &file[..], None, &Columns::default()).unwrap();
+
+        assert_eq!(1, import.accounts.len());
+        assert_eq!("Multi\nLine", import.accounts[0].label);
+        assert_eq!(
+            vec![5, 6],
+            import.errors.iter().map(|e| e.line).collect::<Vec<_>>()
+        );
+        assert!(matches!(
+            import.errors[0].source,
+            InvalidRow::InvalidSecret(_)
+        ));
+        assert!(matches!(import.errors[1].source, InvalidRow::Csv(_)));
+    }
+
+    #[test]
+    fn import_missing_secret_column() {
+        let file = b"issuer,label\nSample Issuer,Sample TOTP\n";
+        let result = load(&mut &file[..], None, &Columns::default());
+
+        assert!(matches!(result, Err(Error::MissingColumn(c)) if c == "secret"));
+    }
+
+    #[test]
+    fn export_plain() {
+        let mut export = Vec::new();
+        let data = [
+            otti_core::Account {
+                label: "Entry 1".to_owned(),
+                secret: Key::new(vec![0; 10]),
+                digits: 6,
+                otp: otti_core::Otp::Totp { window: 30 },
+                algorithm: otti_core::Algorithm::Sha1,
+                issuer: Some("Provider 1".to_owned()),
+                meta: otti_core::Metadata {
+                    tags: vec!["Tag 1".to_owned(), "Tag 2".to_owned()],
+                },
+                extras: BTreeMap::new(),
+            },
+            otti_core::Account {
+                label: "Entry 2".to_owned(),
+                secret: Key::new(vec![0; 10]),
+                digits: 8,
+                otp: otti_core::Otp::Hotp { counter: 3 },
+                algorithm: otti_core::Algorithm::Sha256,
+                issuer: None,
+                meta: otti_core::Metadata::default(),
+                extras: BTreeMap::new(),
+            },
+        ];
+
+        save(&mut export, &data, None, &Columns::default()).unwrap();
+
+        let expected = "issuer,label,secret,digits,period,algorithm,type,counter,tags\nProvider \
+                        1,Entry 1,AAAAAAAAAAAAAAAA,6,30,SHA1,totp,,Tag 1;Tag 2\n,Entry \
+                        2,AAAAAAAAAAAAAAAA,8,,SHA256,hotp,3,\n";
+
+        assert_eq!(expected, String::from_utf8(export).unwrap());
+    }
+}
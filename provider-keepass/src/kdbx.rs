@@ -0,0 +1,509 @@
+//! Decryption of the binary KDBX4 container, that wraps the actual XML database.
+//!
+//! The layout is described in detail in the
+//! [KeePass documentation](https://keepass.info/help/kb/kdbx_4.html).
+
+use std::{collections::BTreeMap, io::Read};
+
+use aes::{
+    cipher::{
+        block_padding::Pkcs7, generic_array::GenericArray, BlockDecryptMut, BlockEncrypt, KeyInit,
+        KeyIvInit, StreamCipher,
+    },
+    Aes256,
+};
+use bytes::Buf;
+use chacha20::ChaCha20;
+use flate2::read::GzDecoder;
+use hmac::{Hmac, Mac};
+use sha2::{Digest, Sha256, Sha512};
+
+use crate::Error;
+
+const SIGNATURE_1: u32 = 0x9AA2_D903;
+const SIGNATURE_2: u32 = 0xB54B_FB67;
+/// The only major version of the format that is supported.
+const MAJOR_VERSION: u16 = 4;
+
+const CIPHER_AES256: [u8; 16] = uuid(0x31c1_f2e6_bf71_4350_be58_0521_6afc_5aff);
+const CIPHER_CHACHA20: [u8; 16] = uuid(0xd603_8a2b_8b6f_4cb5_a524_339a_31db_b59a);
+
+const KDF_AES: [u8; 16] = uuid(0xc9d9_f39a_628a_4460_bf74_0d08_c18a_4fea);
+const KDF_ARGON2D: [u8; 16] = uuid(0xef63_6ddf_8c29_444b_91f7_a9a4_03e3_0a0c);
+const KDF_ARGON2ID: [u8; 16] = uuid(0x9e29_8b19_56db_4773_b23d_fc3e_c6f0_a1e6);
+
+const HEADER_END: u8 = 0;
+const HEADER_CIPHER_ID: u8 = 2;
+const HEADER_COMPRESSION: u8 = 3;
+const HEADER_MASTER_SEED: u8 = 4;
+const HEADER_ENCRYPTION_IV: u8 = 7;
+const HEADER_KDF_PARAMETERS: u8 = 11;
+
+const INNER_HEADER_END: u8 = 0;
+const INNER_HEADER_STREAM_ID: u8 = 1;
+const INNER_HEADER_STREAM_KEY: u8 = 2;
+
+/// Identifier of the only supported inner stream cipher, that protects single values inside the
+/// XML database.
+const INNER_STREAM_CHACHA20: u32 = 3;
+
+const fn uuid(value: u128) -> [u8; 16] {
+    value.to_be_bytes()
+}
+
+/// Decrypted content of a KDBX file.
+pub struct Database {
+    /// The raw XML document.
+    pub xml: Vec<u8>,
+    /// Cipher stream to reveal protected values of the XML document.
+    pub stream: ProtectedStream,
+}
+
+/// Stream cipher, that is used to hide protected values (like passwords) inside the XML document.
+/// All values must be revealed in the order they appear in the document, as each one advances the
+/// key stream.
+pub struct ProtectedStream(ChaCha20);
+
+impl ProtectedStream {
+    fn new(key: &[u8]) -> Self {
+        let hash = Sha512::digest(key);
+        Self(ChaCha20::new(
+            GenericArray::from_slice(&hash[..32]),
+            GenericArray::from_slice(&hash[32..44]),
+        ))
+    }
+
+    pub fn reveal(&mut self, data: &mut [u8]) {
+        self.0.apply_keystream(data);
+    }
+}
+
+/// Create the composite key from a password and key file, which is the base for all further key
+/// derivation.
+pub fn composite_key(password: Option<&[u8]>, key_file: Option<&[u8]>) -> Result<[u8; 32], Error> {
+    if password.is_none() && key_file.is_none() {
+        return Err(Error::MissingCredentials);
+    }
+
+    let mut hasher = Sha256::new();
+
+    if let Some(password) = password {
+        hasher.update(Sha256::digest(password));
+    }
+    if let Some(key_file) = key_file {
+        hasher.update(key_file_key(key_file)?);
+    }
+
+    Ok(hasher.finalize().into())
+}
+
+/// Extract the key from any of the supported key file formats. These are the XML formats in
+/// version 1.0 and 2.0, raw 32 bytes, 64 hex characters or any other file, which is hashed.
+fn key_file_key(data: &[u8]) -> Result<[u8; 32], Error> {
+    if let Some(key) = crate::xml::parse_key_file(data)? {
+        return key.try_into().map_err(|_e| Error::InvalidKeyFile);
+    }
+
+    if data.len() == 32 {
+        let mut key = [0; 32];
+        key.copy_from_slice(data);
+        return Ok(key);
+    }
+
+    if data.len() == 64 {
+        let mut key = [0; 32];
+        if hex::decode_to_slice(data, &mut key).is_ok() {
+            return Ok(key);
+        }
+    }
+
+    Ok(Sha256::digest(data).into())
+}
+
+/// Open a KDBX4 file with the given composite key (see [`composite_key`]).
+pub fn open(data: &[u8], key: &[u8; 32]) -> Result<Database, Error> {
+    let mut rd = data;
+
+    if rd.remaining() < 12 {
+        return Err(Error::InputTooShort);
+    }
+
+    if rd.get_u32_le() != SIGNATURE_1 || rd.get_u32_le() != SIGNATURE_2 {
+        return Err(Error::InvalidSignature);
+    }
+
+    let _minor = rd.get_u16_le();
+    let major = rd.get_u16_le();
+    if major != MAJOR_VERSION {
+        return Err(Error::UnsupportedVersion(major));
+    }
+
+    let header = OuterHeader::read(&mut rd)?;
+    let header_data = &data[..data.len() - rd.remaining()];
+
+    if rd.remaining() < 64 {
+        return Err(Error::InputTooShort);
+    }
+
+    let header_hash = rd.copy_to_bytes(32);
+    let header_hmac = rd.copy_to_bytes(32);
+
+    if Sha256::digest(header_data).as_slice() != header_hash {
+        return Err(Error::CorruptedHeader);
+    }
+
+    let transformed = header.kdf.transform(key)?;
+
+    let master_key = Sha256::new()
+        .chain_update(&header.master_seed)
+        .chain_update(transformed)
+        .finalize();
+    let hmac_key = Sha512::new()
+        .chain_update(&header.master_seed)
+        .chain_update(transformed)
+        .chain_update([1])
+        .finalize();
+
+    let mut mac = block_hmac(&hmac_key, u64::MAX);
+    mac.update(header_data);
+    mac.verify_slice(&header_hmac)
+        .map_err(|_e| Error::InvalidCredentials)?;
+
+    let payload = read_blocks(&mut rd, &hmac_key)?;
+    let payload = match header.cipher {
+        CIPHER_AES256 => <cbc::Decryptor<Aes256>>::new_from_slices(&master_key, &header.iv)
+            .map_err(|_e| Error::InvalidHeader)?
+            .decrypt_padded_vec_mut::<Pkcs7>(&payload)?,
+        CIPHER_CHACHA20 => {
+            let mut payload = payload;
+            ChaCha20::new_from_slices(&master_key, &header.iv)
+                .map_err(|_e| Error::InvalidHeader)?
+                .apply_keystream(&mut payload);
+            payload
+        }
+        _ => return Err(Error::UnsupportedCipher),
+    };
+
+    let payload = if header.compressed {
+        let mut buf = Vec::new();
+        GzDecoder::new(payload.as_slice()).read_to_end(&mut buf)?;
+        buf
+    } else {
+        payload
+    };
+
+    let mut rd = payload.as_slice();
+    let mut stream = None;
+    let mut stream_key = None;
+
+    loop {
+        let (id, value) = read_field(&mut rd)?;
+        match id {
+            INNER_HEADER_END => break,
+            INNER_HEADER_STREAM_ID => stream = Some(read_u32(value)?),
+            INNER_HEADER_STREAM_KEY => stream_key = Some(value),
+            _ => {}
+        }
+    }
+
+    if stream != Some(INNER_STREAM_CHACHA20) {
+        return Err(Error::UnsupportedInnerStream);
+    }
+
+    Ok(Database {
+        xml: rd.to_vec(),
+        stream: ProtectedStream::new(stream_key.ok_or(Error::InvalidHeader)?),
+    })
+}
+
+struct OuterHeader {
+    cipher: [u8; 16],
+    compressed: bool,
+    master_seed: Vec<u8>,
+    iv: Vec<u8>,
+    kdf: Kdf,
+}
+
+impl OuterHeader {
+    fn read(rd: &mut &[u8]) -> Result<Self, Error> {
+        let mut cipher = None;
+        let mut compressed = false;
+        let mut master_seed = None;
+        let mut iv = None;
+        let mut kdf = None;
+
+        loop {
+            let (id, value) = read_field(rd)?;
+            match id {
+                HEADER_END => break,
+                HEADER_CIPHER_ID => {
+                    cipher = Some(value.try_into().map_err(|_e| Error::InvalidHeader)?);
+                }
+                HEADER_COMPRESSION => compressed = read_u32(value)? == 1,
+                HEADER_MASTER_SEED => master_seed = Some(value.to_vec()),
+                HEADER_ENCRYPTION_IV => iv = Some(value.to_vec()),
+                HEADER_KDF_PARAMETERS => kdf = Some(Kdf::read(value)?),
+                _ => {}
+            }
+        }
+
+        Ok(Self {
+            cipher: cipher.ok_or(Error::InvalidHeader)?,
+            compressed,
+            master_seed: master_seed.ok_or(Error::InvalidHeader)?,
+            iv: iv.ok_or(Error::InvalidHeader)?,
+            kdf: kdf.ok_or(Error::InvalidHeader)?,
+        })
+    }
+}
+
+/// Key derivation function, that transforms the composite key into the master key.
+enum Kdf {
+    Aes {
+        rounds: u64,
+        seed: Vec<u8>,
+    },
+    Argon2 {
+        algorithm: argon2::Algorithm,
+        version: argon2::Version,
+        salt: Vec<u8>,
+        iterations: u32,
+        memory: u32,
+        parallelism: u32,
+    },
+}
+
+impl Kdf {
+    fn read(data: &[u8]) -> Result<Self, Error> {
+        let params = read_variant_dictionary(data)?;
+        let get = |key: &str| params.get(key).ok_or(Error::InvalidHeader);
+        let get_u32 = |key: &str| get(key).and_then(|v| read_u32(v));
+        let get_u64 = |key: &str| get(key).and_then(|v| read_u64(v));
+
+        let uuid: [u8; 16] = get("$UUID")?
+            .as_slice()
+            .try_into()
+            .map_err(|_e| Error::InvalidHeader)?;
+
+        let algorithm = match uuid {
+            KDF_AES => {
+                return Ok(Self::Aes {
+                    rounds: get_u64("R")?,
+                    seed: get("S")?.clone(),
+                })
+            }
+            KDF_ARGON2D => argon2::Algorithm::Argon2d,
+            KDF_ARGON2ID => argon2::Algorithm::Argon2id,
+            _ => return Err(Error::UnsupportedKdf),
+        };
+
+        let version = match get_u32("V")? {
+            0x10 => argon2::Version::V0x10,
+            0x13 => argon2::Version::V0x13,
+            _ => return Err(Error::UnsupportedKdf),
+        };
+
+        Ok(Self::Argon2 {
+            algorithm,
+            version,
+            salt: get("S")?.clone(),
+            iterations: get_u64("I")?
+                .try_into()
+                .map_err(|_e| Error::InvalidHeader)?,
+            memory: (get_u64("M")? / 1024)
+                .try_into()
+                .map_err(|_e| Error::InvalidHeader)?,
+            parallelism: get_u32("P")?,
+        })
+    }
+
+    fn transform(&self, key: &[u8; 32]) -> Result<[u8; 32], Error> {
+        let mut output = [0; 32];
+
+        match self {
+            Self::Aes { rounds, seed } => {
+                let cipher = Aes256::new_from_slice(seed).map_err(|_e| Error::InvalidHeader)?;
+                output.copy_from_slice(key);
+
+                for _ in 0..*rounds {
+                    for block in output.chunks_exact_mut(16) {
+                        cipher.encrypt_block(GenericArray::from_mut_slice(block));
+                    }
+                }
+
+                output = Sha256::digest(output).into();
+            }
+            Self::Argon2 {
+                algorithm,
+                version,
+                salt,
+                iterations,
+                memory,
+                parallelism,
+            } => {
+                argon2::Argon2::new(
+                    *algorithm,
+                    *version,
+                    argon2::Params::new(*memory, *iterations, *parallelism, Some(32))?,
+                )
+                .hash_password_into(key, salt, &mut output)?;
+            }
+        }
+
+        Ok(output)
+    }
+}
+
+/// Read the key-value map that KDBX4 uses to describe the KDF parameters. Only the raw bytes are
+/// kept, as the meaning of each value is defined by its key.
+fn read_variant_dictionary(mut data: &[u8]) -> Result<BTreeMap<String, Vec<u8>>, Error> {
+    if data.remaining() < 2 || data.get_u16_le() & 0xff00 != 0x0100 {
+        return Err(Error::InvalidHeader);
+    }
+
+    let mut map = BTreeMap::new();
+
+    loop {
+        if !data.has_remaining() {
+            return Err(Error::InvalidHeader);
+        }
+        if data.get_u8() == 0 {
+            break;
+        }
+
+        let key = read_sized(&mut data)?;
+        let value = read_sized(&mut data)?;
+
+        map.insert(
+            String::from_utf8(key.to_vec()).map_err(|_e| Error::InvalidHeader)?,
+            value.to_vec(),
+        );
+    }
+
+    Ok(map)
+}
+
+/// Read all blocks of the HMAC protected block stream, verifying each block's integrity.
+fn read_blocks(rd: &mut &[u8], hmac_key: &[u8]) -> Result<Vec<u8>, Error> {
+    let mut payload = Vec::new();
+
+    for index in 0.. {
+        if rd.remaining() < 32 {
+            return Err(Error::InputTooShort);
+        }
+
+        let hmac = rd.copy_to_bytes(32);
+        let block = read_sized(rd)?;
+
+        let mut mac = block_hmac(hmac_key, index);
+        mac.update(&index.to_le_bytes());
+        mac.update(&(block.len() as u32).to_le_bytes());
+        mac.update(block);
+        mac.verify_slice(&hmac)
+            .map_err(|_e| Error::CorruptedBlock(index))?;
+
+        if block.is_empty() {
+            break;
+        }
+
+        payload.extend_from_slice(block);
+    }
+
+    Ok(payload)
+}
+
+/// Create the HMAC instance for the block at `index`, using the block specific key. The header
+/// uses the special index [`u64::MAX`].
+fn block_hmac(hmac_key: &[u8], index: u64) -> Hmac<Sha256> {
+    let key = Sha512::new()
+        .chain_update(index.to_le_bytes())
+        .chain_update(hmac_key)
+        .finalize();
+
+    <Hmac<Sha256> as Mac>::new_from_slice(&key).expect("HMAC accepts any key size")
+}
+
+/// Read a single header field, consisting of a 1-byte ID and a size prefixed value.
+fn read_field<'a>(rd: &mut &'a [u8]) -> Result<(u8, &'a [u8]), Error> {
+    if !rd.has_remaining() {
+        return Err(Error::InputTooShort);
+    }
+
+    let id = rd.get_u8();
+    let value = read_sized(rd)?;
+
+    Ok((id, value))
+}
+
+/// Read a value that is prefixed with its size as 32-bit integer.
+fn read_sized<'a>(rd: &mut &'a [u8]) -> Result<&'a [u8], Error> {
+    if rd.remaining() < 4 {
+        return Err(Error::InputTooShort);
+    }
+
+    let size = rd.get_u32_le() as usize;
+    if rd.remaining() < size {
+        return Err(Error::InputTooShort);
+    }
+
+    let (value, rest) = rd.split_at(size);
+    *rd = rest;
+
+    Ok(value)
+}
+
+fn read_u32(value: &[u8]) -> Result<u32, Error> {
+    value
+        .try_into()
+        .map(u32::from_le_bytes)
+        .map_err(|_e| Error::InvalidHeader)
+}
+
+fn read_u64(value: &[u8]) -> Result<u64, Error> {
+    value
+        .try_into()
+        .map(u64::from_le_bytes)
+        .map_err(|_e| Error::InvalidHeader)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Argon2id parameters in the variant dictionary format, with the given version.
+    fn argon2_params(version: u32) -> Vec<u8> {
+        let mut data = vec![0x00, 0x01];
+        let mut entry = |kind: u8, key: &str, value: &[u8]| {
+            data.push(kind);
+            data.extend_from_slice(&(key.len() as u32).to_le_bytes());
+            data.extend_from_slice(key.as_bytes());
+            data.extend_from_slice(&(value.len() as u32).to_le_bytes());
+            data.extend_from_slice(value);
+        };
+
+        entry(0x42, "$UUID", &KDF_ARGON2ID);
+        entry(0x42, "S", &[7; 32]);
+        entry(0x05, "I", &1_u64.to_le_bytes());
+        entry(0x05, "M", &(64 * 1024_u64).to_le_bytes());
+        entry(0x04, "P", &1_u32.to_le_bytes());
+        entry(0x04, "V", &version.to_le_bytes());
+        data.push(0);
+
+        data
+    }
+
+    #[test]
+    fn read_argon2_version() {
+        let transform = |version| {
+            let kdf = Kdf::read(&argon2_params(version)).unwrap();
+            assert!(matches!(&kdf, Kdf::Argon2 { version: v, .. } if *v as u32 == version));
+            kdf.transform(&[1; 32]).unwrap()
+        };
+
+        assert_ne!(transform(0x10), transform(0x13));
+        assert!(matches!(
+            Kdf::read(&argon2_params(0x12)),
+            Err(Error::UnsupportedKdf)
+        ));
+    }
+}
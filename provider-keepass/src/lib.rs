@@ -0,0 +1,259 @@
+//! # Otti - Provider `KeePass`
+//!
+//! Import component that allows to transform entries with TOTP data from a
+//! [`KeePass`](https://keepass.info) KDBX4 database into Otti accounts.
+//!
+//! TOTP data is taken from the `otp` attribute (as `otpauth://` URL), which is used by
+//! [`KeePassXC`](https://keepassxc.org), or the legacy `TOTP Seed` and `TOTP Settings` attribute
+//! pair.
+
+#![deny(rust_2018_idioms, clippy::all, clippy::pedantic)]
+#![allow(clippy::missing_errors_doc, clippy::cast_possible_truncation)]
+
+use std::collections::BTreeMap;
+
+pub use bytes::Buf;
+use otti_core::{ExposeSecret, Key, SecretString};
+
+mod kdbx;
+mod xml;
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("the import data is too short")]
+    InputTooShort,
+    #[error("the file is not a KeePass database")]
+    InvalidSignature,
+    #[error("KDBX version {0} is not supported, only version 4")]
+    UnsupportedVersion(u16),
+    #[error("the database header is invalid")]
+    InvalidHeader,
+    #[error("the database header is corrupted")]
+    CorruptedHeader,
+    #[error("the data block {0} is corrupted")]
+    CorruptedBlock(u64),
+    #[error("the database cipher is not supported")]
+    UnsupportedCipher,
+    #[error("the key derivation function is not supported")]
+    UnsupportedKdf,
+    #[error("the inner stream cipher is not supported")]
+    UnsupportedInnerStream,
+    #[error("either a password or a key file is required to open the database")]
+    MissingCredentials,
+    #[error("the password or key file is invalid")]
+    InvalidCredentials,
+    #[error("the key file is invalid")]
+    InvalidKeyFile,
+    #[error("key derivation failed")]
+    Argon2(#[from] argon2::Error),
+    #[error("data decryption failed")]
+    Aes(#[from] block_padding::UnpadError),
+    #[error("I/O bound error")]
+    Io(#[from] std::io::Error),
+    #[error("XML deserialization failed")]
+    Xml(#[from] quick_xml::Error),
+    #[error("protected value is not valid Base64")]
+    Base64(#[from] base64::DecodeError),
+    #[error("protected value is not valid UTF-8")]
+    InvalidProtectedValue,
+    #[error("the TOTP seed of entry `{0}` is not valid Base32")]
+    InvalidSecret(String, #[source] data_encoding::DecodeError),
+    #[error("the TOTP settings `{1}` of entry `{0}` are invalid")]
+    InvalidSettings(String, String),
+    #[error("the TOTP URL of entry `{0}` is not valid")]
+    InvalidUrl(String, #[source] otti_core::ParseError),
+}
+
+const FIELD_TITLE: &str = "Title";
+const FIELD_USERNAME: &str = "UserName";
+const FIELD_OTP: &str = "otp";
+const FIELD_TOTP_SEED: &str = "TOTP Seed";
+const FIELD_TOTP_SETTINGS: &str = "TOTP Settings";
+
+/// Settings that are assumed, if the `TOTP Settings` attribute is missing.
+const DEFAULT_SETTINGS: &str = "30;6";
+/// Marker in place of the digits in the `TOTP Settings`, to signal a Steam OTP.
+const STEAM_MARKER: &str = "S";
+/// Default amount of digits for Steam TOTPs.
+const DEFAULT_STEAM_DIGITS: u8 = 5;
+
+impl xml::Entry {
+    fn into_account(mut self) -> Result<Option<otti_core::Account>, Error> {
+        let title = self.fields.remove(FIELD_TITLE).unwrap_or_default();
+
+        let mut account = if let Some(url) = self.fields.get(FIELD_OTP) {
+            url.trim()
+                .parse::<otti_core::Account>()
+                .map_err(|e| Error::InvalidUrl(title.clone(), e))?
+        } else if let Some(seed) = self.fields.get(FIELD_TOTP_SEED) {
+            let settings = self
+                .fields
+                .get(FIELD_TOTP_SETTINGS)
+                .map_or(DEFAULT_SETTINGS, String::as_str);
+
+            parse_legacy(&title, seed, settings)?
+        } else {
+            return Ok(None);
+        };
+
+        if let Some(username) = self.fields.remove(FIELD_USERNAME).filter(|u| !u.is_empty()) {
+            account.label = username;
+        }
+        if !title.is_empty() {
+            account.issuer = Some(title);
+        }
+
+        // The first group is the root group, named after the database itself.
+        if self.groups.len() > 1 {
+            account.meta.tags.push(self.groups[1..].join("/"));
+        }
+
+        Ok(Some(account))
+    }
+}
+
+/// Parse the legacy TOTP format, where the secret and its settings are kept in separate
+/// attributes. The settings are in the form `<period>;<digits>`, where the digits can be replaced
+/// with `S` to mark a Steam OTP.
+fn parse_legacy(title: &str, seed: &str, settings: &str) -> Result<otti_core::Account, Error> {
+    let invalid_settings = || Error::InvalidSettings(title.to_owned(), settings.to_owned());
+
+    let mut parts = settings.split(';').map(str::trim);
+    let period = parts
+        .next()
+        .and_then(|p| p.parse().ok())
+        .ok_or_else(invalid_settings)?;
+
+    let (otp, digits) = match parts.next() {
+        Some(STEAM_MARKER) => (otti_core::Otp::Steam { period }, DEFAULT_STEAM_DIGITS),
+        Some(digits) => (
+            otti_core::Otp::Totp { window: period },
+            digits.parse().map_err(|_e| invalid_settings())?,
+        ),
+        None => return Err(invalid_settings()),
+    };
+
+    let secret = otti_core::de::decode_secret(seed)
+        .map_err(|e| Error::InvalidSecret(title.to_owned(), e))?;
+
+    Ok(otti_core::Account {
+        label: String::new(),
+        secret: Key::new(secret),
+        digits,
+        otp,
+        algorithm: otti_core::Algorithm::Sha1,
+        issuer: None,
+        meta: otti_core::Metadata::default(),
+        extras: BTreeMap::new(),
+    })
+}
+
+/// Load all entries with TOTP data from a KDBX4 database. The database can be protected by a
+/// password, a key file or both.
+pub fn load(
+    data: &mut impl Buf,
+    password: Option<&SecretString>,
+    key_file: Option<&[u8]>,
+) -> Result<Vec<otti_core::Account>, Error> {
+    let key = kdbx::composite_key(password.map(|p| p.expose_secret().as_bytes()), key_file)?;
+    let data = data.copy_to_bytes(data.remaining());
+    let mut database = kdbx::open(&data, &key)?;
+
+    xml::parse_entries(&database.xml, &mut database.stream)?
+        .into_iter()
+        .filter_map(|entry| entry.into_account().transpose())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use otti_core::ExposeSecret;
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    #[test]
+    fn import_password() {
+        let file = include_bytes!("../import/database.kdbx");
+        let accounts = load(
+            &mut &file[..],
+            Some(&SecretString::new("123".to_owned())),
+            None,
+        )
+        .unwrap();
+
+        assert_eq!(3, accounts.len());
+        assert!(accounts
+            .iter()
+            .all(|a| a.secret.expose_secret() == b"1234567890"));
+
+        assert_eq!(Some("Sample TOTP"), accounts[0].issuer.as_deref());
+        assert_eq!("user@example.com", accounts[0].label);
+        assert_eq!(8, accounts[0].digits);
+        assert!(matches!(
+            accounts[0].otp,
+            otti_core::Otp::Totp { window: 60 }
+        ));
+        assert!(accounts[0].meta.tags.is_empty());
+
+        assert_eq!(Some("Sample Steam"), accounts[1].issuer.as_deref());
+        assert_eq!(5, accounts[1].digits);
+        assert!(matches!(
+            accounts[1].otp,
+            otti_core::Otp::Steam { period: 30 }
+        ));
+        assert_eq!(vec!["Work".to_owned()], accounts[1].meta.tags);
+
+        assert_eq!(Some("Sample Legacy"), accounts[2].issuer.as_deref());
+        assert_eq!("legacy", accounts[2].label);
+        assert_eq!(6, accounts[2].digits);
+        assert!(matches!(
+            accounts[2].otp,
+            otti_core::Otp::Totp { window: 30 }
+        ));
+        assert_eq!(vec!["Work/Servers".to_owned()], accounts[2].meta.tags);
+    }
+
+    #[test]
+    fn skip_recycle_bin() {
+        let file = include_bytes!("../import/database-recycle-bin.kdbx");
+        let accounts = load(
+            &mut &file[..],
+            Some(&SecretString::new("123".to_owned())),
+            None,
+        )
+        .unwrap();
+
+        let issuers = accounts
+            .iter()
+            .map(|a| a.issuer.as_deref())
+            .collect::<Vec<_>>();
+        assert_eq!(vec![Some("Live Before"), Some("Live After")], issuers);
+    }
+
+    #[test]
+    fn import_key_file() {
+        let file = include_bytes!("../import/database-keyfile.kdbx");
+        let key_file = include_bytes!("../import/database.keyx");
+        let accounts = load(
+            &mut &file[..],
+            Some(&SecretString::new("123".to_owned())),
+            Some(key_file),
+        )
+        .unwrap();
+
+        assert_eq!(3, accounts.len());
+    }
+
+    #[test]
+    fn import_invalid_password() {
+        let file = include_bytes!("../import/database.kdbx");
+        let result = load(
+            &mut &file[..],
+            Some(&SecretString::new("abc".to_owned())),
+            None,
+        );
+
+        assert!(matches!(result, Err(Error::InvalidCredentials)));
+    }
+}
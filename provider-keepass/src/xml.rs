@@ -0,0 +1,216 @@
+//! Parsing of the XML documents that `KeePass` uses for the database content and key files.
+
+use std::collections::BTreeMap;
+
+use base64::engine::{general_purpose, Engine};
+use quick_xml::{events::Event, Reader};
+
+use crate::{kdbx::ProtectedStream, Error};
+
+/// A single entry of the database with all its string fields.
+pub struct Entry {
+    /// Names of all groups, from the root group down to the group that contains this entry.
+    pub groups: Vec<String>,
+    /// String fields like the title, username or any custom attributes.
+    pub fields: BTreeMap<String, String>,
+}
+
+/// Parse all entries from the database XML document. Entries inside the history of other entries
+/// or in the recycle bin are skipped, but their protected values are still revealed to keep the
+/// cipher stream in sync.
+pub fn parse_entries(xml: &[u8], stream: &mut ProtectedStream) -> Result<Vec<Entry>, Error> {
+    let mut reader = Reader::from_reader(xml);
+    let mut buf = Vec::new();
+
+    let mut path = Vec::<Vec<u8>>::new();
+    let mut groups = Vec::<String>::new();
+    let mut history = 0_usize;
+    let mut recycle_bin = RecycleBin::new();
+
+    let mut entries = Vec::new();
+    let mut entry = None::<Entry>;
+    let mut key = String::new();
+    let mut value = String::new();
+    let mut protected = false;
+
+    loop {
+        let event = reader.read_event_into(&mut buf)?;
+        let (start, end) = match &event {
+            Event::Start(e) => (Some(e.clone()), None),
+            Event::Empty(e) => (Some(e.clone()), Some(e.name().as_ref().to_vec())),
+            Event::End(e) => (None, Some(e.name().as_ref().to_vec())),
+            Event::Text(e) => {
+                let text = e.unescape()?;
+                match path.last().map(Vec::as_slice) {
+                    Some(b"Name") if parent(&path) == Some(b"Group") => {
+                        if let Some(name) = groups.last_mut() {
+                            name.push_str(&text);
+                        }
+                    }
+                    Some(b"Key") if parent(&path) == Some(b"String") => key.push_str(&text),
+                    Some(b"Value") if parent(&path) == Some(b"String") => value.push_str(&text),
+                    _ => recycle_bin.text(&path, &text),
+                }
+                (None, None)
+            }
+            Event::Eof => break,
+            _ => (None, None),
+        };
+
+        if let Some(start) = start {
+            match start.name().as_ref() {
+                b"Group" => {
+                    groups.push(String::new());
+                    recycle_bin.groups.push(false);
+                }
+                b"History" => history += 1,
+                b"Entry" if history == 0 => {
+                    entry = Some(Entry {
+                        groups: groups.clone(),
+                        fields: BTreeMap::new(),
+                    });
+                }
+                b"String" => {
+                    key.clear();
+                    value.clear();
+                }
+                b"Value" => {
+                    protected = start
+                        .try_get_attribute("Protected")?
+                        .is_some_and(|a| a.value.eq_ignore_ascii_case(b"true"));
+                }
+                _ => {}
+            }
+
+            path.push(start.name().as_ref().to_vec());
+        }
+
+        if let Some(end) = end {
+            path.pop();
+
+            match end.as_slice() {
+                b"Group" => {
+                    groups.pop();
+                    recycle_bin.groups.pop();
+                }
+                b"History" => history = history.saturating_sub(1),
+                b"Entry" if history == 0 => {
+                    let entry = entry.take();
+                    if !recycle_bin.is_open() {
+                        entries.extend(entry);
+                    }
+                }
+                b"Value" if protected => {
+                    let mut data = general_purpose::STANDARD.decode(value.trim())?;
+                    stream.reveal(&mut data);
+                    value = String::from_utf8(data).map_err(|_e| Error::InvalidProtectedValue)?;
+                    protected = false;
+                }
+                b"String" if history == 0 => {
+                    if let Some(entry) = &mut entry {
+                        entry
+                            .fields
+                            .insert(std::mem::take(&mut key), std::mem::take(&mut value));
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        buf.clear();
+    }
+
+    Ok(entries)
+}
+
+/// Location of the recycle bin, which is identified in the metadata before any groups appear.
+/// Each group is marked, once its UUID turns out to be the recycle bin's.
+struct RecycleBin {
+    uuid: String,
+    enabled: bool,
+    /// Whether each of the currently open groups is the recycle bin.
+    groups: Vec<bool>,
+}
+
+impl RecycleBin {
+    fn new() -> Self {
+        Self {
+            uuid: String::new(),
+            enabled: true,
+            groups: Vec::new(),
+        }
+    }
+
+    /// Handle the text of an element, which may identify the recycle bin.
+    fn text(&mut self, path: &[Vec<u8>], text: &str) {
+        match (path.last().map(Vec::as_slice), parent(path)) {
+            (Some(b"RecycleBinUUID"), Some(b"Meta")) => self.uuid.push_str(text),
+            (Some(b"RecycleBinEnabled"), Some(b"Meta")) => {
+                self.enabled = !text.trim().eq_ignore_ascii_case("false");
+            }
+            (Some(b"UUID"), Some(b"Group")) if self.enabled && text.trim() == self.uuid.trim() => {
+                if let Some(group) = self.groups.last_mut() {
+                    *group = true;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Whether the recycle bin is one of the currently open groups.
+    fn is_open(&self) -> bool {
+        self.groups.contains(&true)
+    }
+}
+
+fn parent(path: &[Vec<u8>]) -> Option<&[u8]> {
+    path.len()
+        .checked_sub(2)
+        .and_then(|i| path.get(i))
+        .map(Vec::as_slice)
+}
+
+/// Try to parse the key from an XML key file. Returns `None` if the data doesn't look like an XML
+/// key file at all, so other formats can be tried.
+pub fn parse_key_file(data: &[u8]) -> Result<Option<Vec<u8>>, Error> {
+    let content = String::from_utf8_lossy(data);
+    let content = content.trim_start_matches('\u{feff}').trim_start();
+    if !content.starts_with("<?xml") && !content.starts_with("<KeyFile") {
+        return Ok(None);
+    }
+
+    let mut reader = Reader::from_str(content);
+    let mut current = Vec::new();
+    let mut version = String::new();
+    let mut key = String::new();
+
+    loop {
+        match reader.read_event()? {
+            Event::Start(e) => current = e.name().as_ref().to_vec(),
+            Event::End(_) => current.clear(),
+            Event::Text(e) => match current.as_slice() {
+                b"Version" => version.push_str(&e.unescape()?),
+                b"Data" => key.push_str(&e.unescape()?),
+                _ => {}
+            },
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+
+    let key = key
+        .chars()
+        .filter(|c| !c.is_whitespace())
+        .collect::<String>();
+
+    if version.trim().starts_with("2.") {
+        hex::decode(key)
+            .map(Some)
+            .map_err(|_e| Error::InvalidKeyFile)
+    } else {
+        general_purpose::STANDARD
+            .decode(key)
+            .map(Some)
+            .map_err(|_e| Error::InvalidKeyFile)
+    }
+}
@@ -0,0 +1,421 @@
+//! # Otti - Provider `OTPClient`
+//!
+//! Import/Export component that allows to transform between the Otti accounts and backups from/to
+//! the [`OTPClient`](https://github.com/paolostivanin/OTPClient) GTK application.
+
+#![deny(rust_2018_idioms, clippy::all, clippy::pedantic)]
+#![allow(clippy::missing_errors_doc, clippy::single_match_else)]
+
+use std::collections::BTreeMap;
+
+use aes_gcm::{
+    aead::{consts::U16, generic_array::GenericArray},
+    aes::Aes256,
+    AeadInPlace, AesGcm, KeyInit,
+};
+pub use bytes::{Buf, BufMut};
+use otti_core::{ExposeSecret, Key, SecretString, Zeroizing};
+use rand::prelude::*;
+use serde::{Deserialize, Serialize};
+
+/// AES-GCM with the 16-byte nonce, that `OTPClient` uses instead of the common 12 bytes.
+type Aes256Gcm16 = AesGcm<Aes256, U16>;
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("the import data is too short")]
+    InputTooShort,
+    #[error("backup format version {0} is not supported")]
+    UnsupportedVersion(i32),
+    #[error("the key derivation parameters are invalid")]
+    InvalidParams,
+    #[error("key derivation failed")]
+    Argon2(#[from] argon2::Error),
+    #[error("data en-/decryption failed")]
+    AesGcm(#[from] aes_gcm::Error),
+    #[error("JSON (de-)serialization failed")]
+    Json(#[from] serde_json::Error),
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Token {
+    #[serde(rename = "type")]
+    ty: OtpType,
+    label: String,
+    #[serde(default)]
+    issuer: String,
+    #[serde(with = "otti_core::de::base32_string")]
+    secret: Zeroizing<Vec<u8>>,
+    digits: u8,
+    algo: Algorithm,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    period: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    counter: Option<u64>,
+}
+
+/// `OTPClient` has no dedicated type for Steam, but detects it from the issuer instead.
+const STEAM_ISSUER: &str = "Steam";
+/// Default period of TOTPs, if not defined otherwise.
+const DEFAULT_PERIOD: u64 = 30;
+
+impl From<Token> for otti_core::Account {
+    fn from(t: Token) -> Self {
+        let period = t.period.unwrap_or(DEFAULT_PERIOD);
+
+        Self {
+            label: t.label,
+            secret: Key::new(t.secret.to_vec()),
+            digits: t.digits,
+            otp: match t.ty {
+                OtpType::Hotp => otti_core::Otp::Hotp {
+                    counter: t.counter.unwrap_or_default(),
+                },
+                OtpType::Totp if t.issuer.eq_ignore_ascii_case(STEAM_ISSUER) => {
+                    otti_core::Otp::Steam { period }
+                }
+                OtpType::Totp => otti_core::Otp::Totp { window: period },
+            },
+            algorithm: t.algo.into(),
+            issuer: (!t.issuer.is_empty()).then_some(t.issuer),
+            meta: otti_core::Metadata::default(),
+            extras: BTreeMap::new(),
+        }
+    }
+}
+
+impl From<&otti_core::Account> for Token {
+    fn from(a: &otti_core::Account) -> Self {
+        let (ty, period, counter, issuer) = match a.otp {
+            otti_core::Otp::Hotp { counter } => (OtpType::Hotp, None, Some(counter), None),
+            otti_core::Otp::Totp { window } => (OtpType::Totp, Some(window), None, None),
+            otti_core::Otp::Steam { period } => (
+                OtpType::Totp,
+                Some(period),
+                None,
+                Some(STEAM_ISSUER.to_owned()),
+            ),
+        };
+
+        Self {
+            ty,
+            label: a.label.clone(),
+            issuer: issuer.or_else(|| a.issuer.clone()).unwrap_or_default(),
+            secret: Zeroizing::new(a.secret.expose_secret().clone()),
+            digits: a.digits,
+            algo: a.algorithm.into(),
+            period,
+            counter,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "UPPERCASE")]
+enum OtpType {
+    Totp,
+    Hotp,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "UPPERCASE")]
+enum Algorithm {
+    Sha1,
+    Sha256,
+    Sha512,
+}
+
+impl From<Algorithm> for otti_core::Algorithm {
+    fn from(a: Algorithm) -> Self {
+        match a {
+            Algorithm::Sha1 => Self::Sha1,
+            Algorithm::Sha256 => Self::Sha256,
+            Algorithm::Sha512 => Self::Sha512,
+        }
+    }
+}
+
+impl From<otti_core::Algorithm> for Algorithm {
+    fn from(a: otti_core::Algorithm) -> Self {
+        match a {
+            otti_core::Algorithm::Sha1 => Self::Sha1,
+            otti_core::Algorithm::Sha256 => Self::Sha256,
+            otti_core::Algorithm::Sha512 => Self::Sha512,
+        }
+    }
+}
+
+/// Version of the encrypted backup format, that is supported.
+const VERSION: i32 = 2;
+/// Size of the initialization vector for AES en-/decryption.
+const IV_SIZE: usize = 16;
+/// Size of the salt used in the key derivation.
+const SALT_SIZE: usize = 32;
+/// Size of the authentication tag, appended to the encrypted data.
+const TAG_SIZE: usize = 16;
+/// Size of the header, that is in front of the encrypted data. It consists of the version, IV,
+/// salt and the 3 Argon2id parameters.
+const HEADER_SIZE: usize = 4 + IV_SIZE + SALT_SIZE + 3 * 4;
+
+fn derive_key(
+    password: &[u8],
+    salt: &[u8],
+    (iterations, memory, parallelism): (u32, u32, u32),
+) -> Result<[u8; 32], Error> {
+    let mut key = [0_u8; 32];
+
+    argon2::Argon2::new(
+        argon2::Algorithm::Argon2id,
+        argon2::Version::V0x13,
+        argon2::Params::new(memory, iterations, parallelism, Some(key.len()))?,
+    )
+    .hash_password_into(password, salt, &mut key)?;
+
+    Ok(key)
+}
+
+fn decrypt(data: &mut impl Buf, password: impl AsRef<[u8]>) -> Result<Vec<u8>, Error> {
+    if data.remaining() < HEADER_SIZE + TAG_SIZE {
+        return Err(Error::InputTooShort);
+    }
+
+    let header = data.copy_to_bytes(HEADER_SIZE);
+    let mut rd = &header[..];
+
+    let version = rd.get_i32_le();
+    if version != VERSION {
+        return Err(Error::UnsupportedVersion(version));
+    }
+
+    let iv = rd.copy_to_bytes(IV_SIZE);
+    let salt = rd.copy_to_bytes(SALT_SIZE);
+    let params = (
+        read_param(&mut rd)?,
+        read_param(&mut rd)?,
+        read_param(&mut rd)?,
+    );
+
+    let key = derive_key(password.as_ref(), &salt, params)?;
+    let cipher = Aes256Gcm16::new(GenericArray::from_slice(&key));
+
+    let mut buf = vec![0_u8; data.remaining() - TAG_SIZE];
+    data.copy_to_slice(&mut buf);
+    let tag = data.copy_to_bytes(TAG_SIZE);
+
+    cipher.decrypt_in_place_detached(
+        GenericArray::from_slice(&iv),
+        &header,
+        &mut buf,
+        GenericArray::from_slice(&tag),
+    )?;
+
+    Ok(buf)
+}
+
+fn read_param(rd: &mut impl Buf) -> Result<u32, Error> {
+    rd.get_i32_le()
+        .try_into()
+        .map_err(|_e| Error::InvalidParams)
+}
+
+fn encrypt(wr: &mut impl BufMut, data: &[u8], password: impl AsRef<[u8]>) -> Result<(), Error> {
+    let iv = random_array::<IV_SIZE>();
+    let salt = random_array::<SALT_SIZE>();
+    let (iterations, memory, parallelism) = kdf_params();
+
+    let mut header = Vec::with_capacity(HEADER_SIZE);
+    header.put_i32_le(VERSION);
+    header.put(&iv[..]);
+    header.put(&salt[..]);
+    for param in [iterations, memory, parallelism] {
+        header.put_i32_le(param.try_into().map_err(|_e| Error::InvalidParams)?);
+    }
+
+    let key = derive_key(password.as_ref(), &salt, (iterations, memory, parallelism))?;
+    let cipher = Aes256Gcm16::new(GenericArray::from_slice(&key));
+
+    let mut buf = data.to_owned();
+    let tag = cipher.encrypt_in_place_detached(GenericArray::from_slice(&iv), &header, &mut buf)?;
+
+    wr.put(&header[..]);
+    wr.put(&buf[..]);
+    wr.put(&tag[..]);
+
+    Ok(())
+}
+
+/// Argon2id parameters as iterations, memory cost in KiB and parallelism. The regular values are
+/// the defaults of `OTPClient`, but they are considerably lowered in tests to keep them fast.
+fn kdf_params() -> (u32, u32, u32) {
+    if cfg!(test) {
+        (1, 1024, 1)
+    } else {
+        (4, 131_072, 4)
+    }
+}
+
+fn random_array<const N: usize>() -> [u8; N] {
+    let mut array = [0; N];
+    if cfg!(not(test)) {
+        rand::thread_rng().fill_bytes(&mut array);
+    }
+
+    array
+}
+
+pub fn load(
+    data: &mut impl Buf,
+    password: Option<&SecretString>,
+) -> Result<Vec<otti_core::Account>, Error> {
+    let json = match password {
+        Some(pw) => decrypt(data, pw.expose_secret())?,
+        None => {
+            let mut buf = vec![0_u8; data.remaining()];
+            data.copy_to_slice(&mut buf);
+            buf
+        }
+    };
+
+    Ok(serde_json::from_slice::<Vec<Token>>(&json)?
+        .into_iter()
+        .map(Into::into)
+        .collect())
+}
+
+pub fn save(
+    buf: &mut impl BufMut,
+    data: &[otti_core::Account],
+    password: Option<&SecretString>,
+) -> Result<(), Error> {
+    let json = serde_json::to_vec(&data.iter().map(Into::into).collect::<Vec<Token>>())?;
+
+    match password {
+        Some(pw) => encrypt(buf, &json, pw.expose_secret()),
+        None => {
+            buf.put(json.as_ref());
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use pretty_assertions::assert_eq;
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn roundtrip_plain() {
+        let file = include_bytes!("../import/otpclient-export.json");
+        let accounts = load(&mut &file[..], None).unwrap();
+
+        let mut file = Vec::new();
+        save(&mut file, &accounts, None).unwrap();
+
+        load(&mut file.as_slice(), None).unwrap();
+    }
+
+    #[test]
+    fn roundtrip_encrypted() {
+        let file = include_bytes!("../import/otpclient-export.enc");
+        let accounts = load(&mut &file[..], Some(&SecretString::new("123".to_owned()))).unwrap();
+
+        assert_eq!(4, accounts.len());
+        assert!(matches!(
+            accounts[1].otp,
+            otti_core::Otp::Hotp { counter: 5 }
+        ));
+        assert!(matches!(
+            accounts[3].otp,
+            otti_core::Otp::Steam { period: 30 }
+        ));
+
+        let mut file = Vec::new();
+        save(
+            &mut file,
+            &accounts,
+            Some(&SecretString::new("abc".to_owned())),
+        )
+        .unwrap();
+
+        load(
+            &mut file.as_slice(),
+            Some(&SecretString::new("abc".to_owned())),
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn export_plain() {
+        let mut export = Vec::new();
+        let data = [otti_core::Account {
+            label: "Entry 1".to_owned(),
+            secret: Key::new(vec![0; 10]),
+            digits: 6,
+            otp: otti_core::Otp::Totp { window: 30 },
+            algorithm: otti_core::Algorithm::Sha1,
+            issuer: Some("Provider 1".to_owned()),
+            meta: otti_core::Metadata {
+                tags: vec!["Tag 1".to_owned()],
+            },
+            extras: BTreeMap::new(),
+        }];
+
+        save(&mut export, &data, None).unwrap();
+
+        let output = serde_json::from_slice::<serde_json::Value>(&export).unwrap();
+        let expected = json! {[{
+            "type": "TOTP",
+            "label": "Entry 1",
+            "issuer": "Provider 1",
+            "secret": "AAAAAAAAAAAAAAAA",
+            "digits": 6,
+            "algo": "SHA1",
+            "period": 30
+        }]};
+
+        assert_eq!(expected, output);
+    }
+
+    #[test]
+    fn export_encrypted() {
+        let mut export = Vec::new();
+        let data = [otti_core::Account {
+            label: "Entry 1".to_owned(),
+            secret: Key::new(vec![0; 10]),
+            digits: 6,
+            otp: otti_core::Otp::Totp { window: 30 },
+            algorithm: otti_core::Algorithm::Sha1,
+            issuer: Some("Provider 1".to_owned()),
+            meta: otti_core::Metadata {
+                tags: vec!["Tag 1".to_owned()],
+            },
+            extras: BTreeMap::new(),
+        }];
+
+        save(
+            &mut export,
+            &data,
+            Some(&SecretString::new("123".to_owned())),
+        )
+        .unwrap();
+
+        let expected = &[
+            2, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+            0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1, 0, 0, 0, 0, 4,
+            0, 0, 1, 0, 0, 0, 84, 144, 126, 41, 221, 177, 172, 77, 237, 185, 119, 248, 1, 234, 65,
+            82, 3, 113, 180, 246, 171, 134, 27, 44, 233, 30, 32, 145, 78, 96, 105, 224, 0, 163, 40,
+            149, 234, 178, 167, 23, 134, 40, 32, 106, 25, 58, 30, 247, 25, 113, 73, 203, 100, 130,
+            68, 99, 153, 118, 219, 137, 43, 85, 122, 152, 120, 219, 206, 200, 24, 22, 99, 174, 253,
+            36, 31, 209, 122, 127, 253, 173, 241, 3, 172, 38, 154, 12, 40, 215, 201, 142, 39, 86,
+            219, 204, 252, 237, 95, 163, 166, 221, 63, 52, 251, 25, 90, 164, 240, 36, 204, 120, 75,
+            47, 186, 156, 101, 8, 51, 65, 114, 213, 136, 108, 103, 250, 233, 160, 122, 213, 192,
+            117, 126, 72, 103, 131, 102, 252, 78, 62,
+        ];
+
+        assert_eq!(expected, export.as_slice());
+    }
+}
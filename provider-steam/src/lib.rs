@@ -0,0 +1,566 @@
+//! # Otti - Provider `Steam Desktop Authenticator`
+//!
+//! Import/Export component that allows to transform between the Otti accounts and the `.maFile`
+//! account files of the
+//! [`Steam Desktop Authenticator`](https://github.com/Jessecar96/SteamDesktopAuthenticator).
+//!
+//! Each `.maFile` describes a single account. If the files are encrypted, the parameters for
+//! decryption are kept in a separate `manifest.json` file in the same folder.
+
+#![deny(rust_2018_idioms, clippy::all, clippy::pedantic)]
+#![allow(clippy::missing_errors_doc)]
+
+use std::{
+    collections::{BTreeMap, HashSet},
+    fs,
+    path::Path,
+};
+
+use aes::{
+    cipher::{
+        block_padding::Pkcs7, generic_array::GenericArray, BlockDecryptMut, BlockEncryptMut,
+        KeyIvInit,
+    },
+    Aes256,
+};
+use base64::engine::{general_purpose, Engine};
+pub use bytes::{Buf, BufMut};
+use hmac::Hmac;
+use otti_core::{ExposeSecret, Key, SecretString, Zeroizing};
+use rand::{Rng, RngCore};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use sha1::Sha1;
+
+mod de;
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("the cryptographic key length didn't match the cipher")]
+    InvalidLength(#[from] hmac::digest::InvalidLength),
+    #[error("data decryption failed")]
+    Aes(#[from] block_padding::UnpadError),
+    #[error("encrypted data is not valid Base64")]
+    Base64(#[from] base64::DecodeError),
+    #[error("JSON (de-)serialization failed")]
+    Json(#[from] serde_json::Error),
+    #[error("I/O bound error")]
+    Io(#[from] std::io::Error),
+    #[error("the file is encrypted, but the manifest entry with its parameters is missing")]
+    MissingEncryptionParams,
+}
+
+/// The manifest, that lists all account files and their encryption parameters.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Manifest {
+    encrypted: bool,
+    #[serde(default)]
+    first_run: bool,
+    entries: Vec<ManifestEntry>,
+}
+
+impl Manifest {
+    /// Whether the account files listed in this manifest are encrypted.
+    #[must_use]
+    pub fn encrypted(&self) -> bool {
+        self.encrypted
+    }
+
+    /// All account files listed in this manifest.
+    #[must_use]
+    pub fn entries(&self) -> &[ManifestEntry] {
+        &self.entries
+    }
+}
+
+/// A single account file in the [`Manifest`].
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ManifestEntry {
+    #[serde(with = "de::base64_string::option")]
+    encryption_iv: Option<Vec<u8>>,
+    #[serde(with = "de::base64_string::option")]
+    encryption_salt: Option<Vec<u8>>,
+    filename: String,
+    steamid: u64,
+}
+
+impl ManifestEntry {
+    /// File name of the account file, relative to the manifest's location.
+    #[must_use]
+    pub fn filename(&self) -> &str {
+        &self.filename
+    }
+}
+
+/// Account file content. Only the values, needed to generate OTPs, are extracted while everything
+/// else is kept as-is.
+#[derive(Debug, Serialize, Deserialize)]
+struct MaFile {
+    #[serde(with = "de::base64_string")]
+    shared_secret: Zeroizing<Vec<u8>>,
+    account_name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    revocation_code: Option<String>,
+    #[serde(flatten)]
+    fields: Map<String, Value>,
+}
+
+const EXTRA_REVOCATION_CODE: &str = "steam/revocation_code";
+const EXTRA_FIELDS: &str = "steam/fields";
+
+/// Issuer for all imported accounts, as `.maFile`s only contain Steam accounts.
+const ISSUER: &str = "Steam";
+/// Period of Steam OTPs.
+const PERIOD: u64 = 30;
+/// Amount of characters in Steam OTPs.
+const DIGITS: u8 = 5;
+
+impl TryFrom<MaFile> for otti_core::Account {
+    type Error = Error;
+
+    fn try_from(f: MaFile) -> Result<Self, Self::Error> {
+        let mut extras = BTreeMap::new();
+        if let Some(code) = f.revocation_code {
+            extras.insert(EXTRA_REVOCATION_CODE.to_owned(), code.into_bytes());
+        }
+        if !f.fields.is_empty() {
+            extras.insert(EXTRA_FIELDS.to_owned(), serde_json::to_vec(&f.fields)?);
+        }
+
+        Ok(Self {
+            label: f.account_name,
+            secret: Key::new(f.shared_secret.to_vec()),
+            digits: DIGITS,
+            otp: otti_core::Otp::Steam { period: PERIOD },
+            algorithm: otti_core::Algorithm::Sha1,
+            issuer: Some(ISSUER.to_owned()),
+            meta: otti_core::Metadata::default(),
+            extras,
+        })
+    }
+}
+
+impl From<&otti_core::Account> for MaFile {
+    fn from(a: &otti_core::Account) -> Self {
+        Self {
+            shared_secret: Zeroizing::new(a.secret.expose_secret().clone()),
+            account_name: a.label.clone(),
+            revocation_code: a
+                .extras
+                .get(EXTRA_REVOCATION_CODE)
+                .cloned()
+                .and_then(|v| String::from_utf8(v).ok()),
+            fields: a
+                .extras
+                .get(EXTRA_FIELDS)
+                .and_then(|v| serde_json::from_slice(v).ok())
+                .unwrap_or_default(),
+        }
+    }
+}
+
+impl MaFile {
+    /// The Steam ID of the account, if known. It is used to name the account files.
+    fn steam_id(&self) -> Option<u64> {
+        self.fields
+            .get("Session")
+            .and_then(|s| s.get("SteamID"))
+            .and_then(Value::as_u64)
+    }
+}
+
+/// Amount of rounds for [`pbkdf2`] key derivation.
+const PBKDF2_ROUNDS: u32 = 50_000;
+/// Size of the key for AES en-/decryption.
+const KEY_SIZE: usize = 32;
+/// Size of the salt used in the key derivation.
+const SALT_SIZE: usize = 8;
+/// Size of the initialization vector for AES en-/decryption.
+const BLOCK_SIZE: usize = 16;
+
+fn derive_key(password: &[u8], salt: &[u8]) -> Result<[u8; KEY_SIZE], Error> {
+    let mut key = [0_u8; KEY_SIZE];
+    pbkdf2::pbkdf2::<Hmac<Sha1>>(password, salt, PBKDF2_ROUNDS, &mut key)?;
+
+    Ok(key)
+}
+
+fn decrypt(
+    data: &mut impl Buf,
+    password: impl AsRef<[u8]>,
+    entry: &ManifestEntry,
+) -> Result<Vec<u8>, Error> {
+    let (Some(iv), Some(salt)) = (&entry.encryption_iv, &entry.encryption_salt) else {
+        return Err(Error::MissingEncryptionParams);
+    };
+    if iv.len() != BLOCK_SIZE {
+        return Err(Error::MissingEncryptionParams);
+    }
+
+    let mut buf = vec![0_u8; data.remaining()];
+    data.copy_to_slice(&mut buf);
+
+    let buf = general_purpose::STANDARD.decode(buf.trim_ascii())?;
+    let key = derive_key(password.as_ref(), salt)?;
+
+    let key = GenericArray::from_slice(&key);
+    let iv = GenericArray::from_slice(iv);
+    let cipher = <cbc::Decryptor<Aes256>>::new(key, iv);
+
+    cipher
+        .decrypt_padded_vec_mut::<Pkcs7>(&buf)
+        .map_err(Into::into)
+}
+
+/// Encrypt the data and store the used parameters in the manifest `entry`.
+fn encrypt(
+    data: &[u8],
+    password: impl AsRef<[u8]>,
+    entry: &mut ManifestEntry,
+) -> Result<Vec<u8>, Error> {
+    let salt = random_salt();
+    let iv = random_array();
+    let key = derive_key(password.as_ref(), &salt)?;
+
+    let key = GenericArray::from_slice(&key);
+    let cipher = <cbc::Encryptor<Aes256>>::new(key, &iv);
+
+    let buf = cipher.encrypt_padded_vec_mut::<Pkcs7>(data);
+
+    entry.encryption_iv = Some(iv.to_vec());
+    entry.encryption_salt = Some(salt.to_vec());
+
+    Ok(general_purpose::STANDARD.encode(buf).into_bytes())
+}
+
+fn random_salt() -> [u8; SALT_SIZE] {
+    if cfg!(test) {
+        [0; SALT_SIZE]
+    } else {
+        rand::thread_rng().gen()
+    }
+}
+
+fn random_array() -> GenericArray<u8, <Aes256 as aes::cipher::BlockSizeUser>::BlockSize> {
+    let mut array = GenericArray::default();
+    if cfg!(not(test)) {
+        rand::thread_rng().fill_bytes(&mut array);
+    }
+
+    array
+}
+
+/// Load the manifest, that is needed to decrypt encrypted account files.
+pub fn load_manifest(data: &mut impl Buf) -> Result<Manifest, Error> {
+    serde_json::from_reader(data.reader()).map_err(Into::into)
+}
+
+/// Load a single account file. If a `password` is given, the file is decrypted with the
+/// parameters from its `entry` in the [`Manifest`].
+pub fn load(
+    data: &mut impl Buf,
+    password: Option<&SecretString>,
+    entry: Option<&ManifestEntry>,
+) -> Result<Vec<otti_core::Account>, Error> {
+    let file = match password {
+        Some(pw) => {
+            let buf = decrypt(
+                data,
+                pw.expose_secret(),
+                entry.ok_or(Error::MissingEncryptionParams)?,
+            )?;
+            serde_json::from_slice::<MaFile>(&buf)?
+        }
+        None => serde_json::from_reader::<_, MaFile>(data.reader())?,
+    };
+
+    Ok(vec![file.try_into()?])
+}
+
+/// Load Steam accounts from the file at `path` with the given content. A manifest loads all
+/// account files listed in it, while a single account file is loaded on its own. In the latter
+/// case, the manifest is still loaded from the same folder if the file is encrypted.
+pub fn load_path(
+    path: &Path,
+    data: &mut impl Buf,
+    password: Option<&SecretString>,
+) -> Result<Vec<otti_core::Account>, Error> {
+    let dir = path.parent().unwrap_or_else(|| Path::new("."));
+
+    if path.file_name().is_some_and(|name| name == "manifest.json") {
+        let manifest = load_manifest(data)?;
+        let password = password.filter(|_| manifest.encrypted());
+        let mut accounts = Vec::new();
+
+        for entry in manifest.entries() {
+            let file = fs::read(dir.join(entry.filename()))?;
+            accounts.extend(load(&mut file.as_slice(), password, Some(entry))?);
+        }
+
+        return Ok(accounts);
+    }
+
+    let manifest = match password {
+        Some(_) => Some(load_manifest(
+            &mut fs::read(dir.join("manifest.json"))?.as_slice(),
+        )?),
+        None => None,
+    };
+    let entry = manifest.as_ref().and_then(|m| {
+        m.entries()
+            .iter()
+            .find(|e| path.file_name().is_some_and(|name| name == e.filename()))
+    });
+
+    load(data, password, entry)
+}
+
+/// Save all Steam accounts as separate account files, together with a manifest. Accounts with
+/// other OTP types are skipped, as they can't be represented.
+///
+/// The result is a list of file names with their content, which must all be placed in the same
+/// folder. Files are named after the account's Steam ID, with a numbered suffix for any further
+/// account with the same ID.
+pub fn save(
+    data: &[otti_core::Account],
+    password: Option<&SecretString>,
+) -> Result<Vec<(String, Vec<u8>)>, Error> {
+    let mut files = Vec::new();
+    let mut names = HashSet::new();
+    let mut manifest = Manifest {
+        encrypted: password.is_some(),
+        first_run: false,
+        entries: Vec::new(),
+    };
+
+    for (i, account) in data
+        .iter()
+        .filter(|a| matches!(a.otp, otti_core::Otp::Steam { .. }))
+        .enumerate()
+    {
+        let file = MaFile::from(account);
+        let steamid = file.steam_id().unwrap_or_default();
+        let stem = match file.steam_id() {
+            Some(id) => id.to_string(),
+            None => format!("account-{}", i + 1),
+        };
+
+        let mut filename = format!("{stem}.maFile");
+        for n in 2.. {
+            if names.insert(filename.clone()) {
+                break;
+            }
+            filename = format!("{stem}-{n}.maFile");
+        }
+
+        let json = serde_json::to_vec(&file)?;
+        let mut entry = ManifestEntry {
+            encryption_iv: None,
+            encryption_salt: None,
+            filename: filename.clone(),
+            steamid,
+        };
+
+        let content = match password {
+            Some(pw) => encrypt(&json, pw.expose_secret(), &mut entry)?,
+            None => json,
+        };
+
+        manifest.entries.push(entry);
+        files.push((filename, content));
+    }
+
+    files.push(("manifest.json".to_owned(), serde_json::to_vec(&manifest)?));
+
+    Ok(files)
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+    use serde_json::json;
+
+    use super::*;
+
+    fn labels(accounts: &[otti_core::Account]) -> Vec<&str> {
+        accounts.iter().map(|a| a.label.as_str()).collect()
+    }
+
+    #[test]
+    fn roundtrip_plain() {
+        let file = include_bytes!("../import/76561198000000001.maFile");
+        let accounts = load(&mut &file[..], None, None).unwrap();
+
+        let files = save(&accounts, None).unwrap();
+        assert_eq!(2, files.len());
+        assert_eq!("76561198000000001.maFile", files[0].0);
+
+        let reloaded = load(&mut files[0].1.as_slice(), None, None).unwrap();
+        assert_eq!(accounts[0].extras, reloaded[0].extras);
+    }
+
+    #[test]
+    fn roundtrip_encrypted() {
+        let manifest = include_bytes!("../import/encrypted/manifest.json");
+        let manifest = load_manifest(&mut &manifest[..]).unwrap();
+        let file = include_bytes!("../import/encrypted/76561198000000002.maFile");
+        let accounts = load(
+            &mut &file[..],
+            Some(&SecretString::new("123".to_owned())),
+            manifest.entries().first(),
+        )
+        .unwrap();
+
+        let files = save(&accounts, Some(&SecretString::new("abc".to_owned()))).unwrap();
+        let manifest = load_manifest(&mut files[1].1.as_slice()).unwrap();
+        assert!(manifest.encrypted());
+
+        load(
+            &mut files[0].1.as_slice(),
+            Some(&SecretString::new("abc".to_owned())),
+            manifest.entries().first(),
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn load_from_path() {
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("import");
+        let password = SecretString::new("123".to_owned());
+
+        let path = dir.join("76561198000000001.maFile");
+        let accounts = load_path(&path, &mut fs::read(&path).unwrap().as_slice(), None).unwrap();
+        assert_eq!(vec!["sample_user"], labels(&accounts));
+
+        let path = dir.join("encrypted/manifest.json");
+        let from_manifest = load_path(
+            &path,
+            &mut fs::read(&path).unwrap().as_slice(),
+            Some(&password),
+        )
+        .unwrap();
+
+        let path = dir.join("encrypted/76561198000000002.maFile");
+        let from_file = load_path(
+            &path,
+            &mut fs::read(&path).unwrap().as_slice(),
+            Some(&password),
+        )
+        .unwrap();
+
+        assert_eq!(1, from_manifest.len());
+        assert_eq!(labels(&from_manifest), labels(&from_file));
+    }
+
+    #[test]
+    fn export_same_steam_id() {
+        let file = include_bytes!("../import/76561198000000001.maFile");
+        let mut accounts = load(&mut &file[..], None, None).unwrap();
+        accounts.extend(load(&mut &file[..], None, None).unwrap());
+
+        let files = save(&accounts, None).unwrap();
+        let names = files
+            .iter()
+            .map(|(name, _)| name.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            vec![
+                "76561198000000001.maFile",
+                "76561198000000001-2.maFile",
+                "manifest.json"
+            ],
+            names
+        );
+
+        let manifest = load_manifest(&mut files[2].1.as_slice()).unwrap();
+        let filenames = manifest
+            .entries()
+            .iter()
+            .map(ManifestEntry::filename)
+            .collect::<Vec<_>>();
+        assert_eq!(names[..2], filenames);
+    }
+
+    #[test]
+    fn import_plain() {
+        let file = include_bytes!("../import/76561198000000001.maFile");
+        let accounts = load(&mut &file[..], None, None).unwrap();
+
+        assert_eq!(1, accounts.len());
+        assert_eq!("sample_user", accounts[0].label);
+        assert_eq!(Some("Steam"), accounts[0].issuer.as_deref());
+        assert_eq!(b"1234567890", accounts[0].secret.expose_secret().as_slice());
+        assert!(matches!(
+            accounts[0].otp,
+            otti_core::Otp::Steam { period: 30 }
+        ));
+        assert_eq!(
+            Some(&b"R12345".to_vec()),
+            accounts[0].extras.get("steam/revocation_code")
+        );
+    }
+
+    #[test]
+    fn export_plain() {
+        let data = [
+            otti_core::Account {
+                label: "Entry 1".to_owned(),
+                secret: Key::new(vec![0; 10]),
+                digits: 5,
+                otp: otti_core::Otp::Steam { period: 30 },
+                algorithm: otti_core::Algorithm::Sha1,
+                issuer: Some("Steam".to_owned()),
+                meta: otti_core::Metadata::default(),
+                extras: BTreeMap::from([
+                    ("steam/revocation_code".to_owned(), b"R12345".to_vec()),
+                    (
+                        "steam/fields".to_owned(),
+                        br#"{"Session":{"SteamID":1},"status":1}"#.to_vec(),
+                    ),
+                ]),
+            },
+            otti_core::Account {
+                label: "Entry 2".to_owned(),
+                secret: Key::new(vec![0; 10]),
+                digits: 6,
+                otp: otti_core::Otp::Totp { window: 30 },
+                algorithm: otti_core::Algorithm::Sha1,
+                issuer: Some("Provider 2".to_owned()),
+                meta: otti_core::Metadata::default(),
+                extras: BTreeMap::new(),
+            },
+        ];
+
+        let files = save(&data, None).unwrap();
+        let names = files
+            .iter()
+            .map(|(name, _)| name.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(vec!["1.maFile", "manifest.json"], names);
+
+        let output = serde_json::from_slice::<Value>(&files[0].1).unwrap();
+        let expected = json! {{
+            "shared_secret": "AAAAAAAAAAAAAA==",
+            "account_name": "Entry 1",
+            "revocation_code": "R12345",
+            "Session": {
+                "SteamID": 1
+            },
+            "status": 1
+        }};
+        assert_eq!(expected, output);
+
+        let output = serde_json::from_slice::<Value>(&files[1].1).unwrap();
+        let expected = json! {{
+            "encrypted": false,
+            "first_run": false,
+            "entries": [{
+                "encryption_iv": null,
+                "encryption_salt": null,
+                "filename": "1.maFile",
+                "steamid": 1
+            }]
+        }};
+        assert_eq!(expected, output);
+    }
+}
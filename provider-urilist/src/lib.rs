@@ -0,0 +1,194 @@
+//! # Otti - Provider URI list
+//!
+//! Import/Export component that allows to transform between the Otti accounts and plain text
+//! files, that contain one `otpauth://` URL per line.
+//!
+//! Empty lines and lines starting with `#` are ignored. Lines that fail to parse don't abort the
+//! import, but are reported together with their line number.
+
+#![deny(rust_2018_idioms, clippy::all, clippy::pedantic)]
+#![allow(clippy::missing_errors_doc)]
+
+pub use bytes::{Buf, BufMut};
+use otti_core::SecretString;
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("URI lists can't be encrypted, omit the password")]
+    Encrypted,
+}
+
+/// A single line of the URI list, that couldn't be parsed into an account.
+#[derive(Debug, thiserror::Error)]
+#[error("line {line} is not a valid OTP URL")]
+pub struct LineError {
+    /// Line number, starting at 1.
+    pub line: usize,
+    /// The reason why the line failed to parse.
+    #[source]
+    pub source: otti_core::ParseError,
+}
+
+/// Accounts that were loaded from a URI list, together with the lines that failed to load.
+pub struct Import {
+    pub accounts: Vec<otti_core::Account>,
+    pub errors: Vec<LineError>,
+}
+
+/// Marker at the start of a line, that turns it into a comment.
+const COMMENT: char = '#';
+
+pub fn load(data: &mut impl Buf, password: Option<&SecretString>) -> Result<Import, Error> {
+    if password.is_some() {
+        return Err(Error::Encrypted);
+    }
+
+    let data = data.copy_to_bytes(data.remaining());
+    let mut import = Import {
+        accounts: Vec::new(),
+        errors: Vec::new(),
+    };
+
+    for (i, line) in data.split(|&b| b == b'\n').enumerate() {
+        let result = std::str::from_utf8(line)
+            .map_err(Into::into)
+            .map(str::trim)
+            .and_then(|line| {
+                if line.is_empty() || line.starts_with(COMMENT) {
+                    Ok(None)
+                } else {
+                    line.parse().map(Some)
+                }
+            });
+
+        match result {
+            Ok(account) => import.accounts.extend(account),
+            Err(source) => import.errors.push(LineError {
+                line: i + 1,
+                source,
+            }),
+        }
+    }
+
+    Ok(import)
+}
+
+pub fn save(
+    buf: &mut impl BufMut,
+    data: &[otti_core::Account],
+    password: Option<&SecretString>,
+) -> Result<(), Error> {
+    if password.is_some() {
+        return Err(Error::Encrypted);
+    }
+
+    for account in data {
+        buf.put(account.to_url().as_bytes());
+        buf.put_u8(b'\n');
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use otti_core::{ExposeSecret, Key};
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    #[test]
+    fn roundtrip_plain() {
+        let file = include_bytes!("../import/otpauth-uris.txt");
+        let import = load(&mut &file[..], None).unwrap();
+
+        let mut file = Vec::new();
+        save(&mut file, &import.accounts, None).unwrap();
+
+        let roundtrip = load(&mut file.as_slice(), None).unwrap();
+        assert_eq!(
+            import
+                .accounts
+                .iter()
+                .map(otti_core::Account::to_url)
+                .collect::<Vec<_>>(),
+            roundtrip
+                .accounts
+                .iter()
+                .map(otti_core::Account::to_url)
+                .collect::<Vec<_>>()
+        );
+        assert!(roundtrip.errors.is_empty());
+    }
+
+    #[test]
+    fn import_plain() {
+        let file = include_bytes!("../import/otpauth-uris.txt");
+        let import = load(&mut &file[..], None).unwrap();
+
+        assert_eq!(3, import.accounts.len());
+        assert!(import
+            .accounts
+            .iter()
+            .all(|a| a.secret.expose_secret() == b"1234567890"));
+
+        assert_eq!(Some("Sample Issuer"), import.accounts[0].issuer.as_deref());
+        assert_eq!("Sample TOTP", import.accounts[0].label);
+        assert!(matches!(
+            import.accounts[1].otp,
+            otti_core::Otp::Hotp { counter: 5 }
+        ));
+        assert!(matches!(
+            import.accounts[2].otp,
+            otti_core::Otp::Steam { period: 30 }
+        ));
+
+        assert_eq!(
+            vec![6, 7],
+            import.errors.iter().map(|e| e.line).collect::<Vec<_>>()
+        );
+        assert!(matches!(
+            import.errors[0].source,
+            otti_core::ParseError::InvalidScheme(_)
+        ));
+    }
+
+    #[test]
+    fn export_plain() {
+        let mut export = Vec::new();
+        let data = [
+            otti_core::Account {
+                label: "Entry 1".to_owned(),
+                secret: Key::new(vec![0; 10]),
+                digits: 6,
+                otp: otti_core::Otp::Totp { window: 30 },
+                algorithm: otti_core::Algorithm::Sha1,
+                issuer: Some("Provider 1".to_owned()),
+                meta: otti_core::Metadata {
+                    tags: vec!["Tag 1".to_owned()],
+                },
+                extras: BTreeMap::new(),
+            },
+            otti_core::Account {
+                label: "Entry 2".to_owned(),
+                secret: Key::new(vec![0; 10]),
+                digits: 8,
+                otp: otti_core::Otp::Hotp { counter: 3 },
+                algorithm: otti_core::Algorithm::Sha256,
+                issuer: None,
+                meta: otti_core::Metadata::default(),
+                extras: BTreeMap::new(),
+            },
+        ];
+
+        save(&mut export, &data, None).unwrap();
+
+        let expected = "otpauth://totp/Provider%201:Entry%201?secret=AAAAAAAAAAAAAAAA&\
+                        issuer=Provider%201&algorithm=SHA1&digits=6&period=30\notpauth://hotp/\
+                        Entry%202?secret=AAAAAAAAAAAAAAAA&algorithm=SHA256&digits=8&counter=3\n";
+
+        assert_eq!(expected, String::from_utf8(export).unwrap());
+    }
+}
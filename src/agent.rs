@@ -0,0 +1,546 @@
+//! Unlock agent, that keeps the accounts of a store in memory and hands them out over a Unix
+//! domain socket, similar to `ssh-agent`. Commands first ask a running agent for the accounts and
+//! only unlock the store themselves, if there is none.
+//!
+//! Only processes of the same user may talk to the agent, which is checked through the peer
+//! credentials of each connection. The agent stops after being idle for a while, when it's told
+//! to stop, or as soon as the store file changes on disk.
+
+use std::{path::Path, time::Duration};
+
+use anyhow::Result;
+use otti_core::Account;
+use otti_store::Store;
+use serde::{Deserialize, Serialize};
+
+/// Default time after which an agent stops, when it didn't receive any requests.
+// `Duration::from_mins` is only available on recent toolchains.
+#[allow(unknown_lints, clippy::duration_suboptimal_units)]
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(15 * 60);
+
+#[derive(Serialize, Deserialize)]
+enum Request {
+    Accounts,
+    Stop,
+}
+
+/// Answer of the agent, where the agent sends its accounts by reference and the client receives
+/// them as owned list.
+#[derive(Serialize, Deserialize)]
+enum Response<A> {
+    Accounts(A),
+    Stopped,
+    /// The store changed since the agent unlocked it, so the agent stopped.
+    Locked,
+}
+
+/// Get the accounts from the agent of the store, if one is running.
+///
+/// Any failure to talk to the agent is treated the same as no agent running, so the caller can
+/// always fall back to unlocking the store directly.
+pub fn fetch(store: &Store) -> Option<Vec<Account>> {
+    match imp::request(store, &Request::Accounts) {
+        Ok(Some(Response::<Vec<Account>>::Accounts(accounts))) => Some(accounts),
+        _ => None,
+    }
+}
+
+/// Stop the agent of the store. Returns `false` if no agent was running.
+pub fn stop(store: &Store) -> Result<bool> {
+    Ok(imp::request::<Vec<Account>>(store, &Request::Stop)?.is_some())
+}
+
+/// Whether an agent is currently running for the store.
+pub fn is_running(store: &Store) -> Result<bool> {
+    imp::is_running(store)
+}
+
+/// Start an agent for the already unlocked accounts of the store. Unless running in the
+/// `foreground`, the agent is started as separate background process and this function returns
+/// as soon as the agent accepts connections. The background process loads the same `config` file,
+/// if one was given explicitly.
+pub fn start(
+    store: &Store,
+    config: Option<&Path>,
+    accounts: &[Account],
+    timeout: Duration,
+    foreground: bool,
+) -> Result<()> {
+    imp::start(store, config, accounts, timeout, foreground)
+}
+
+/// Run the background agent, that was started by [`start`], which receives the accounts through
+/// its standard input.
+pub fn serve(store: &Store, timeout: Duration) -> Result<()> {
+    imp::serve(store, timeout)
+}
+
+#[cfg(unix)]
+mod imp {
+    use std::{
+        env, fs,
+        io::{self, Write},
+        net::Shutdown,
+        os::{
+            fd::AsRawFd,
+            unix::{
+                ffi::OsStrExt,
+                fs::{DirBuilderExt, MetadataExt, PermissionsExt},
+                net::{UnixListener, UnixStream},
+                process::CommandExt,
+            },
+        },
+        path::{Path, PathBuf},
+        process::{Command, Stdio},
+        thread,
+        time::{Duration, Instant, SystemTime},
+    };
+
+    use anyhow::{bail, ensure, Context, Result};
+    use blake2::{digest::consts::U8, Blake2b, Digest};
+    use crossbeam_channel::RecvTimeoutError;
+    use nix::unistd::Uid;
+    use otti_core::Account;
+    use otti_store::Store;
+    use serde::de::DeserializeOwned;
+
+    use super::{Request, Response};
+    use crate::harden;
+
+    /// Maximum time to wait for the agent, both when talking to it and when starting it.
+    const WAIT: Duration = Duration::from_secs(5);
+
+    pub fn request<A: DeserializeOwned>(
+        store: &Store,
+        request: &Request,
+    ) -> Result<Option<Response<A>>> {
+        let path = socket_path(store)?;
+        if !path.try_exists()? {
+            return Ok(None);
+        }
+
+        let Ok(mut stream) = UnixStream::connect(&path) else {
+            return Ok(None);
+        };
+        stream.set_read_timeout(Some(WAIT))?;
+        stream.set_write_timeout(Some(WAIT))?;
+
+        rmp_serde::encode::write(&mut stream, request)?;
+        stream.shutdown(Shutdown::Write)?;
+
+        match rmp_serde::from_read(&mut stream)? {
+            Response::Locked => Ok(None),
+            response => Ok(Some(response)),
+        }
+    }
+
+    pub fn is_running(store: &Store) -> Result<bool> {
+        Ok(UnixStream::connect(socket_path(store)?).is_ok())
+    }
+
+    pub fn start(
+        store: &Store,
+        config: Option<&Path>,
+        accounts: &[Account],
+        timeout: Duration,
+        foreground: bool,
+    ) -> Result<()> {
+        ensure!(
+            !is_running(store)?,
+            "an agent is already running for this store"
+        );
+
+        if foreground {
+            let path = socket_path(store)?;
+            let listener = bind(&path)?;
+            return run(store, &path, listener, accounts, timeout);
+        }
+
+        let mut command = Command::new(env::current_exe()?);
+        if !harden::is_enabled() {
+            command.arg("--no-harden");
+        }
+        if let Some(config) = config {
+            command.arg("--config").arg(config);
+        }
+
+        let mut child = command
+            .arg("--store")
+            .arg(store.path())
+            .args(["agent", "serve", "--timeout"])
+            .arg(timeout.as_secs().to_string())
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            // Detach from the terminal's process group, to not receive its signals.
+            .process_group(0)
+            .spawn()
+            .context("failed starting the agent process")?;
+
+        let mut stdin = child.stdin.take().context("agent process has no input")?;
+        rmp_serde::encode::write(&mut stdin, accounts)?;
+        drop(stdin);
+
+        let start = Instant::now();
+        while !is_running(store)? {
+            if let Some(status) = child.try_wait()? {
+                bail!("agent process stopped early with {status}");
+            }
+            ensure!(start.elapsed() < WAIT, "agent process didn't start in time");
+
+            thread::sleep(Duration::from_millis(50));
+        }
+
+        Ok(())
+    }
+
+    pub fn serve(store: &Store, timeout: Duration) -> Result<()> {
+        // Decode right from the input, to not keep another copy of the secrets in a buffer.
+        let accounts = rmp_serde::from_read::<_, Vec<Account>>(io::stdin().lock())?;
+        harden::lock(&accounts);
+
+        let path = socket_path(store)?;
+        let listener = bind(&path)?;
+        run(store, &path, listener, &accounts, timeout)
+    }
+
+    /// Serve the accounts, until the agent is stopped for any reason. The socket at `path` is
+    /// removed afterwards, so clients don't try to connect to it anymore.
+    fn run(
+        store: &Store,
+        path: &Path,
+        listener: UnixListener,
+        accounts: &[Account],
+        timeout: Duration,
+    ) -> Result<()> {
+        let modified = modified(store)?;
+        let uid = Uid::current();
+        let (tx, rx) = crossbeam_channel::unbounded();
+
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                if tx.send(stream).is_err() {
+                    break;
+                }
+            }
+        });
+
+        let result = loop {
+            let stream = match rx.recv_timeout(timeout) {
+                Ok(stream) => stream,
+                Err(RecvTimeoutError::Timeout) => break Ok(()),
+                Err(RecvTimeoutError::Disconnected) => break Err(anyhow::anyhow!("socket closed")),
+            };
+
+            // Failing connections only affect a single client, not the agent itself.
+            let Ok(mut stream) = stream else {
+                continue;
+            };
+            if !authorized(&stream, uid) {
+                continue;
+            }
+
+            match handle(&mut stream, store, modified, accounts) {
+                Ok(true) | Err(_) => {}
+                Ok(false) => break Ok(()),
+            }
+        };
+
+        fs::remove_file(path).ok();
+        result
+    }
+
+    /// Answer a single request. Returns whether the agent should keep running.
+    fn handle(
+        stream: &mut UnixStream,
+        store: &Store,
+        modified: SystemTime,
+        accounts: &[Account],
+    ) -> Result<bool> {
+        stream.set_read_timeout(Some(WAIT))?;
+        stream.set_write_timeout(Some(WAIT))?;
+
+        let request = rmp_serde::from_read::<_, Request>(&mut *stream)?;
+
+        let (response, running) = if self::modified(store).ok() == Some(modified) {
+            match request {
+                Request::Accounts => (Response::Accounts(accounts), true),
+                Request::Stop => (Response::Stopped, false),
+            }
+        } else {
+            (Response::Locked, false)
+        };
+
+        rmp_serde::encode::write(stream, &response)?;
+        stream.flush()?;
+
+        Ok(running)
+    }
+
+    fn bind(path: &Path) -> Result<UnixListener> {
+        // A socket, that nobody listens on anymore, is left over from an agent that crashed.
+        if path.try_exists()? {
+            ensure!(
+                UnixStream::connect(path).is_err(),
+                "an agent is already running for this store"
+            );
+            fs::remove_file(path)?;
+        }
+
+        let listener = UnixListener::bind(path)
+            .with_context(|| format!("failed binding agent socket `{}`", path.display()))?;
+        fs::set_permissions(path, fs::Permissions::from_mode(0o600))?;
+
+        Ok(listener)
+    }
+
+    /// Whether the client on the other end of the stream runs as the given user.
+    fn authorized(stream: &UnixStream, uid: Uid) -> bool {
+        peer_uid(stream).ok() == Some(uid)
+    }
+
+    fn modified(store: &Store) -> Result<SystemTime> {
+        Ok(fs::metadata(store.path())?.modified()?)
+    }
+
+    /// Location of the agent's socket, which is unique for each store. It's placed in a directory,
+    /// that only the current user can access.
+    ///
+    /// The name is derived from the store's path with a hash, that is stable between releases,
+    /// so clients still find agents that were started by a previous version.
+    fn socket_path(store: &Store) -> Result<PathBuf> {
+        let uid = Uid::current();
+        let dir = match env::var_os("XDG_RUNTIME_DIR") {
+            Some(dir) => PathBuf::from(dir).join("otti"),
+            None => env::temp_dir().join(format!("otti-{uid}")),
+        };
+        private_dir(&dir, uid)?;
+
+        Ok(socket_in(&dir, store))
+    }
+
+    /// Location of the store's agent socket inside the given directory.
+    fn socket_in(dir: &Path, store: &Store) -> PathBuf {
+        let path = fs::canonicalize(store.path()).unwrap_or_else(|_| store.path().to_owned());
+        let hash = u64::from_be_bytes(Blake2b::<U8>::digest(path.as_os_str().as_bytes()).into());
+
+        dir.join(format!("agent-{hash:016x}.sock"))
+    }
+
+    /// Create the directory if needed, and ensure that only the given user can access it.
+    #[allow(clippy::verbose_bit_mask)]
+    fn private_dir(dir: &Path, uid: Uid) -> Result<()> {
+        if !dir.try_exists()? {
+            fs::DirBuilder::new()
+                .recursive(true)
+                .mode(0o700)
+                .create(dir)?;
+        }
+
+        let meta = fs::metadata(dir)?;
+        ensure!(
+            meta.uid() == uid.as_raw() && meta.mode() & 0o077 == 0,
+            "agent directory `{}` must be private to the current user",
+            dir.display()
+        );
+
+        Ok(())
+    }
+
+    #[cfg(any(target_os = "android", target_os = "linux"))]
+    fn peer_uid(stream: &UnixStream) -> Result<Uid> {
+        let cred = nix::sys::socket::getsockopt(
+            stream.as_raw_fd(),
+            nix::sys::socket::sockopt::PeerCredentials,
+        )?;
+        Ok(Uid::from_raw(cred.uid()))
+    }
+
+    #[cfg(any(
+        target_os = "macos",
+        target_os = "ios",
+        target_os = "freebsd",
+        target_os = "openbsd",
+        target_os = "netbsd",
+        target_os = "dragonfly",
+    ))]
+    fn peer_uid(stream: &UnixStream) -> Result<Uid> {
+        Ok(nix::unistd::getpeereid(stream.as_raw_fd())?.0)
+    }
+
+    /// Without a way to identify the peer, all connections are refused.
+    #[cfg(not(any(
+        target_os = "android",
+        target_os = "linux",
+        target_os = "macos",
+        target_os = "ios",
+        target_os = "freebsd",
+        target_os = "openbsd",
+        target_os = "netbsd",
+        target_os = "dragonfly",
+    )))]
+    fn peer_uid(_stream: &UnixStream) -> Result<Uid> {
+        bail!("peer credentials are not supported on this system")
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use std::sync::OnceLock;
+
+        use super::*;
+
+        /// Private directory shared by all tests, that takes the place of the runtime directory.
+        fn test_dir() -> &'static Path {
+            static DIR: OnceLock<PathBuf> = OnceLock::new();
+            DIR.get_or_init(|| {
+                let dir = env::temp_dir().join(format!("otti-agent-test-{}", std::process::id()));
+                private_dir(&dir, Uid::current()).unwrap();
+                dir
+            })
+        }
+
+        /// Store file with some content, so it has a modification time.
+        fn store(name: &str) -> Store {
+            let path = test_dir().join(name);
+            fs::write(&path, b"store").unwrap();
+            Store::at(path)
+        }
+
+        fn send(
+            store: &Store,
+            modified: SystemTime,
+            request: &Request,
+        ) -> (bool, Response<Vec<Account>>) {
+            let (mut client, mut server) = UnixStream::pair().unwrap();
+            rmp_serde::encode::write(&mut client, request).unwrap();
+            client.shutdown(Shutdown::Write).unwrap();
+
+            let running = handle(&mut server, store, modified, &[]).unwrap();
+            drop(server);
+
+            (running, rmp_serde::from_read(&mut client).unwrap())
+        }
+
+        #[test]
+        fn check_peer_uid() {
+            let (client, _server) = UnixStream::pair().unwrap();
+            let uid = Uid::current();
+
+            assert!(authorized(&client, uid));
+            assert!(!authorized(&client, Uid::from_raw(uid.as_raw() + 1)));
+        }
+
+        #[test]
+        fn require_private_dir() {
+            let uid = Uid::current();
+            let dir = test_dir().join("private");
+
+            private_dir(&dir, uid).unwrap();
+            assert_eq!(0o700, fs::metadata(&dir).unwrap().mode() & 0o777);
+
+            fs::set_permissions(&dir, fs::Permissions::from_mode(0o755)).unwrap();
+            let err = private_dir(&dir, uid).unwrap_err();
+            assert!(err.to_string().contains("must be private"), "{err}");
+
+            let other = Uid::from_raw(uid.as_raw() + 1);
+            fs::set_permissions(&dir, fs::Permissions::from_mode(0o700)).unwrap();
+            assert!(private_dir(&dir, other).is_err());
+        }
+
+        #[test]
+        fn stable_socket_path() {
+            let store = store("stable.otti");
+            let path = socket_in(test_dir(), &store);
+
+            assert_eq!(path, socket_in(test_dir(), &store));
+            assert_eq!(test_dir(), path.parent().unwrap());
+            assert_ne!(path, socket_in(test_dir(), &self::store("other.otti")));
+        }
+
+        #[test]
+        fn replace_stale_socket() {
+            let path = socket_in(test_dir(), &store("stale.otti"));
+
+            let listener = bind(&path).unwrap();
+            let err = bind(&path).unwrap_err();
+            assert!(err.to_string().contains("already running"), "{err}");
+
+            // The socket file stays behind, like after a crash.
+            drop(listener);
+            assert!(path.exists());
+            bind(&path).unwrap();
+        }
+
+        #[test]
+        fn answer_requests() {
+            let store = store("answer.otti");
+            let modified = modified(&store).unwrap();
+
+            let (running, response) = send(&store, modified, &Request::Accounts);
+            assert!(running);
+            assert!(matches!(response, Response::Accounts(a) if a.is_empty()));
+
+            let (running, response) = send(&store, modified, &Request::Stop);
+            assert!(!running);
+            assert!(matches!(response, Response::Stopped));
+        }
+
+        #[test]
+        fn lock_on_store_change() {
+            let store = store("changed.otti");
+            let modified = modified(&store).unwrap() - Duration::from_secs(1);
+
+            let (running, response) = send(&store, modified, &Request::Accounts);
+            assert!(!running);
+            assert!(matches!(response, Response::Locked));
+        }
+
+        #[test]
+        fn stop_when_idle() {
+            let store = store("idle.otti");
+            let path = socket_in(test_dir(), &store);
+            let listener = bind(&path).unwrap();
+
+            let start = Instant::now();
+            run(&store, &path, listener, &[], Duration::from_millis(100)).unwrap();
+
+            assert!(start.elapsed() >= Duration::from_millis(100));
+            assert!(!path.exists());
+        }
+    }
+}
+
+#[cfg(not(unix))]
+mod imp {
+    use std::{path::Path, time::Duration};
+
+    use anyhow::{bail, Result};
+    use otti_core::Account;
+    use otti_store::Store;
+
+    use super::{Request, Response};
+
+    #[allow(clippy::unnecessary_wraps)]
+    pub fn request<A>(_store: &Store, _request: &Request) -> Result<Option<Response<A>>> {
+        Ok(None)
+    }
+
+    #[allow(clippy::unnecessary_wraps)]
+    pub fn is_running(_store: &Store) -> Result<bool> {
+        Ok(false)
+    }
+
+    pub fn start(
+        _store: &Store,
+        _config: Option<&Path>,
+        _accounts: &[Account],
+        _timeout: Duration,
+        _foreground: bool,
+    ) -> Result<()> {
+        bail!("the agent is only supported on Unix systems")
+    }
+
+    pub fn serve(_store: &Store, _timeout: Duration) -> Result<()> {
+        bail!("the agent is only supported on Unix systems")
+    }
+}
@@ -0,0 +1,235 @@
+//! Automatic export of the accounts into a backup directory, whenever they change. The exports
+//! are named after the provider's default export name, with a timestamp added, and only a limited
+//! amount of them is kept. Existing backups are never overwritten.
+//!
+//! Failing to write a backup isn't fatal, as the store itself was saved successfully. It's
+//! reported as warning instead.
+
+use std::{
+    fs,
+    io::{self, Write},
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{bail, Context, Result};
+use otti_core::Account;
+
+use crate::{
+    cli::{PasswordArgs, Provider},
+    config::AutoBackup,
+    password,
+};
+
+/// Attempts to find a free file name, before giving up.
+const MAX_ATTEMPTS: u32 = 1000;
+
+/// Write a backup of the accounts, if automatic backups are enabled.
+pub fn write(args: &AutoBackup, accounts: &[Account]) {
+    let Some(dir) = &args.dir else {
+        return;
+    };
+
+    match try_write(args, dir, accounts) {
+        Ok(path) => println!("Wrote automatic backup to {}", path.display()),
+        Err(e) => eprintln!("Warning: failed writing the automatic backup: {e:#}"),
+    }
+}
+
+fn try_write(args: &AutoBackup, dir: &Path, accounts: &[Account]) -> Result<PathBuf> {
+    let password = password::read(
+        PasswordArgs {
+            password_file: args.password_file.clone(),
+            password_command: args.password_command.clone(),
+            ..PasswordArgs::default()
+        },
+        "",
+    )?
+    .context(
+        "backups need a password, set with `--auto-backup-password-file` or \
+         `--auto-backup-password-command`, or in the config file",
+    )?;
+
+    let provider = Provider::from(args.provider);
+    let data = crate::encode(
+        provider,
+        accounts,
+        Some(&password),
+        &provider_csv::Columns::default(),
+    )?;
+
+    let (stem, extension) = split_name(provider.export_name(true));
+
+    fs::create_dir_all(dir)
+        .with_context(|| format!("failed creating backup directory `{}`", dir.display()))?;
+    let path = write_new(dir, stem, extension, &data)?;
+
+    if args.keep > 0 {
+        prune(dir, stem, extension, args.keep)?;
+    }
+
+    Ok(path)
+}
+
+/// Split the file name at the first dot, to keep multi-part extensions like `.json.aes` intact.
+fn split_name(name: &str) -> (&str, &str) {
+    name.split_once('.').unwrap_or((name, "backup"))
+}
+
+/// Write a new backup file, that is only accessible by the current user. If a backup with the
+/// same timestamp exists already, the timestamp is moved forward, to keep the backups in order.
+fn write_new(dir: &Path, stem: &str, extension: &str, content: &[u8]) -> Result<PathBuf> {
+    let now = SystemTime::now();
+
+    for attempt in 0..MAX_ATTEMPTS {
+        let time = now + Duration::from_millis(attempt.into());
+        let path = dir.join(format!("{stem}-{}.{extension}", timestamp(time)));
+
+        let mut options = fs::OpenOptions::new();
+        options.write(true).create_new(true);
+        #[cfg(unix)]
+        std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+
+        let mut file = match options.open(&path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => continue,
+            Err(e) => {
+                return Err(e).with_context(|| format!("failed creating `{}`", path.display()))
+            }
+        };
+
+        file.write_all(content)
+            .and_then(|()| file.sync_all())
+            .with_context(|| format!("failed writing `{}`", path.display()))?;
+
+        return Ok(path);
+    }
+
+    bail!(
+        "failed finding a free backup file name in `{}`",
+        dir.display()
+    )
+}
+
+/// Remove the oldest backups, so only the `keep` most recent ones are left. Backups are ordered by
+/// their name, as the timestamps sort in chronological order.
+fn prune(dir: &Path, stem: &str, extension: &str, keep: usize) -> Result<()> {
+    let prefix = format!("{stem}-");
+    let suffix = format!(".{extension}");
+
+    let mut backups = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let name = entry?.file_name();
+        if name
+            .to_str()
+            .is_some_and(|name| name.starts_with(&prefix) && name.ends_with(&suffix))
+        {
+            backups.push(name);
+        }
+    }
+
+    if backups.len() <= keep {
+        return Ok(());
+    }
+
+    backups.sort_unstable();
+    for name in &backups[..backups.len() - keep] {
+        let path = dir.join(name);
+        fs::remove_file(&path)
+            .with_context(|| format!("failed removing old backup `{}`", path.display()))?;
+    }
+
+    Ok(())
+}
+
+/// Format the point in time as compact ISO 8601 timestamp in UTC with milliseconds, like
+/// `20240131T235959.123Z`.
+fn timestamp(time: SystemTime) -> String {
+    let since_epoch = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let (secs, millis) = (since_epoch.as_secs(), since_epoch.subsec_millis());
+    let (days, secs) = (secs / 86400, secs % 86400);
+
+    // Conversion of the days since the epoch into a calendar date, taken from
+    // <http://howardhinnant.github.io/date_algorithms.html#civil_from_days>.
+    let z = days + 719_468;
+    let era = z / 146_097;
+    let doe = z % 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + u64::from(month <= 2);
+
+    format!(
+        "{year:04}{month:02}{day:02}T{:02}{:02}{:02}.{millis:03}Z",
+        secs / 3600,
+        secs % 3600 / 60,
+        secs % 60
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    #[test]
+    fn format_timestamp() {
+        let at = |millis| timestamp(UNIX_EPOCH + Duration::from_millis(millis));
+
+        assert_eq!("19700101T000000.000Z", at(0));
+        assert_eq!("20000229T000000.000Z", at(951_782_400_000));
+        assert_eq!("20231114T221320.042Z", at(1_700_000_000_042));
+    }
+
+    #[test]
+    fn keep_backups_of_the_same_time() {
+        let dir = std::env::temp_dir().join(format!("otti-auto-backup-new-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+
+        let first = write_new(&dir, "aegis-export", "json", b"1").unwrap();
+        let second = write_new(&dir, "aegis-export", "json", b"2").unwrap();
+
+        assert_ne!(first, second);
+        assert!(first < second, "later backups sort after earlier ones");
+        assert_eq!(b"1", fs::read(&first).unwrap().as_slice());
+        assert_eq!(b"2", fs::read(&second).unwrap().as_slice());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn prune_oldest_backups() {
+        let dir = std::env::temp_dir().join(format!("otti-auto-backup-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+
+        for name in [
+            "aegis-export-20240102T000000Z.json",
+            "aegis-export-20240101T000000Z.json",
+            "aegis-export-20240103T000000Z.json",
+            "other.json",
+        ] {
+            fs::write(dir.join(name), "").unwrap();
+        }
+
+        prune(&dir, "aegis-export", "json", 2).unwrap();
+
+        let mut names = fs::read_dir(&dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+            .collect::<Vec<_>>();
+        names.sort();
+        assert_eq!(
+            vec![
+                "aegis-export-20240102T000000Z.json",
+                "aegis-export-20240103T000000Z.json",
+                "other.json",
+            ],
+            names
+        );
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -5,8 +5,11 @@ use std::{
 };
 
 use anyhow::{ensure, Context, Result};
-use clap::{CommandFactory, Parser, Subcommand, ValueEnum, ValueHint};
+use clap::{Args, CommandFactory, Parser, Subcommand, ValueEnum, ValueHint};
 use clap_complete::Shell;
+use serde::{Deserialize, Serialize};
+
+use crate::agent;
 
 /// The one-time password (OTP for short) manager for the terminal, with interactive and fancy
 /// terminal user interface (TUI for short).
@@ -16,6 +19,31 @@ use clap_complete::Shell;
 #[derive(Parser)]
 #[command(author, version, propagate_version = true)]
 pub struct Opt {
+    /// Location of the config file, instead of `config.toml` in the user's config directory.
+    #[arg(long, global = true, env = "OTTI_CONFIG", value_hint = ValueHint::FilePath)]
+    pub config: Option<PathBuf>,
+    /// Location of the store file, instead of the default store in the user's data directory.
+    /// Can be set with the `OTTI_STORE` environment variable or the config file as well, which
+    /// have lower priority than a selected vault.
+    #[arg(long, global = true, value_hint = ValueHint::FilePath)]
+    pub store: Option<PathBuf>,
+    /// Name of a vault to use instead of the default store. Vaults are kept next to the default
+    /// store and allow to separate accounts, like for work and personal use.
+    #[arg(long, global = true, conflicts_with = "store")]
+    pub vault: Option<String>,
+    /// Cost of deriving the encryption key from the store password. Stores that were sealed
+    /// with a lower cost are upgraded the next time they're unlocked. Defaults to `moderate`,
+    /// unless set in the config file.
+    #[arg(long, global = true, value_enum)]
+    pub kdf_cost: Option<KdfCost>,
+    /// Don't harden the process, which otherwise locks the unlocked secrets into memory and
+    /// disables core dumps, to keep them from ending up on disk.
+    #[arg(long, global = true)]
+    pub no_harden: bool,
+    #[command(flatten)]
+    pub unlock: UnlockArgs,
+    #[command(flatten)]
+    pub auto_backup: AutoBackupArgs,
     #[command(subcommand)]
     pub cmd: Option<Command>,
 }
@@ -30,9 +58,15 @@ impl Opt {
 pub enum Command {
     /// Import OTP accounts from another application.
     Import {
-        /// Optional password if the file is protected.
-        #[arg(short, long)]
-        password: Option<String>,
+        #[command(flatten)]
+        password: PasswordArgs,
+        /// Optional key file to unlock the database, only supported for `KeePass`.
+        #[arg(short, long, value_hint = ValueHint::FilePath)]
+        key_file: Option<PathBuf>,
+        /// Map an account field to a differently named column, only supported for CSV files. Can
+        /// be repeated once for each field.
+        #[arg(long = "column", value_name = "FIELD=NAME", value_parser = parse_column)]
+        columns: Vec<(provider_csv::Field, String)>,
         /// Provider/application that this file came from.
         #[arg(value_enum)]
         provider: Provider,
@@ -42,9 +76,12 @@ pub enum Command {
     },
     /// Export OTP accounts to another application.
     Export {
-        /// Optional password to protect the file.
-        #[arg(short, long)]
-        password: Option<String>,
+        #[command(flatten)]
+        password: PasswordArgs,
+        /// Map an account field to a differently named column, only supported for CSV files. Can
+        /// be repeated once for each field.
+        #[arg(long = "column", value_name = "FIELD=NAME", value_parser = parse_column)]
+        columns: Vec<(provider_csv::Field, String)>,
         /// Provider/application that this file will be imported into.
         #[arg(value_enum)]
         provider: Provider,
@@ -53,6 +90,8 @@ pub enum Command {
         #[arg(value_hint = ValueHint::FilePath)]
         file: Option<PathBuf>,
     },
+    /// List the issuer and label of all accounts.
+    List,
     /// Search for a single account and print the current OTP.
     Show {
         /// Name of the issuer to search by.
@@ -60,6 +99,63 @@ pub enum Command {
         /// Optional label to further restrict the search to a single entry.
         label: Option<String>,
     },
+    /// Change the password of the store.
+    ///
+    /// If the store is unlocked with a key file or recovery key instead, the new password is
+    /// added as an additional slot.
+    Passwd,
+    /// Manage the slots, that can each unlock the store, like passwords, key files and recovery
+    /// keys.
+    Slots {
+        #[command(subcommand)]
+        cmd: SlotsCommand,
+    },
+    /// Share the store with other people, each unlocking it with their own age identity.
+    ///
+    /// To set up a shared store, import the accounts with `--identity` or add the recipients to
+    /// an existing store, then remove any other slots.
+    Recipients {
+        #[command(subcommand)]
+        cmd: RecipientsCommand,
+    },
+    /// Check the store itself for damage.
+    Store {
+        #[command(subcommand)]
+        cmd: StoreCommand,
+    },
+    /// Reconcile copies of the store, that were changed on different devices.
+    Sync {
+        #[command(subcommand)]
+        cmd: SyncCommand,
+    },
+    /// List the latest changes to the accounts, that are recorded inside the store.
+    History,
+    /// Revert the latest changes to the accounts, as listed by `history`. The revert is recorded
+    /// as a change itself, so it can be undone in turn.
+    Undo {
+        /// Amount of changes to revert.
+        #[arg(default_value_t = 1)]
+        count: usize,
+    },
+    /// Restore the store from one of the backups, that are kept whenever the store changes.
+    Restore {
+        /// Number of the backup to restore, where 1 is the most recent one. All available backups
+        /// are listed if omitted.
+        backup: Option<usize>,
+    },
+    /// Keep the unlocked accounts in memory, so commands don't ask for the password each time.
+    ///
+    /// While the agent runs, `show`, `list` and the TUI get the accounts from it. Only processes
+    /// of the same user can talk to the agent.
+    Agent {
+        #[command(subcommand)]
+        cmd: AgentCommand,
+    },
+    /// Show the configuration, that is loaded from the config file at startup.
+    Config {
+        #[command(subcommand)]
+        cmd: ConfigCommand,
+    },
     /// Generate auto-completion scripts for various shells.
     Completions {
         /// Shell to generate an auto-completion script for.
@@ -76,8 +172,241 @@ pub enum Command {
     },
 }
 
-/// Possible supported providers for data import/export.
+#[derive(Subcommand)]
+pub enum SlotsCommand {
+    /// List all slots of the store.
+    List,
+    /// Add a new slot to the store, without changing the existing ones.
+    Add {
+        #[command(subcommand)]
+        slot: NewSlot,
+    },
+    /// Remove a slot from the store. The last remaining slot can't be removed.
+    Remove {
+        /// Number of the slot, as shown in the slot list.
+        slot: usize,
+    },
+}
+
+#[derive(Clone, Copy, Subcommand)]
+pub enum StoreCommand {
+    /// Verify the store step by step, to tell apart a damaged store from a wrong password.
+    ///
+    /// If the store is damaged, the backups are checked as well and the most recent intact one
+    /// is offered for restoring.
+    Verify,
+}
+
+#[derive(Subcommand)]
+pub enum SyncCommand {
+    /// Merge the accounts of another copy into the store, like the conflict copies that
+    /// synchronization tools create.
+    ///
+    /// Accounts are matched by their secret and merged field by field. The higher HOTP counter
+    /// and the union of tags are taken automatically, while any other field that differs is
+    /// asked for. The other copy is left untouched.
+    Merge {
+        /// The common version both copies were changed from, like a backup from before the
+        /// conflict. Without it, the state after the latest change recorded in the history of
+        /// both copies is taken. If there is none, removed accounts are kept and each difference
+        /// is asked for.
+        #[arg(long, value_hint = ValueHint::FilePath)]
+        base: Option<PathBuf>,
+        /// The other copy of the store.
+        #[arg(value_hint = ValueHint::FilePath)]
+        other: PathBuf,
+    },
+}
+
+#[derive(Clone, Copy, Subcommand)]
+pub enum ConfigCommand {
+    /// Print the configuration in effect, including the defaults of all keys that aren't set and
+    /// the overrides of the `--kdf-cost` and `--auto-backup-*` options.
+    Show,
+    /// Print the location of the config file, whether it exists or not.
+    Path,
+}
+
+#[derive(Clone, Copy, Subcommand)]
+pub enum AgentCommand {
+    /// Unlock the store and start the agent in the background.
+    Start {
+        /// Seconds without any requests, after which the agent stops.
+        #[arg(long, default_value_t = agent::DEFAULT_TIMEOUT.as_secs())]
+        timeout: u64,
+        /// Keep the agent running in the foreground, instead of starting a background process.
+        #[arg(long)]
+        foreground: bool,
+    },
+    /// Stop the running agent, which removes the accounts from memory.
+    Stop,
+    /// Show whether an agent is running for the store.
+    Status,
+    /// Serve the accounts received through the standard input, as started by `agent start`.
+    #[command(hide = true)]
+    Serve {
+        #[arg(long)]
+        timeout: u64,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum RecipientsCommand {
+    /// List the public keys of all recipients.
+    List,
+    /// Add the public key of a new recipient.
+    Add {
+        /// Public key of the recipient, in the format `age1...`.
+        recipient: otti_store::Recipient,
+    },
+    /// Remove a recipient. If only recipients remain, the store is re-encrypted with a new key,
+    /// so the revoked recipient can't decrypt future changes.
+    Revoke {
+        /// Public key of the recipient, in the format `age1...`.
+        recipient: otti_store::Recipient,
+    },
+    /// Generate a new identity file, compatible with age, and print its public key.
+    Keygen {
+        /// Location of the new identity file.
+        #[arg(value_hint = ValueHint::FilePath)]
+        path: PathBuf,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum NewSlot {
+    /// Additional password.
+    Password,
+    /// Key file, whose whole content is the secret. A new file with random content is created, if
+    /// it doesn't exist yet.
+    KeyFile {
+        /// Location of the key file.
+        #[arg(value_hint = ValueHint::FilePath)]
+        path: PathBuf,
+    },
+    /// Random recovery key, that is printed once and meant to be written down.
+    RecoveryKey,
+}
+
+/// Credential to unlock the store with, instead of the password.
+#[derive(Args)]
+#[group(multiple = false)]
+pub struct UnlockArgs {
+    /// Unlock the store with a key file, that was added as slot before.
+    #[arg(long, global = true, value_hint = ValueHint::FilePath)]
+    pub store_key_file: Option<PathBuf>,
+    /// Prompt for a recovery key to unlock the store, that was added as slot before.
+    #[arg(long, global = true)]
+    pub recovery_key: bool,
+    /// Unlock the store with an age identity file, whose public key is a recipient of the store.
+    #[arg(long, global = true, value_hint = ValueHint::FilePath)]
+    pub identity: Option<PathBuf>,
+}
+
+/// Export of a backup in a provider's format, whenever the accounts change. It's a safety net
+/// against losing the store, that can be imported again by Otti or another application. All
+/// options can be set in the config file as well, which they take precedence over.
+#[derive(Args)]
+#[command(next_help_heading = "Automatic backups")]
+pub struct AutoBackupArgs {
+    /// Directory to write a backup export into, whenever the accounts change. Automatic backups
+    /// are disabled, if not set.
+    #[arg(
+        id = "auto_backup_dir",
+        long = "auto-backup-dir",
+        global = true,
+        env = "OTTI_AUTO_BACKUP_DIR",
+        value_hint = ValueHint::DirPath
+    )]
+    pub dir: Option<PathBuf>,
+    /// Provider, whose format the backups are exported in. Only providers with encrypted exports
+    /// are supported. Defaults to `aegis`.
+    #[arg(
+        id = "auto_backup_provider",
+        long = "auto-backup-provider",
+        global = true,
+        env = "OTTI_AUTO_BACKUP_PROVIDER",
+        value_enum
+    )]
+    pub provider: Option<BackupProvider>,
+    /// Amount of backups to keep, after which the oldest ones are removed. A value of 0 keeps all
+    /// of them. Defaults to 10.
+    #[arg(
+        id = "auto_backup_keep",
+        long = "auto-backup-keep",
+        global = true,
+        env = "OTTI_AUTO_BACKUP_KEEP"
+    )]
+    pub keep: Option<usize>,
+    /// Read the password of the backups from the first line of a file.
+    #[arg(
+        id = "auto_backup_password_file",
+        long = "auto-backup-password-file",
+        global = true,
+        env = "OTTI_AUTO_BACKUP_PASSWORD_FILE",
+        value_hint = ValueHint::FilePath
+    )]
+    pub password_file: Option<PathBuf>,
+    /// Run a shell command and use the first line of its output as password of the backups.
+    #[arg(
+        id = "auto_backup_password_command",
+        long = "auto-backup-password-command",
+        global = true,
+        env = "OTTI_AUTO_BACKUP_PASSWORD_COMMAND",
+        value_name = "COMMAND",
+        value_hint = ValueHint::CommandString,
+        conflicts_with = "auto_backup_password_file"
+    )]
+    pub password_command: Option<String>,
+}
+
+/// Presets for the cost of the store's key derivation.
 #[derive(Clone, Copy, ValueEnum)]
+pub enum KdfCost {
+    /// Minimum cost, for slow devices.
+    Low,
+    /// Balance between security and unlock time.
+    Moderate,
+    /// High cost, for sensitive data on fast devices.
+    High,
+}
+
+impl KdfCost {
+    pub fn cost(self) -> otti_store::Cost {
+        match self {
+            Self::Low => otti_store::Cost::LOW,
+            Self::Moderate => otti_store::Cost::MODERATE,
+            Self::High => otti_store::Cost::HIGH,
+        }
+    }
+}
+
+/// Password for an import or export file. Only one of the sources can be used at a time, and the
+/// file is considered unprotected if none is given.
+#[derive(Args, Default)]
+#[group(multiple = false)]
+pub struct PasswordArgs {
+    /// Password of the file. Prefer any of the other password options, as the value ends up in
+    /// the shell history and is visible to other processes.
+    #[arg(short, long)]
+    pub password: Option<String>,
+    /// Prompt for the password interactively.
+    #[arg(long)]
+    pub password_prompt: bool,
+    /// Read the password from the first line of a file.
+    #[arg(long, value_hint = ValueHint::FilePath)]
+    pub password_file: Option<PathBuf>,
+    /// Read the password from the first line of an already open file descriptor (Unix only).
+    #[arg(long, value_name = "FD")]
+    pub password_fd: Option<u32>,
+    /// Run a shell command and use the first line of its output as password.
+    #[arg(long, value_name = "COMMAND", value_hint = ValueHint::CommandString)]
+    pub password_command: Option<String>,
+}
+
+/// Possible supported providers for data import/export.
+#[derive(Clone, Copy, ValueEnum, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
 pub enum Provider {
     /// Aegis authenticator.
     Aegis,
@@ -85,6 +414,44 @@ pub enum Provider {
     AndOtp,
     /// Authenticator Pro.
     AuthPro,
+    /// Bitwarden password manager (unencrypted JSON export only).
+    Bitwarden,
+    /// Comma-separated values, with a header row that names the columns.
+    Csv,
+    /// `KeePass` password database in KDBX4 format (import only).
+    KeePass,
+    /// `OTPClient` GTK application.
+    OtpClient,
+    /// Steam Desktop Authenticator, with the `manifest.json` or a single `.maFile` as import file
+    /// and a folder as export target.
+    Steam,
+    /// Plain text file with one `otpauth://` URL per line.
+    UriList,
+}
+
+/// Providers, whose exports can be protected with a password, as needed for automatic backups.
+#[derive(Clone, Copy, ValueEnum, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum BackupProvider {
+    /// Aegis authenticator.
+    Aegis,
+    /// Android OTP Authenticator.
+    AndOtp,
+    /// Authenticator Pro.
+    AuthPro,
+    /// `OTPClient` GTK application.
+    OtpClient,
+}
+
+impl From<BackupProvider> for Provider {
+    fn from(value: BackupProvider) -> Self {
+        match value {
+            BackupProvider::Aegis => Self::Aegis,
+            BackupProvider::AndOtp => Self::AndOtp,
+            BackupProvider::AuthPro => Self::AuthPro,
+            BackupProvider::OtpClient => Self::OtpClient,
+        }
+    }
 }
 
 impl Provider {
@@ -113,10 +480,31 @@ impl Provider {
                     "auth-pro-export.json"
                 }
             }
+            Self::Bitwarden => "bitwarden-export.json",
+            Self::Csv => "csv-export.csv",
+            Self::KeePass => "keepass-export.kdbx",
+            Self::OtpClient => {
+                if with_password {
+                    "otpclient-export.enc"
+                } else {
+                    "otpclient-export.json"
+                }
+            }
+            Self::Steam => "steam-export",
+            Self::UriList => "uri-list-export.txt",
         }
     }
 }
 
+/// Parse a column mapping in the form `<field>=<name>`.
+fn parse_column(value: &str) -> Result<(provider_csv::Field, String)> {
+    let (field, name) = value
+        .split_once('=')
+        .context("column mapping must be in the form `FIELD=NAME`")?;
+
+    Ok((field.trim().parse()?, name.trim().to_owned()))
+}
+
 /// Generate shell completions, written to the standard output.
 #[allow(clippy::unnecessary_wraps)]
 pub fn completions(shell: Shell) -> Result<()> {
@@ -0,0 +1,538 @@
+//! Configuration file, that changes the defaults of Otti. It's loaded at startup from
+//! `config.toml` in the user's config directory, or the file given with `--config`. All keys are
+//! optional, and options on the command line or in the environment take precedence over them.
+//!
+//! The keys are described in the README, and `otti config show` prints the configuration in
+//! effect.
+
+use std::{
+    fmt, fs, io,
+    path::{Path, PathBuf},
+    str::FromStr,
+    time::Duration,
+};
+
+use anyhow::{ensure, Context, Result};
+use clap::ValueEnum;
+use directories::ProjectDirs;
+use ratatui::style;
+use serde::{
+    de::{self, value::MapAccessDeserializer, MapAccess, Unexpected, Visitor},
+    Deserialize, Deserializer, Serialize, Serializer,
+};
+
+use crate::cli::{AutoBackupArgs, BackupProvider, KdfCost, Opt};
+
+/// Settings of all parts of the application.
+#[derive(Default, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct Config {
+    /// Location of the store file.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub store: Option<PathBuf>,
+    /// Cost of the store's key derivation.
+    pub kdf_cost: Cost,
+    pub ui: Ui,
+    pub clipboard: Clipboard,
+    pub colors: Colors,
+    pub dialogs: Dialogs,
+    pub auto_backup: AutoBackup,
+}
+
+impl Config {
+    /// Load the configuration from the given file, or the default location if none is given.
+    /// Only a missing file at the default location falls back to the default configuration.
+    pub fn load(path: Option<&Path>) -> Result<Self> {
+        let (path, explicit) = match path {
+            Some(path) => (path.to_owned(), true),
+            None => (self::path()?, false),
+        };
+
+        match fs::read_to_string(&path) {
+            Ok(content) => content
+                .parse()
+                .with_context(|| format!("invalid config file `{}`", path.display())),
+            Err(e) if e.kind() == io::ErrorKind::NotFound && !explicit => Ok(Self::default()),
+            Err(e) => {
+                Err(e).with_context(|| format!("failed reading config file `{}`", path.display()))
+            }
+        }
+    }
+
+    /// Override the settings with the options from the command line and environment.
+    pub fn apply(&mut self, opt: &Opt) {
+        if let Some(cost) = opt.kdf_cost {
+            self.kdf_cost = Cost::Preset(cost);
+        }
+
+        self.auto_backup.apply(&opt.auto_backup);
+    }
+}
+
+impl FromStr for Config {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let config = toml::from_str::<Self>(s)?;
+
+        ensure!(
+            config.auto_backup.password_file.is_none()
+                || config.auto_backup.password_command.is_none(),
+            "only one of `auto-backup.password-file` and `auto-backup.password-command` can be set"
+        );
+
+        Ok(config)
+    }
+}
+
+/// Default location of the configuration file, in the user's config directory.
+pub fn path() -> Result<PathBuf> {
+    ProjectDirs::from("rocks", "dnaka91", "otti")
+        .map(|dirs| dirs.config_dir().join("config.toml"))
+        .context("failed finding the user's config directory")
+}
+
+/// Cost of the key derivation, either as one of the presets or with custom parameters.
+#[derive(Clone, Copy)]
+pub enum Cost {
+    Preset(KdfCost),
+    Custom(otti_store::Cost),
+}
+
+impl Cost {
+    pub fn get(self) -> otti_store::Cost {
+        match self {
+            Self::Preset(preset) => preset.cost(),
+            Self::Custom(cost) => cost,
+        }
+    }
+}
+
+impl Default for Cost {
+    fn default() -> Self {
+        Self::Preset(KdfCost::Moderate)
+    }
+}
+
+/// Custom key derivation parameters, as written in the file.
+#[derive(Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+struct CustomCost {
+    iterations: u32,
+    memory: u32,
+}
+
+impl Serialize for Cost {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            Self::Preset(preset) => preset
+                .to_possible_value()
+                .expect("no skipped presets")
+                .get_name()
+                .serialize(serializer),
+            Self::Custom(cost) => CustomCost {
+                iterations: cost.iterations,
+                memory: cost.memory,
+            }
+            .serialize(serializer),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Cost {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct CostVisitor;
+
+        impl<'de> Visitor<'de> for CostVisitor {
+            type Value = Cost;
+
+            fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                f.write_str(
+                    "a preset of `low`, `moderate` or `high`, or a table of `iterations` and \
+                     `memory`",
+                )
+            }
+
+            fn visit_str<E: de::Error>(self, v: &str) -> Result<Self::Value, E> {
+                KdfCost::from_str(v, false)
+                    .map(Cost::Preset)
+                    .map_err(|_| E::invalid_value(Unexpected::Str(v), &self))
+            }
+
+            fn visit_map<A: MapAccess<'de>>(self, map: A) -> Result<Self::Value, A::Error> {
+                let CustomCost { iterations, memory } =
+                    CustomCost::deserialize(MapAccessDeserializer::new(map))?;
+
+                let bounds = otti_store::Cost::ITERATIONS;
+                if !bounds.contains(&iterations) {
+                    return Err(de::Error::custom(format!(
+                        "iterations must be within {} and {}",
+                        bounds.start(),
+                        bounds.end()
+                    )));
+                }
+
+                let bounds = otti_store::Cost::MEMORY;
+                if !bounds.contains(&memory) {
+                    return Err(de::Error::custom(format!(
+                        "memory must be within {} and {} KiB",
+                        bounds.start(),
+                        bounds.end()
+                    )));
+                }
+
+                Ok(Cost::Custom(otti_store::Cost { iterations, memory }))
+            }
+        }
+
+        deserializer.deserialize_any(CostVisitor)
+    }
+}
+
+/// Behavior of the TUI.
+#[derive(Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct Ui {
+    /// Interval between refreshes.
+    pub tick_interval: TickInterval,
+}
+
+impl Default for Ui {
+    fn default() -> Self {
+        Self {
+            tick_interval: TickInterval(Duration::from_secs(1)),
+        }
+    }
+}
+
+/// Refresh interval of the TUI in milliseconds, which is limited to a minimum to not keep the CPU
+/// busy.
+#[derive(Clone, Copy, Deserialize, Serialize)]
+#[serde(try_from = "u64", into = "u64")]
+pub struct TickInterval(pub Duration);
+
+impl TickInterval {
+    const MIN: u64 = 100;
+}
+
+impl TryFrom<u64> for TickInterval {
+    type Error = String;
+
+    fn try_from(value: u64) -> Result<Self, Self::Error> {
+        if value < Self::MIN {
+            return Err(format!("must be at least {} milliseconds", Self::MIN));
+        }
+
+        Ok(Self(Duration::from_millis(value)))
+    }
+}
+
+impl From<TickInterval> for u64 {
+    fn from(value: TickInterval) -> Self {
+        value.0.as_millis().try_into().unwrap_or(Self::MAX)
+    }
+}
+
+/// Copying of codes to the clipboard in the TUI.
+#[derive(Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct Clipboard {
+    pub enabled: bool,
+    /// Seconds, after which a copied code is cleared again, or 0 to keep it.
+    pub clear_after: u64,
+}
+
+impl Default for Clipboard {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            clear_after: 0,
+        }
+    }
+}
+
+/// Colors of the TUI.
+#[derive(Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct Colors {
+    /// Filled part of the countdown until the next code.
+    pub gauge: Color,
+    /// Remaining part of the countdown until the next code.
+    pub gauge_background: Color,
+    /// Indicator of the selected account.
+    pub selection: Color,
+    /// Position in the list of accounts.
+    pub scrollbar: Color,
+    /// Background of the scrollbar.
+    pub scrollbar_track: Color,
+}
+
+impl Default for Colors {
+    fn default() -> Self {
+        Self {
+            gauge: Color(style::Color::Green),
+            gauge_background: Color(style::Color::DarkGray),
+            selection: Color(style::Color::Blue),
+            scrollbar: Color(style::Color::White),
+            scrollbar_track: Color(style::Color::DarkGray),
+        }
+    }
+}
+
+/// Terminal color, written as name, palette index or RGB value.
+#[derive(Clone, Copy, Deserialize, Serialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Color(pub style::Color);
+
+impl TryFrom<String> for Color {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse().map(Self).map_err(|_| {
+            format!(
+                "invalid color `{value}`, expected a name like `dark-gray`, a palette index or an \
+                 RGB value like `#a0b0c0`"
+            )
+        })
+    }
+}
+
+impl From<Color> for String {
+    fn from(value: Color) -> Self {
+        value.0.to_string()
+    }
+}
+
+/// Sizes of the dialogs in the TUI.
+#[derive(Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct Dialogs {
+    pub help: Size,
+    pub code: Size,
+}
+
+impl Default for Dialogs {
+    fn default() -> Self {
+        Self {
+            help: Size {
+                width: 70,
+                height: 10,
+            },
+            code: Size {
+                width: 20,
+                height: 5,
+            },
+        }
+    }
+}
+
+/// Size of a dialog in terminal cells, which must leave room for the border and content.
+#[derive(Clone, Copy, Deserialize, Serialize)]
+#[serde(try_from = "RawSize", into = "RawSize")]
+pub struct Size {
+    pub width: u16,
+    pub height: u16,
+}
+
+impl Size {
+    const MIN_HEIGHT: u16 = 3;
+    const MIN_WIDTH: u16 = 10;
+}
+
+#[derive(Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+struct RawSize {
+    width: u16,
+    height: u16,
+}
+
+impl TryFrom<RawSize> for Size {
+    type Error = String;
+
+    fn try_from(RawSize { width, height }: RawSize) -> Result<Self, Self::Error> {
+        if width < Self::MIN_WIDTH || height < Self::MIN_HEIGHT {
+            return Err(format!(
+                "dialogs must be at least {} cells wide and {} cells high",
+                Self::MIN_WIDTH,
+                Self::MIN_HEIGHT
+            ));
+        }
+
+        Ok(Self { width, height })
+    }
+}
+
+impl From<Size> for RawSize {
+    fn from(Size { width, height }: Size) -> Self {
+        Self { width, height }
+    }
+}
+
+/// Automatic backups, like the `--auto-backup-*` options.
+#[derive(Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct AutoBackup {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dir: Option<PathBuf>,
+    pub provider: BackupProvider,
+    pub keep: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub password_file: Option<PathBuf>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub password_command: Option<String>,
+}
+
+impl AutoBackup {
+    /// Override the settings with any of the given options. Both password sources are replaced
+    /// together, as only one of them can be used.
+    fn apply(&mut self, args: &AutoBackupArgs) {
+        if let Some(dir) = &args.dir {
+            self.dir = Some(dir.clone());
+        }
+        if let Some(provider) = args.provider {
+            self.provider = provider;
+        }
+        if let Some(keep) = args.keep {
+            self.keep = keep;
+        }
+        if args.password_file.is_some() || args.password_command.is_some() {
+            self.password_file.clone_from(&args.password_file);
+            self.password_command.clone_from(&args.password_command);
+        }
+    }
+}
+
+impl Default for AutoBackup {
+    fn default() -> Self {
+        Self {
+            dir: None,
+            provider: BackupProvider::Aegis,
+            keep: 10,
+            password_file: None,
+            password_command: None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn error(content: &str) -> String {
+        format!("{:#}", content.parse::<Config>().err().unwrap())
+    }
+
+    #[test]
+    fn roundtrip_default() {
+        let content = toml::to_string_pretty(&Config::default()).unwrap();
+        let config = content.parse::<Config>().unwrap();
+
+        assert_eq!(content, toml::to_string_pretty(&config).unwrap());
+        assert_eq!(otti_store::Cost::MODERATE, config.kdf_cost.get());
+        assert_eq!(Duration::from_secs(1), config.ui.tick_interval.0);
+    }
+
+    #[test]
+    fn parse_all_keys() {
+        let config = indoc::indoc! {r##"
+            store = "/tmp/store.otti"
+            kdf-cost = { iterations = 5, memory = 1024 }
+
+            [ui]
+            tick-interval = 500
+
+            [clipboard]
+            enabled = false
+            clear-after = 30
+
+            [colors]
+            gauge = "light-green"
+            gauge-background = "8"
+            selection = "#a0b0c0"
+            scrollbar = "white"
+            scrollbar-track = "dark gray"
+
+            [dialogs]
+            help = { width = 60, height = 12 }
+            code = { width = 30, height = 7 }
+
+            [auto-backup]
+            dir = "/tmp/backups"
+            provider = "and-otp"
+            keep = 3
+            password-command = "echo secret"
+        "##}
+        .parse::<Config>()
+        .unwrap();
+
+        assert_eq!(Some(Path::new("/tmp/store.otti")), config.store.as_deref());
+        assert_eq!(
+            otti_store::Cost {
+                iterations: 5,
+                memory: 1024
+            },
+            config.kdf_cost.get()
+        );
+        assert_eq!(Duration::from_millis(500), config.ui.tick_interval.0);
+        assert!(!config.clipboard.enabled);
+        assert_eq!(style::Color::Indexed(8), config.colors.gauge_background.0);
+        assert_eq!(
+            style::Color::Rgb(0xa0, 0xb0, 0xc0),
+            config.colors.selection.0
+        );
+        assert_eq!(7, config.dialogs.code.height);
+        assert!(matches!(
+            config.auto_backup.provider,
+            BackupProvider::AndOtp
+        ));
+        assert_eq!(3, config.auto_backup.keep);
+    }
+
+    #[test]
+    fn parse_readme_example() {
+        let readme = include_str!("../README.md");
+        let start = readme.find("```toml\n").unwrap() + 8;
+        let end = start + readme[start..].find("```").unwrap();
+
+        readme[start..end].parse::<Config>().unwrap();
+    }
+
+    #[test]
+    fn point_at_invalid_keys() {
+        let message = error("[ui]\ntick = 1000\n");
+        assert!(message.contains("line 2"), "{message}");
+        assert!(message.contains("unknown field `tick`"), "{message}");
+
+        let message = error("[colors]\nselection = \"blurple\"\n");
+        assert!(message.contains("line 2"), "{message}");
+        assert!(message.contains("selection = \"blurple\""), "{message}");
+        assert!(message.contains("invalid color `blurple`"), "{message}");
+
+        let message = error("[dialogs]\ncode = { width = 5, height = 5 }\n");
+        assert!(message.contains("code = { width = 5"), "{message}");
+
+        let message = error("kdf-cost = { iterations = 0, memory = 1024 }\n");
+        assert!(message.contains("kdf-cost = {"), "{message}");
+        assert!(message.contains("iterations must be within"), "{message}");
+
+        let message = error("kdf-cost = \"extreme\"\n");
+        assert!(message.contains("a preset of `low`"), "{message}");
+
+        let message = error("[auto-backup]\npassword-file = \"a\"\npassword-command = \"b\"\n");
+        assert!(message.contains("only one of"), "{message}");
+    }
+
+    #[test]
+    fn reject_unencrypted_backup_providers() {
+        let message = error("[auto-backup]\nprovider = \"uri-list\"\n");
+        assert!(message.contains("unknown variant `uri-list`"), "{message}");
+
+        let result = <Opt as clap::Parser>::try_parse_from([
+            "otti",
+            "--auto-backup-provider",
+            "uri-list",
+            "list",
+        ]);
+        assert!(result.is_err());
+    }
+}
@@ -0,0 +1,194 @@
+//! Hardening of the process against leaking unlocked secrets to disk. The account keys are locked
+//! into memory, so they're never written to swap, and core dumps are disabled, so a crash doesn't
+//! persist them either.
+//!
+//! Only the account keys are locked, as they're kept in memory for as long as the process runs.
+//! The store's master key and decrypted history only exist briefly while the store is unlocked
+//! or sealed, and are zeroized right after.
+//!
+//! Failing to harden the process isn't fatal, but reported as warning, as the accounts are still
+//! usable without it.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use otti_core::Account;
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+/// Enable the hardening for the rest of the process, which immediately disables core dumps.
+pub fn enable() {
+    ENABLED.store(true, Ordering::Relaxed);
+
+    if let Err(e) = imp::disable_core_dumps() {
+        eprintln!("Warning: {e:#}");
+    }
+}
+
+/// Whether the hardening was enabled, and is therefore expected by child processes as well.
+pub fn is_enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+/// Lock the keys of all accounts into memory, if the hardening is enabled.
+pub fn lock(accounts: &[Account]) {
+    if !is_enabled() {
+        return;
+    }
+
+    if let Err(e) = imp::lock(accounts) {
+        eprintln!("Warning: {e:#}");
+    }
+}
+
+#[cfg(unix)]
+mod imp {
+    use anyhow::{bail, Context, Result};
+    use nix::{
+        errno::Errno,
+        sys::{
+            mman,
+            resource::{self, Resource},
+        },
+    };
+    use otti_core::{Account, ExposeSecret};
+
+    pub fn disable_core_dumps() -> Result<()> {
+        resource::setrlimit(Resource::RLIMIT_CORE, 0, 0).context("failed disabling core dumps")?;
+
+        #[cfg(any(target_os = "android", target_os = "linux"))]
+        {
+            // SAFETY: The option only takes a plain integer argument and doesn't access memory.
+            let result = unsafe { libc::prctl(libc::PR_SET_DUMPABLE, 0) };
+            if result != 0 {
+                return Err(std::io::Error::last_os_error())
+                    .context("failed marking the process as not dumpable");
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn lock(accounts: &[Account]) -> Result<()> {
+        for account in accounts {
+            let key = account.secret.expose_secret();
+            if key.is_empty() {
+                continue;
+            }
+
+            // SAFETY: The range covers exactly the key's content, which stays alive during the
+            // call. Locking doesn't modify the memory, and the pages simply stay locked until the
+            // process exits.
+            match unsafe { mman::mlock(key.as_ptr().cast(), key.len()) } {
+                Ok(()) => {}
+                Err(Errno::ENOMEM | Errno::EPERM | Errno::EAGAIN) => {
+                    bail!(limit_exhausted(&memlock_limit()))
+                }
+                Err(e) => return Err(e).context("failed locking the secrets into memory"),
+            }
+        }
+
+        Ok(())
+    }
+
+    pub(super) fn limit_exhausted(limit: &str) -> String {
+        format!(
+            "failed locking the secrets into memory, as the memlock limit of {limit} is \
+             exhausted. Raise it with `ulimit -l`, or pass `--no-harden` to skip locking"
+        )
+    }
+
+    fn memlock_limit() -> String {
+        resource::getrlimit(Resource::RLIMIT_MEMLOCK)
+            .map_or_else(|_| "unknown".to_owned(), |(soft, _)| format_limit(soft))
+    }
+
+    pub(super) fn format_limit(limit: resource::rlim_t) -> String {
+        if limit == resource::RLIM_INFINITY {
+            "unlimited".to_owned()
+        } else {
+            format!("{} KiB", limit / 1024)
+        }
+    }
+}
+
+#[cfg(not(unix))]
+mod imp {
+    use anyhow::Result;
+    use otti_core::Account;
+
+    #[allow(clippy::unnecessary_wraps)]
+    pub fn disable_core_dumps() -> Result<()> {
+        Ok(())
+    }
+
+    #[allow(clippy::unnecessary_wraps)]
+    pub fn lock(_accounts: &[Account]) -> Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use nix::sys::resource::{self, Resource};
+    use otti_core::{Algorithm, Key, Metadata, Otp};
+
+    use super::*;
+
+    #[test]
+    fn disable_core_dumps() {
+        imp::disable_core_dumps().unwrap();
+
+        assert_eq!((0, 0), resource::getrlimit(Resource::RLIMIT_CORE).unwrap());
+    }
+
+    #[test]
+    fn lock_accounts() {
+        let accounts = (1..=3)
+            .map(|i| Account {
+                label: format!("account {i}"),
+                secret: Key::new(vec![i; 20]),
+                digits: 6,
+                otp: Otp::Totp { window: 30 },
+                algorithm: Algorithm::Sha1,
+                issuer: None,
+                meta: Metadata::default(),
+                extras: BTreeMap::new(),
+            })
+            .collect::<Vec<_>>();
+
+        enable();
+        assert!(is_enabled());
+        imp::lock(&accounts).unwrap();
+
+        #[cfg(target_os = "linux")]
+        {
+            let status = std::fs::read_to_string("/proc/self/status").unwrap();
+            let locked = status
+                .lines()
+                .find_map(|line| line.strip_prefix("VmLck:"))
+                .and_then(|value| {
+                    value
+                        .trim()
+                        .trim_end_matches("kB")
+                        .trim()
+                        .parse::<u64>()
+                        .ok()
+                })
+                .unwrap();
+            assert!(locked > 0, "no memory locked");
+        }
+    }
+
+    #[test]
+    fn report_memlock_limit() {
+        assert_eq!("unlimited", imp::format_limit(resource::RLIM_INFINITY));
+        assert_eq!("64 KiB", imp::format_limit(64 * 1024));
+
+        let message = imp::limit_exhausted(&imp::format_limit(64 * 1024));
+        assert!(message.contains("memlock limit of 64 KiB"), "{message}");
+        assert!(message.contains("`ulimit -l`"), "{message}");
+        assert!(message.contains("`--no-harden`"), "{message}");
+    }
+}
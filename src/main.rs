@@ -6,115 +6,318 @@
 )]
 
 use std::{
-    fs,
-    path::PathBuf,
-    time::{Duration, UNIX_EPOCH},
+    env, fs,
+    io::Write,
+    path::{Path, PathBuf},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
 };
 
-use anyhow::Result;
+use anyhow::{bail, ensure, Context, Result};
 use arboard::Clipboard;
 use crossbeam_channel::select;
 use crossterm::event::KeyCode;
+use otti_store::{Change, Credential, Entry, Identity, Outcome, Secret, Store};
 use ratatui::{
     layout::{Constraint, Direction, Layout},
-    style::{Color, Style},
+    style::Style,
     widgets::{Block, Borders, Gauge},
 };
-use secrecy::SecretString;
+use secrecy::{ExposeSecret, SecretString};
 use widgets::CodeDialog;
 
 use crate::{
-    cli::{Command, Opt, Provider},
+    cli::{
+        AgentCommand, Command, ConfigCommand, NewSlot, Opt, Provider, RecipientsCommand,
+        SlotsCommand, StoreCommand, SyncCommand, UnlockArgs,
+    },
+    config::Config,
     widgets::{HelpDialog, List, ListState, ScrollBar},
 };
 
+mod agent;
+mod auto_backup;
 mod cli;
+mod config;
+mod harden;
+mod password;
+mod prompt;
+mod sync;
 mod terminal;
+#[cfg(test)]
+mod test_alloc;
 mod widgets;
 
 fn main() -> Result<()> {
     let opt = Opt::parse();
+    if !opt.no_harden {
+        harden::enable();
+    }
+    // The path is printed before loading the config, to help finding a broken one.
+    if let Some(Command::Config {
+        cmd: ConfigCommand::Path,
+    }) = opt.cmd
+    {
+        let path = opt.config.map_or_else(config::path, Ok)?;
+        println!("{}", path.display());
+        return Ok(());
+    }
 
-    opt.cmd.map_or_else(run, |cmd| match cmd {
-        Command::Import {
-            password,
-            provider,
-            file,
-        } => import(password, provider, file),
-        Command::Export {
-            password,
-            provider,
-            file,
-        } => export(password, provider, file),
-        Command::Show { issuer, label } => show(&issuer, label.as_deref()),
-        Command::Completions { shell } => cli::completions(shell),
-        Command::Manpages { dir } => cli::manpages(&dir),
-    })
+    let mut config = Config::load(opt.config.as_deref())?;
+    config.apply(&opt);
+
+    let store = || {
+        match (&opt.store, &opt.vault) {
+            (Some(path), _) => Ok(Store::at(path)),
+            (None, Some(name)) => Store::vault(name),
+            (None, None) => match env::var_os("OTTI_STORE") {
+                Some(path) => Ok(Store::at(path)),
+                None => config
+                    .store
+                    .as_ref()
+                    .map_or_else(Store::new, |path| Ok(Store::at(path))),
+            },
+        }
+        .map(|store| store.with_cost(config.kdf_cost.get()))
+    };
+
+    opt.cmd.map_or_else(
+        || run(&store()?, &opt.unlock, &config),
+        |cmd| match cmd {
+            Command::Import {
+                password,
+                key_file,
+                columns,
+                provider,
+                file,
+            } => import(
+                &store()?,
+                &opt.unlock,
+                &config.auto_backup,
+                password::read(password, "Backup password:")?.as_ref(),
+                key_file,
+                columns,
+                provider,
+                file,
+            ),
+            Command::Export {
+                password,
+                columns,
+                provider,
+                file,
+            } => export(
+                &store()?,
+                &opt.unlock,
+                password::read(password, "Backup password:")?.as_ref(),
+                columns,
+                provider,
+                file,
+            ),
+            Command::List => list(&store()?, &opt.unlock),
+            Command::Show { issuer, label } => {
+                show(&store()?, &opt.unlock, &issuer, label.as_deref())
+            }
+            Command::Passwd => passwd(&store()?, &opt.unlock),
+            Command::Slots { cmd } => slots(&store()?, &opt.unlock, cmd),
+            Command::Recipients { cmd } => recipients(&store()?, &opt.unlock, cmd),
+            Command::Store { cmd } => match cmd {
+                StoreCommand::Verify => verify(&store()?, &opt.unlock, &config.auto_backup),
+            },
+            Command::Sync { cmd } => sync(&store()?, &opt.unlock, &config.auto_backup, cmd),
+            Command::History => history(&store()?, &opt.unlock),
+            Command::Undo { count } => undo(&store()?, &opt.unlock, &config.auto_backup, count),
+            Command::Restore { backup } => {
+                restore(&store()?, &opt.unlock, &config.auto_backup, backup)
+            }
+            Command::Agent { cmd } => agent(&store()?, &opt.unlock, opt.config.as_deref(), cmd),
+            Command::Config { cmd } => match cmd {
+                ConfigCommand::Show => {
+                    print!("{}", toml::to_string_pretty(&config)?);
+                    Ok(())
+                }
+                ConfigCommand::Path => unreachable!("handled before loading the config"),
+            },
+            Command::Completions { shell } => cli::completions(shell),
+            Command::Manpages { dir } => cli::manpages(&dir),
+        },
+    )
 }
 
-fn import(password: Option<String>, provider: Provider, file: PathBuf) -> Result<()> {
-    let file = fs::read(file)?;
+#[allow(clippy::too_many_arguments)]
+fn import(
+    store: &Store,
+    unlock: &UnlockArgs,
+    auto_backup: &config::AutoBackup,
+    password: Option<&SecretString>,
+    key_file: Option<PathBuf>,
+    columns: Vec<(provider_csv::Field, String)>,
+    provider: Provider,
+    file: PathBuf,
+) -> Result<()> {
+    ensure!(
+        key_file.is_none() || matches!(provider, Provider::KeePass),
+        "key files are only supported for KeePass databases"
+    );
+    ensure!(
+        columns.is_empty() || matches!(provider, Provider::Csv),
+        "column mappings are only supported for CSV files"
+    );
+
+    let path = file;
+    let file = fs::read(&path)?;
+    let key_file = key_file.map(fs::read).transpose()?;
 
     let accounts = match provider {
         Provider::Aegis => provider_aegis::load(&mut file.as_slice(), password)?,
         Provider::AndOtp => provider_andotp::load(&mut file.as_slice(), password)?,
         Provider::AuthPro => provider_authpro::load(&mut file.as_slice(), password)?,
+        Provider::Bitwarden => provider_bitwarden::load(&mut file.as_slice(), password)?,
+        Provider::Csv => {
+            let import = provider_csv::load(&mut file.as_slice(), password, &csv_columns(columns))?;
+            for error in &import.errors {
+                eprintln!("Skipping the row at line {}: {}", error.line, error.source);
+            }
+            import.accounts
+        }
+        Provider::KeePass => {
+            provider_keepass::load(&mut file.as_slice(), password, key_file.as_deref())?
+        }
+        Provider::OtpClient => provider_otpclient::load(&mut file.as_slice(), password)?,
+        Provider::Steam => provider_steam::load_path(&path, &mut file.as_slice(), password)?,
+        Provider::UriList => {
+            let import = provider_urilist::load(&mut file.as_slice(), password)?;
+            for error in &import.errors {
+                eprintln!("Skipping line {}: {}", error.line, error.source);
+            }
+            import.accounts
+        }
     };
 
     println!("Opened backup file");
 
-    if otti_store::exists()? {
+    if store.exists()? {
         println!("An OTP store already exists");
 
-        let resp = rprompt::prompt_reply("Overwrite? [yN] ")?;
-
-        if !matches!(resp.as_str(), "y" | "Y") {
+        if !prompt::confirm("Overwrite?")? {
             println!("Import cancelled");
             return Ok(());
         }
+
+        // Sealing into the existing store keeps its slots and records the replaced accounts in
+        // its history.
+        store.seal(&accounts, &credential(unlock, "Store password:")?)?;
+        println!("Imported {} accounts", accounts.len());
+        println!("The previous accounts can be brought back with `otti undo`");
+        auto_backup::write(auto_backup, &accounts);
+
+        return Ok(());
     }
 
     println!("Imported {} accounts", accounts.len());
 
-    let password = SecretString::new(rpassword::prompt_password("Store password:")?);
+    let credential = match &unlock.identity {
+        Some(path) => read_identity(path)?,
+        None => Credential::Password(prompt::new_password("Store password:")?),
+    };
 
-    otti_store::seal(&accounts, &password)?;
+    store.create(&accounts, &credential)?;
+    auto_backup::write(auto_backup, &accounts);
 
     Ok(())
 }
 
-fn export(file_password: Option<String>, provider: Provider, file: Option<PathBuf>) -> Result<()> {
-    let password = SecretString::new(rpassword::prompt_password("Store password:")?);
-    let accounts = otti_store::open(&password)?;
+fn export(
+    store: &Store,
+    unlock: &UnlockArgs,
+    file_password: Option<&SecretString>,
+    columns: Vec<(provider_csv::Field, String)>,
+    provider: Provider,
+    file: Option<PathBuf>,
+) -> Result<()> {
+    if matches!(provider, Provider::KeePass) {
+        bail!("exporting to KeePass databases is not supported");
+    }
+    ensure!(
+        columns.is_empty() || matches!(provider, Provider::Csv),
+        "column mappings are only supported for CSV files"
+    );
+
+    let accounts = store.open(&credential(unlock, "Store password:")?)?;
+    harden::lock(&accounts);
     let file = file.unwrap_or_else(|| PathBuf::from(provider.export_name(file_password.is_some())));
 
-    let mut data = Vec::new();
+    if matches!(provider, Provider::Steam) {
+        fs::create_dir_all(&file)?;
 
-    match provider {
-        Provider::Aegis => provider_aegis::save(&mut data, &accounts, file_password)?,
-        Provider::AndOtp => provider_andotp::save(&mut data, &accounts, file_password)?,
-        Provider::AuthPro => provider_authpro::save(&mut data, &accounts, file_password)?,
+        for (name, content) in provider_steam::save(&accounts, file_password)? {
+            fs::write(file.join(name), content)?;
+        }
+
+        return Ok(());
     }
 
+    let data = encode(provider, &accounts, file_password, &csv_columns(columns))?;
     fs::write(file, data)?;
 
     Ok(())
 }
 
-fn show(issuer: &str, label: Option<&str>) -> Result<()> {
-    let password = SecretString::new(rpassword::prompt_password("Password:")?);
+/// Encode the accounts in the format of a provider, that exports into a single file.
+fn encode(
+    provider: Provider,
+    accounts: &[otti_core::Account],
+    password: Option<&SecretString>,
+    columns: &provider_csv::Columns,
+) -> Result<Vec<u8>> {
+    let mut data = Vec::new();
+
+    match provider {
+        Provider::Aegis => provider_aegis::save(&mut data, accounts, password)?,
+        Provider::AndOtp => provider_andotp::save(&mut data, accounts, password)?,
+        Provider::AuthPro => provider_authpro::save(&mut data, accounts, password)?,
+        Provider::Bitwarden => provider_bitwarden::save(&mut data, accounts, password)?,
+        Provider::Csv => provider_csv::save(&mut data, accounts, password, columns)?,
+        Provider::OtpClient => provider_otpclient::save(&mut data, accounts, password)?,
+        Provider::UriList => provider_urilist::save(&mut data, accounts, password)?,
+        Provider::KeePass => bail!("exporting to KeePass databases is not supported"),
+        Provider::Steam => bail!("Steam exports are written into a folder, not a single file"),
+    }
+
+    Ok(data)
+}
 
-    let accounts = otti_store::open(&password)?;
+/// Build the CSV column names from the default names, overridden by the user's mappings.
+fn csv_columns(mappings: Vec<(provider_csv::Field, String)>) -> provider_csv::Columns {
+    let mut columns = provider_csv::Columns::default();
+    for (field, name) in mappings {
+        columns.set(field, name);
+    }
+
+    columns
+}
+
+fn list(store: &Store, unlock: &UnlockArgs) -> Result<()> {
+    for account in accounts(store, unlock)? {
+        match &account.issuer {
+            Some(issuer) => println!("{issuer} ({})", account.label),
+            None => println!("{}", account.label),
+        }
+    }
+
+    Ok(())
+}
+
+fn show(store: &Store, unlock: &UnlockArgs, issuer: &str, label: Option<&str>) -> Result<()> {
+    let accounts = accounts(store, unlock)?;
     let issuer = issuer.to_lowercase();
     let label = label.map(str::to_lowercase);
 
     let acc = accounts.iter().find(|a| {
         a.issuer
             .as_deref()
-            .map_or(false, |i| i.to_lowercase().contains(&issuer))
+            .is_some_and(|i| i.to_lowercase().contains(&issuer))
             && label
                 .as_deref()
-                .map_or(true, |l| a.label.to_lowercase().contains(l))
+                .is_none_or(|l| a.label.to_lowercase().contains(l))
     });
 
     match acc {
@@ -141,6 +344,514 @@ fn show(issuer: &str, label: Option<&str>) -> Result<()> {
     Ok(())
 }
 
+fn passwd(store: &Store, unlock: &UnlockArgs) -> Result<()> {
+    let current = credential(unlock, "Current password:")?;
+    store.open(&current)?;
+
+    let new = Credential::Password(prompt::new_password("New password:")?);
+
+    if matches!(current, Credential::Password(_)) {
+        store.replace_slot(&current, &new)?;
+        println!("Password changed");
+    } else {
+        store.add_slot(&current, &new)?;
+        println!("Password added as new slot");
+    }
+
+    println!("Existing backups are still protected by the previous password");
+
+    Ok(())
+}
+
+fn slots(store: &Store, unlock: &UnlockArgs, cmd: SlotsCommand) -> Result<()> {
+    match cmd {
+        SlotsCommand::List => {
+            for (i, kind) in store.slots()?.iter().enumerate() {
+                println!("{}: {kind}", i + 1);
+            }
+        }
+        SlotsCommand::Add { slot } => {
+            let current = credential(unlock, "Password:")?;
+            store.open(&current)?;
+
+            let new = match &slot {
+                NewSlot::Password => Credential::Password(prompt::new_password("New password:")?),
+                NewSlot::KeyFile { path } => key_file(path)?,
+                NewSlot::RecoveryKey => Credential::generate_recovery_key(),
+            };
+
+            let index = store.add_slot(&current, &new)?;
+            println!("Added {} as slot {}", new.kind(), index + 1);
+
+            if let Credential::RecoveryKey(key) = &new {
+                println!();
+                println!("    {}", key.expose_secret());
+                println!();
+                println!("Write the recovery key down and keep it in a safe place.");
+                println!("It's shown only this time and can't be recovered later.");
+            }
+        }
+        SlotsCommand::Remove { slot } => {
+            ensure!(
+                (1..=store.slots()?.len()).contains(&slot),
+                "slot {slot} doesn't exist"
+            );
+
+            let current = credential(unlock, "Password:")?;
+            store.remove_slot(&current, slot - 1)?;
+            println!("Removed slot {slot}");
+        }
+    }
+
+    Ok(())
+}
+
+fn recipients(store: &Store, unlock: &UnlockArgs, cmd: RecipientsCommand) -> Result<()> {
+    match cmd {
+        RecipientsCommand::List => {
+            for recipient in store.recipients()? {
+                println!("{recipient}");
+            }
+        }
+        RecipientsCommand::Add { recipient } => {
+            store.add_recipient(&credential(unlock, "Password:")?, &recipient)?;
+            println!("Added recipient {recipient}");
+        }
+        RecipientsCommand::Revoke { recipient } => {
+            let rotated = store.revoke_recipient(&credential(unlock, "Password:")?, &recipient)?;
+            println!("Revoked recipient {recipient}");
+
+            if !rotated {
+                println!("The store has other slots than recipients, so its key wasn't replaced.");
+                println!("The revoked recipient may still decrypt it, if they kept the old key.");
+            }
+            println!("Existing backups can still be decrypted by the revoked recipient");
+        }
+        RecipientsCommand::Keygen { path } => {
+            let identity = Identity::generate();
+            let recipient = identity.recipient();
+            let content = format!(
+                "# public key: {recipient}\n{}\n",
+                identity.to_secret_string().expose_secret()
+            );
+
+            write_secret_file(&path, content.as_bytes())?;
+            println!("Public key: {recipient}");
+        }
+    }
+
+    Ok(())
+}
+
+fn agent(
+    store: &Store,
+    unlock: &UnlockArgs,
+    config: Option<&Path>,
+    cmd: AgentCommand,
+) -> Result<()> {
+    match cmd {
+        AgentCommand::Start {
+            timeout,
+            foreground,
+        } => {
+            let accounts = store.open(&credential(unlock, "Password:")?)?;
+            harden::lock(&accounts);
+            if !foreground {
+                println!("Starting agent for {}", store.path().display());
+            }
+
+            agent::start(
+                store,
+                config,
+                &accounts,
+                Duration::from_secs(timeout),
+                foreground,
+            )?;
+        }
+        AgentCommand::Stop => {
+            if agent::stop(store)? {
+                println!("Agent stopped");
+            } else {
+                println!("No agent running");
+            }
+        }
+        AgentCommand::Status => {
+            if agent::is_running(store)? {
+                println!("Agent running for {}", store.path().display());
+            } else {
+                println!("No agent running");
+            }
+        }
+        AgentCommand::Serve { timeout } => agent::serve(store, Duration::from_secs(timeout))?,
+    }
+
+    Ok(())
+}
+
+/// Get the accounts from the running agent, or unlock the store if there is none.
+fn accounts(store: &Store, unlock: &UnlockArgs) -> Result<Vec<otti_core::Account>> {
+    let accounts = match agent::fetch(store) {
+        Some(accounts) => accounts,
+        None => store.open(&credential(unlock, "Password:")?)?,
+    };
+
+    harden::lock(&accounts);
+    Ok(accounts)
+}
+
+/// Ask for the credential to unlock the store, which is the password, unless the user selected a
+/// key file, recovery key or identity instead.
+fn credential(unlock: &UnlockArgs, prompt: &str) -> Result<Credential> {
+    Ok(if let Some(path) = &unlock.identity {
+        read_identity(path)?
+    } else if let Some(path) = &unlock.store_key_file {
+        let content = fs::read(path)
+            .with_context(|| format!("failed reading key file `{}`", path.display()))?;
+        Credential::KeyFile(Secret::new(content))
+    } else if unlock.recovery_key {
+        Credential::RecoveryKey(prompt::password("Recovery key:")?)
+    } else {
+        Credential::Password(prompt::password(prompt)?)
+    })
+}
+
+fn read_identity(path: &Path) -> Result<Credential> {
+    let content = fs::read_to_string(path)
+        .with_context(|| format!("failed reading identity file `{}`", path.display()))?;
+
+    Ok(Credential::Identity(Identity::from_file(&content)?))
+}
+
+/// Load an existing key file, or create a new one with random content if it doesn't exist yet.
+fn key_file(path: &Path) -> Result<Credential> {
+    if path.try_exists()? {
+        let content = fs::read(path)
+            .with_context(|| format!("failed reading key file `{}`", path.display()))?;
+        ensure!(
+            !content.is_empty(),
+            "key file `{}` is empty",
+            path.display()
+        );
+
+        return Ok(Credential::KeyFile(Secret::new(content)));
+    }
+
+    let credential = Credential::generate_key_file();
+    let Credential::KeyFile(content) = &credential else {
+        unreachable!();
+    };
+
+    write_secret_file(path, content.expose_secret())?;
+    println!("Created new key file at {}", path.display());
+
+    Ok(credential)
+}
+
+/// Create a new file, that is only accessible by the current user, without overwriting any
+/// existing file.
+fn write_secret_file(path: &Path, content: &[u8]) -> Result<()> {
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+
+    options
+        .open(path)
+        .and_then(|mut file| file.write_all(content))
+        .with_context(|| format!("failed writing `{}`", path.display()))
+}
+
+fn sync(
+    store: &Store,
+    unlock: &UnlockArgs,
+    auto_backup: &config::AutoBackup,
+    cmd: SyncCommand,
+) -> Result<()> {
+    match cmd {
+        SyncCommand::Merge { base, other } => {
+            let credential = credential(unlock, "Password:")?;
+            let (local, generation) = store.open_tracked(&credential)?;
+            harden::lock(&local);
+
+            let (remote, remote_history) = open_copy(&other, &credential)?;
+            harden::lock(&remote);
+            let base = match base {
+                Some(path) => open_copy(&path, &credential)?.0,
+                None => {
+                    let history = store.history(&credential)?;
+                    otti_store::common_base(&local, &history, &remote_history).unwrap_or_else(
+                        || {
+                            eprintln!(
+                                "Warning: the copies share no recorded change and no `--base` was \
+                                 given, so accounts removed on either side can't be detected and \
+                                 are kept"
+                            );
+                            Vec::new()
+                        },
+                    )
+                }
+            };
+            harden::lock(&base);
+
+            let (accounts, summary) = sync::merge(&base, local, remote, |conflict| {
+                Ok(match conflict {
+                    sync::Conflict::Field {
+                        account,
+                        field,
+                        local,
+                        remote,
+                    } => {
+                        println!("{account} changed its {field} on both sides");
+                        println!("  local:  {local}");
+                        println!("  other:  {remote}");
+
+                        if prompt::confirm("Take the other value?")? {
+                            sync::Side::Remote
+                        } else {
+                            sync::Side::Local
+                        }
+                    }
+                    sync::Conflict::Removal { account, removed } => {
+                        if prompt::confirm(&format!(
+                            "{account} was removed on one side and modified on the other; keep it?"
+                        ))? {
+                            removed.opposite()
+                        } else {
+                            *removed
+                        }
+                    }
+                })
+            })?;
+
+            if summary.is_unchanged() {
+                println!("The store already contains all changes");
+                return Ok(());
+            }
+
+            store.seal_tracked(&accounts, &credential, generation)?;
+            println!(
+                "Merged {} added, {} removed and {} updated accounts, with {} conflicts",
+                summary.added, summary.removed, summary.updated, summary.conflicts
+            );
+            println!("`{}` can be removed now", other.display());
+            auto_backup::write(auto_backup, &accounts);
+        }
+    }
+
+    Ok(())
+}
+
+/// Open another copy of the store along with its history, which is tried with the store's
+/// credential first, as it's usually the same. Otherwise, the copy's own password is asked for.
+fn open_copy(
+    path: &Path,
+    credential: &Credential,
+) -> Result<(Vec<otti_core::Account>, Vec<Entry>)> {
+    let copy = Store::at(path).with_rehash(false);
+    let open = |credential: &Credential| -> Result<_, otti_store::Error> {
+        Ok((copy.open(credential)?, copy.history(credential)?))
+    };
+
+    match open(credential) {
+        Err(otti_store::Error::InvalidCredential(_)) => {
+            let password = prompt::password(&format!("Password for `{}`:", path.display()))?;
+            open(&Credential::Password(password))
+        }
+        result => result,
+    }
+    .with_context(|| format!("failed opening `{}`", path.display()))
+}
+
+fn history(store: &Store, unlock: &UnlockArgs) -> Result<()> {
+    let history = store.history(&credential(unlock, "Password:")?)?;
+    if history.is_empty() {
+        println!("No changes recorded");
+    }
+
+    for (i, entry) in history.iter().rev().enumerate() {
+        print_entry(i + 1, entry);
+    }
+
+    Ok(())
+}
+
+fn undo(
+    store: &Store,
+    unlock: &UnlockArgs,
+    auto_backup: &config::AutoBackup,
+    count: usize,
+) -> Result<()> {
+    ensure!(
+        count > 0,
+        "the amount of changes to undo must be at least 1"
+    );
+
+    let credential = credential(unlock, "Password:")?;
+    let history = store.history(&credential)?;
+    ensure!(
+        count <= history.len(),
+        "only {} changes are recorded",
+        history.len()
+    );
+
+    for (i, entry) in history.iter().rev().take(count).enumerate() {
+        print_entry(i + 1, entry);
+    }
+
+    if !prompt::confirm(&format!("Undo {count} changes?"))? {
+        println!("Undo cancelled");
+        return Ok(());
+    }
+
+    store.undo(&credential, count)?;
+    println!("Reverted {count} changes");
+    write_auto_backup(store, &credential, auto_backup);
+
+    Ok(())
+}
+
+/// Print a history entry with its number, where 1 is the most recent change.
+fn print_entry(number: usize, entry: &Entry) {
+    println!("{number}: {}", ago(entry.time));
+
+    for change in &entry.changes {
+        match change {
+            Change::Added(account) => println!("  added    {}", sync::name(account)),
+            Change::Removed(account) => println!("  removed  {}", sync::name(account)),
+            Change::Modified { before, after } => {
+                let (before, after) = (sync::name(before), sync::name(after));
+                if before == after {
+                    println!("  modified {after}");
+                } else {
+                    println!("  modified {before} -> {after}");
+                }
+            }
+        }
+    }
+}
+
+/// Rough age of the point in time, like `5 minutes ago`.
+fn ago(time: SystemTime) -> String {
+    let secs = time.elapsed().map_or(0, |elapsed| elapsed.as_secs());
+    let (amount, unit) = match secs {
+        0..60 => return "just now".to_owned(),
+        60..3600 => (secs / 60, "minute"),
+        3600..86400 => (secs / 3600, "hour"),
+        _ => (secs / 86400, "day"),
+    };
+
+    format!("{amount} {unit}{} ago", if amount == 1 { "" } else { "s" })
+}
+
+fn restore(
+    store: &Store,
+    unlock: &UnlockArgs,
+    auto_backup: &config::AutoBackup,
+    backup: Option<usize>,
+) -> Result<()> {
+    let Some(backup) = backup else {
+        let backups = store.backups()?;
+        if backups.is_empty() {
+            println!("No backups available");
+        }
+
+        for (i, path) in backups.iter().enumerate() {
+            println!("{}: {}", i + 1, path.display());
+        }
+
+        return Ok(());
+    };
+
+    let path = store.backup_path(backup);
+    ensure!(path.try_exists()?, "backup {backup} doesn't exist");
+
+    let credential = credential(unlock, "Password:")?;
+    let accounts = Store::at(path).with_rehash(false).open(&credential)?;
+
+    println!("Backup {backup} contains {} accounts", accounts.len());
+
+    if !prompt::confirm("Restore?")? {
+        println!("Restore cancelled");
+        return Ok(());
+    }
+
+    store.restore(backup)?;
+    println!("Restored backup {backup}, the previous store is kept as backup 1");
+    write_auto_backup(store, &credential, auto_backup);
+
+    Ok(())
+}
+
+/// Write an automatic backup of the store's current accounts, for changes that don't have the
+/// accounts at hand. The store is only opened, if automatic backups are enabled, and without
+/// re-sealing it, as the change itself was already saved. Failures are only reported, like for
+/// any other automatic backup.
+fn write_auto_backup(store: &Store, credential: &Credential, auto_backup: &config::AutoBackup) {
+    if auto_backup.dir.is_none() {
+        return;
+    }
+
+    match Store::at(store.path()).with_rehash(false).open(credential) {
+        Ok(accounts) => {
+            harden::lock(&accounts);
+            auto_backup::write(auto_backup, &accounts);
+        }
+        Err(e) => eprintln!("Warning: failed writing the automatic backup: {e:#}"),
+    }
+}
+
+fn verify(store: &Store, unlock: &UnlockArgs, auto_backup: &config::AutoBackup) -> Result<()> {
+    let credential = credential(unlock, "Password:")?;
+    let report = store.verify(&credential)?;
+
+    for (check, outcome) in &report.checks {
+        match outcome {
+            Outcome::Passed(details) => println!("{check:<16} ok       {details}"),
+            Outcome::Failed(reason) => println!("{check:<16} FAILED   {reason}"),
+            Outcome::Skipped => println!("{check:<16} skipped"),
+        }
+    }
+
+    if report.is_ok() {
+        println!("\nThe store is intact");
+        return Ok(());
+    }
+
+    println!();
+    let mut intact = None;
+
+    for (i, path) in store.backups()?.iter().enumerate() {
+        let backup = Store::at(path).verify(&credential)?;
+        let state = if backup.is_ok() {
+            "intact"
+        } else if backup.is_damaged() {
+            "damaged"
+        } else {
+            "not unlocked"
+        };
+
+        println!("Backup {}: {state}", i + 1);
+        if backup.is_ok() && intact.is_none() {
+            intact = Some(i + 1);
+        }
+    }
+
+    match intact {
+        Some(backup) if prompt::confirm(&format!("Restore backup {backup}?"))? => {
+            store.restore(backup)?;
+            println!("Restored backup {backup}, the previous store is kept as backup 1");
+            write_auto_backup(store, &credential, auto_backup);
+            Ok(())
+        }
+        Some(_) => bail!("the store failed verification"),
+        None if report.is_damaged() => bail!("the store is damaged and no intact backup exists"),
+        None => bail!(
+            "the {} didn't unlock the store or any of its backups",
+            credential.kind()
+        ),
+    }
+}
+
 #[derive(Copy, Clone, PartialEq, Eq)]
 enum CurrentDialog {
     None,
@@ -148,15 +859,15 @@ enum CurrentDialog {
     Code,
 }
 
-fn run() -> Result<()> {
-    let password = SecretString::new(rpassword::prompt_password("Password:")?);
-
-    let accounts = otti_store::open(&password)?;
+fn run(store: &Store, unlock: &UnlockArgs, config: &Config) -> Result<()> {
+    let accounts = accounts(store, unlock)?;
 
     let mut terminal = terminal::create()?;
     let events = terminal::create_event_listener();
-    let ticker = crossbeam_channel::tick(Duration::from_millis(1000));
-    let mut clipboard = Clipboard::new()?;
+    let ticker = crossbeam_channel::tick(config.ui.tick_interval.0);
+    let mut clipboard = config.clipboard.enabled.then(Clipboard::new).transpose()?;
+    let clear_after = Duration::from_secs(config.clipboard.clear_after);
+    let mut copied = None;
 
     let mut counter = 30 - (UNIX_EPOCH.elapsed()?.as_secs() % 30) as u16;
     let mut list_state = ListState::default();
@@ -184,27 +895,48 @@ fn run() -> Result<()> {
 
             let gauge = Gauge::default()
                 .block(Block::default().borders(Borders::ALL))
-                .gauge_style(Style::default().fg(Color::Green).bg(Color::DarkGray))
+                .gauge_style(
+                    Style::default()
+                        .fg(config.colors.gauge.0)
+                        .bg(config.colors.gauge_background.0),
+                )
                 .label(format!("{counter}s"))
                 .percent(counter * 100 / 30);
 
             let list = List::new(&accounts)
                 .block(Block::default().borders(Borders::ALL))
-                .scrollbar(ScrollBar::default(), 2);
+                .selection_color(config.colors.selection.0)
+                .scrollbar(
+                    ScrollBar::default()
+                        .colors(config.colors.scrollbar.0, config.colors.scrollbar_track.0),
+                    2,
+                );
 
             f.render_widget(gauge, chunks[0]);
             f.render_stateful_widget(list, chunks[1], &mut list_state);
 
             match showing {
                 CurrentDialog::None => {}
-                CurrentDialog::Help => f.render_widget(HelpDialog, area),
-                CurrentDialog::Code => f.render_widget(CodeDialog::new(&otp_code), area),
+                CurrentDialog::Help => {
+                    let size = config.dialogs.help;
+                    f.render_widget(HelpDialog::default().size(size.width, size.height), area);
+                }
+                CurrentDialog::Code => {
+                    let size = config.dialogs.code;
+                    f.render_widget(
+                        CodeDialog::new(&otp_code).size(size.width, size.height),
+                        area,
+                    );
+                }
             }
         })?;
 
         let value = select! {
             recv(ticker) -> _ => {
                 counter = 30 - (UNIX_EPOCH.elapsed()?.as_secs() % 30) as u16;
+                if let Some(clipboard) = &mut clipboard {
+                    clear_clipboard(clipboard, &mut copied, clear_after);
+                }
                 None
             },
             recv(events) -> event => event.ok(),
@@ -218,15 +950,17 @@ fn run() -> Result<()> {
                 KeyCode::Char('h') => toggle_dialog(&mut showing, CurrentDialog::Help),
                 KeyCode::Char('s') => toggle_dialog(&mut showing, CurrentDialog::Code),
                 KeyCode::Char('c') => {
-                    if let Some(acc) = accounts.get(list_state.selection()) {
-                        clipboard.set_text(
-                            otti_gen::generate::<otti_gen::Sha1>(
-                                &acc.secret,
-                                &acc.otp,
-                                Some(acc.digits),
-                            )?
-                            .to_string(),
-                        )?;
+                    if let (Some(clipboard), Some(acc)) =
+                        (&mut clipboard, accounts.get(list_state.selection()))
+                    {
+                        let code = otti_gen::generate::<otti_gen::Sha1>(
+                            &acc.secret,
+                            &acc.otp,
+                            Some(acc.digits),
+                        )?
+                        .to_string();
+                        clipboard.set_text(code.clone())?;
+                        copied = Some((code, Instant::now()));
                     }
                 }
                 _ => {}
@@ -237,6 +971,26 @@ fn run() -> Result<()> {
     Ok(())
 }
 
+/// Clear the copied code from the clipboard once it's expired, unless the clipboard's content was
+/// replaced in the meantime. A zero duration keeps the code.
+fn clear_clipboard(
+    clipboard: &mut Clipboard,
+    copied: &mut Option<(String, Instant)>,
+    clear_after: Duration,
+) {
+    let Some((code, at)) = copied else {
+        return;
+    };
+    if clear_after.is_zero() || at.elapsed() < clear_after {
+        return;
+    }
+
+    if clipboard.get_text().is_ok_and(|text| text == *code) {
+        clipboard.clear().ok();
+    }
+    *copied = None;
+}
+
 fn toggle_dialog(showing: &mut CurrentDialog, dialog: CurrentDialog) {
     *showing = if *showing == dialog {
         CurrentDialog::None
@@ -0,0 +1,197 @@
+//! Reading of passwords for import and export files from the different sources, that can be
+//! selected on the command line.
+
+use std::{
+    fs::File,
+    io::{self, Read},
+    process::{Command, Stdio},
+};
+
+use anyhow::{ensure, Context, Result};
+use otti_core::Zeroizing;
+use otti_store::SecretBuffer;
+use secrecy::SecretString;
+
+use crate::cli::PasswordArgs;
+
+/// Read the password from whatever source the user selected, or `None` if no password was given.
+/// The `prompt` is only shown if the user asked to enter the password interactively.
+pub fn read(args: PasswordArgs, prompt: &str) -> Result<Option<SecretString>> {
+    let content = if let Some(password) = args.password {
+        return Ok(Some(SecretString::new(password)));
+    } else if args.password_prompt {
+        return crate::prompt::password(prompt).map(Some);
+    } else if let Some(path) = args.password_file {
+        File::open(&path)
+            .and_then(read_secret)
+            .with_context(|| format!("failed reading password file `{}`", path.display()))?
+    } else if let Some(fd) = args.password_fd {
+        read_fd(fd)?
+    } else if let Some(command) = args.password_command {
+        run_command(&command)?
+    } else {
+        return Ok(None);
+    };
+
+    first_line(&content).map(Some)
+}
+
+/// Read all content into a buffer, that zeroizes it once it's no longer needed.
+fn read_secret(mut reader: impl Read) -> io::Result<Zeroizing<Vec<u8>>> {
+    let mut buf = SecretBuffer::default();
+    io::copy(&mut reader, &mut buf)?;
+    Ok(buf.into_inner())
+}
+
+/// Reduce the content to its first line, without the line ending. Files and command outputs
+/// usually end with a newline, which is not part of the password. Only the first line is copied
+/// into the password, so the rest stays in the zeroizing buffer.
+fn first_line(content: &[u8]) -> Result<SecretString> {
+    let content = std::str::from_utf8(content).context("password is not valid UTF-8")?;
+    let line = content.lines().next().unwrap_or_default();
+
+    Ok(SecretString::new(line.to_owned()))
+}
+
+#[cfg(unix)]
+fn read_fd(fd: u32) -> Result<Zeroizing<Vec<u8>>> {
+    File::open(format!("/dev/fd/{fd}"))
+        .and_then(read_secret)
+        .with_context(|| format!("failed reading password from file descriptor {fd}"))
+}
+
+#[cfg(not(unix))]
+fn read_fd(_fd: u32) -> Result<Zeroizing<Vec<u8>>> {
+    anyhow::bail!("reading the password from a file descriptor is only supported on Unix systems")
+}
+
+/// Run the command through the system shell and capture its output. The command shares the input
+/// and error output with Otti, so it can prompt on the terminal and report its own errors.
+fn run_command(command: &str) -> Result<Zeroizing<Vec<u8>>> {
+    let (shell, flag) = if cfg!(windows) {
+        ("cmd", "/C")
+    } else {
+        ("sh", "-c")
+    };
+
+    let mut child = Command::new(shell)
+        .args([flag, command])
+        .stdin(Stdio::inherit())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::inherit())
+        .spawn()
+        .with_context(|| format!("failed running password command `{command}`"))?;
+
+    // Read the output directly, instead of collecting it in a plain buffer.
+    let stdout = child
+        .stdout
+        .take()
+        .context("password command has no output")?;
+    let output = read_secret(stdout);
+    let status = child.wait()?;
+    let output = output
+        .with_context(|| format!("failed reading the output of password command `{command}`"))?;
+
+    ensure!(
+        status.success(),
+        "password command `{command}` failed with {status}"
+    );
+
+    Ok(output)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs};
+
+    use secrecy::ExposeSecret;
+
+    use super::*;
+    use crate::test_alloc;
+
+    fn password(content: &str) -> String {
+        let password = first_line(content.as_bytes()).unwrap();
+        password.expose_secret().clone()
+    }
+
+    #[test]
+    fn keep_first_line() {
+        assert_eq!("secret", password("secret\nother\n"));
+        assert_eq!("secret", password("secret\r\nother\r\n"));
+        assert_eq!("secret", password("secret"));
+        assert_eq!("", password(""));
+        assert_eq!("", password("\nsecret"));
+        assert!(first_line(&[0xff]).is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn run_password_command() {
+        assert_eq!(b"secret\n", run_command("echo secret").unwrap().as_slice());
+
+        let err = run_command("exit 3").unwrap_err();
+        assert!(err.to_string().contains("exit status: 3"), "{err}");
+    }
+
+    #[test]
+    fn zeroize_password_file() {
+        let path = env::temp_dir().join(format!("otti-password-test-{}", std::process::id()));
+        fs::write(&path, "otti-file-password\notti-file-rest\n").unwrap();
+
+        let args = PasswordArgs {
+            password_file: Some(path.clone()),
+            ..PasswordArgs::default()
+        };
+        let leaked = test_alloc::freed_containing(b"otti-file-", || {
+            let password = read(args, "").unwrap().unwrap();
+            assert_eq!("otti-file-password", password.expose_secret());
+        });
+
+        fs::remove_file(&path).unwrap();
+        assert!(!leaked);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn zeroize_password_fd() {
+        use std::os::fd::AsRawFd;
+
+        // A pipe has no known size, so the buffer grows several times while reading it.
+        let mut child = Command::new("sh")
+            .args([
+                "-c",
+                "printf '%s-fd-%s\\n' otti password; seq 500 | sed 's/^/otti-fd-rest-/'",
+            ])
+            .stdout(Stdio::piped())
+            .spawn()
+            .unwrap();
+        let fd = child.stdout.as_ref().unwrap().as_raw_fd();
+
+        let args = PasswordArgs {
+            password_fd: Some(fd.try_into().unwrap()),
+            ..PasswordArgs::default()
+        };
+        let leaked = test_alloc::freed_containing(b"otti-fd-", || {
+            let password = read(args, "").unwrap().unwrap();
+            assert_eq!("otti-fd-password", password.expose_secret());
+        });
+
+        assert!(child.wait().unwrap().success());
+        assert!(!leaked);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn zeroize_password_command() {
+        // The command itself must not contain the marker, so it's assembled from parts.
+        let args = PasswordArgs {
+            password_command: Some("printf '%s-command-%s\\n' otti password otti rest".to_owned()),
+            ..PasswordArgs::default()
+        };
+
+        assert!(!test_alloc::freed_containing(b"otti-command-", || {
+            let password = read(args, "").unwrap().unwrap();
+            assert_eq!("otti-command-password", password.expose_secret());
+        }));
+    }
+}
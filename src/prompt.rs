@@ -0,0 +1,153 @@
+//! Interactive prompts on the terminal, for passwords and confirmations.
+
+use anyhow::{bail, Result};
+use secrecy::{ExposeSecret, SecretString};
+
+/// Passwords with a strength below this score cause a warning when they're set.
+const WEAK_THRESHOLD: u8 = 2;
+/// Amount of attempts to enter a new password twice in the same way.
+const NEW_PASSWORD_ATTEMPTS: usize = 3;
+
+/// Very common passwords and fragments, that make a password trivial to guess.
+const COMMON_PASSWORDS: &[&str] = &[
+    "password", "passwort", "123456", "qwerty", "azerty", "letmein", "welcome", "admin",
+    "iloveyou", "monkey", "dragon", "abc123", "111111", "secret", "master", "sunshine", "football",
+    "otti",
+];
+
+/// Ask for a password without showing the input.
+pub fn password(prompt: &str) -> Result<SecretString> {
+    rpassword::prompt_password(prompt)
+        .map(SecretString::new)
+        .map_err(Into::into)
+}
+
+/// Ask for a new password, that has to be entered twice to protect against typos. Weak passwords
+/// cause a warning, but can still be used if the user insists.
+pub fn new_password(prompt: &str) -> Result<SecretString> {
+    for _ in 0..NEW_PASSWORD_ATTEMPTS {
+        let password = password(prompt)?;
+
+        if password.expose_secret().is_empty() {
+            eprintln!("The password must not be empty");
+            continue;
+        }
+
+        let strength = strength(password.expose_secret());
+        if strength < WEAK_THRESHOLD {
+            eprintln!(
+                "Warning: the password is {} and could be guessed easily",
+                describe(strength)
+            );
+
+            if !confirm("Use it anyway?")? {
+                continue;
+            }
+        }
+
+        let repeated = self::password("Repeat password:")?;
+        if password.expose_secret() == repeated.expose_secret() {
+            return Ok(password);
+        }
+
+        eprintln!("The passwords don't match, please try again");
+    }
+
+    bail!("no new password set after {NEW_PASSWORD_ATTEMPTS} attempts")
+}
+
+/// Ask a yes/no question, where no is the default.
+pub fn confirm(question: &str) -> Result<bool> {
+    let resp = rprompt::prompt_reply(format!("{question} [yN] "))?;
+    Ok(matches!(resp.trim(), "y" | "Y"))
+}
+
+/// Rough estimate of the password strength, in the same range as the zxcvbn score, from 0 (too
+/// guessable) to 4 (very unguessable).
+///
+/// The estimate is based on the entropy of each character given the character classes in use,
+/// where characters that repeat or continue a sequence of their predecessor count only a single
+/// bit. Passwords built around a very common password are always scored as 0.
+pub fn strength(password: &str) -> u8 {
+    let lower = password.to_lowercase();
+    if COMMON_PASSWORDS
+        .iter()
+        .any(|common| lower.contains(common) && common.len() * 2 >= lower.len())
+    {
+        return 0;
+    }
+
+    let mut pool = 0_u32;
+    if password.chars().any(|c| c.is_ascii_lowercase()) {
+        pool += 26;
+    }
+    if password.chars().any(|c| c.is_ascii_uppercase()) {
+        pool += 26;
+    }
+    if password.chars().any(|c| c.is_ascii_digit()) {
+        pool += 10;
+    }
+    if password
+        .chars()
+        .any(|c| c.is_ascii_punctuation() || c == ' ')
+    {
+        pool += 33;
+    }
+    if !password.is_ascii() {
+        pool += 100;
+    }
+
+    let char_bits = f64::from(pool.max(1)).log2();
+    let mut prev = None::<char>;
+    let bits = password
+        .chars()
+        .map(|c| {
+            let predictable = prev.is_some_and(|p| (c as i64 - p as i64).abs() <= 1);
+            prev = Some(c);
+            if predictable {
+                1.0
+            } else {
+                char_bits
+            }
+        })
+        .sum::<f64>();
+
+    match bits {
+        b if b < 28.0 => 0,
+        b if b < 36.0 => 1,
+        b if b < 60.0 => 2,
+        b if b < 80.0 => 3,
+        _ => 4,
+    }
+}
+
+fn describe(strength: u8) -> &'static str {
+    match strength {
+        0 => "very weak",
+        1 => "weak",
+        2 => "fair",
+        3 => "strong",
+        _ => "very strong",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn weak_passwords() {
+        assert_eq!(0, strength(""));
+        assert_eq!(0, strength("password1"));
+        assert_eq!(0, strength("Qwerty!"));
+        assert_eq!(0, strength("aaaaaaaaaaaaaaaa"));
+        assert_eq!(0, strength("abcdefghijklmnop"));
+        assert_eq!(1, strength("xkcdpuzl"));
+    }
+
+    #[test]
+    fn strong_passwords() {
+        assert!(strength("correct horse battery staple") >= 3);
+        assert!(strength("m9#Lq2!vXz7@pR4t") >= 4);
+    }
+}
@@ -0,0 +1,455 @@
+//! Merging of two copies of the same store, that were modified independently, like the conflict
+//! copies that file synchronization tools create.
+//!
+//! Accounts are matched by their secret, as it's the only part of an account that never changes.
+//! All other fields are merged one by one, where the common base version of both copies, if
+//! known, tells which side changed a field. HOTP counters and tags are merged automatically, while
+//! any other field that was changed on both sides is a conflict for the user to resolve.
+//!
+//! The base is either given explicitly, or reconstructed from the latest change that both copies
+//! recorded in their history.
+
+use std::collections::BTreeMap;
+
+use anyhow::Result;
+use otti_core::{Account, Algorithm, ExposeSecret, Otp};
+
+/// Side of the merge, whose value is kept.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Side {
+    /// The store, that is merged into.
+    Local,
+    /// The other copy, that is merged from.
+    Remote,
+}
+
+impl Side {
+    /// The other side of the merge.
+    pub fn opposite(self) -> Self {
+        match self {
+            Self::Local => Self::Remote,
+            Self::Remote => Self::Local,
+        }
+    }
+}
+
+/// Conflicting change of an account, that was made on both sides.
+pub enum Conflict {
+    /// A field was changed to different values on both sides.
+    Field {
+        /// Name of the affected account.
+        account: String,
+        /// Name of the conflicting field.
+        field: &'static str,
+        /// The local value, formatted for display.
+        local: String,
+        /// The remote value, formatted for display.
+        remote: String,
+    },
+    /// The account was removed on one side and modified on the other. Keeping the version of the
+    /// `removed` side removes the account.
+    Removal {
+        /// Name of the affected account.
+        account: String,
+        /// The side, that removed the account.
+        removed: Side,
+    },
+}
+
+/// Changes, that the merge made to the local accounts.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct Summary {
+    pub added: usize,
+    pub removed: usize,
+    pub updated: usize,
+    pub conflicts: usize,
+}
+
+impl Summary {
+    /// Whether the merge result is the same as the local accounts.
+    pub fn is_unchanged(&self) -> bool {
+        self.added == 0 && self.removed == 0 && self.updated == 0
+    }
+}
+
+/// Merge the remote accounts into the local ones. Accounts of the `base` version, which both
+/// sides were derived from, tell apart added from removed accounts and which side changed a
+/// field. Without it, accounts are never removed and each changed field is a conflict.
+pub fn merge(
+    base: &[Account],
+    local: Vec<Account>,
+    remote: Vec<Account>,
+    mut resolve: impl FnMut(&Conflict) -> Result<Side>,
+) -> Result<(Vec<Account>, Summary)> {
+    let mut summary = Summary::default();
+    let mut remote = remote.into_iter().map(Some).collect::<Vec<_>>();
+    let mut merged = Vec::with_capacity(local.len().max(remote.len()));
+
+    for mut account in local {
+        let base = find(base, &account);
+        let other = remote
+            .iter_mut()
+            .find(|r| r.as_ref().is_some_and(|r| same_secret(r, &account)))
+            .and_then(Option::take);
+
+        let Some(other) = other else {
+            // Removed on the remote side, unless it was added locally.
+            if let Some(base) = base {
+                if Fields::of(&account) == Fields::of(base)
+                    || removal(&mut resolve, &mut summary, &account, Side::Remote)?
+                {
+                    summary.removed += 1;
+                    continue;
+                }
+            }
+
+            merged.push(account);
+            continue;
+        };
+
+        let before = Fields::of(&account);
+        let fields = merge_fields(
+            &mut resolve,
+            &mut summary,
+            &name(&account),
+            base.map(Fields::of).as_ref(),
+            &before,
+            &Fields::of(&other),
+        )?;
+
+        if fields != before {
+            summary.updated += 1;
+            fields.apply(&mut account);
+        }
+
+        merged.push(account);
+    }
+
+    for account in remote.into_iter().flatten() {
+        // Removed locally, unless it was added on the remote side.
+        if let Some(base) = find(base, &account) {
+            if Fields::of(&account) == Fields::of(base)
+                || removal(&mut resolve, &mut summary, &account, Side::Local)?
+            {
+                continue;
+            }
+        }
+
+        summary.added += 1;
+        merged.push(account);
+    }
+
+    Ok((merged, summary))
+}
+
+/// Ask whether an account, that was modified on one side but removed on the other, should be
+/// removed.
+fn removal(
+    resolve: &mut impl FnMut(&Conflict) -> Result<Side>,
+    summary: &mut Summary,
+    account: &Account,
+    removed: Side,
+) -> Result<bool> {
+    summary.conflicts += 1;
+    let side = resolve(&Conflict::Removal {
+        account: name(account),
+        removed,
+    })?;
+
+    Ok(side == removed)
+}
+
+/// All fields of an account, except for the secret that identifies it.
+#[derive(PartialEq, Eq)]
+struct Fields {
+    label: String,
+    issuer: Option<String>,
+    digits: u8,
+    otp: Otp,
+    algorithm: Algorithm,
+    tags: Vec<String>,
+    extras: BTreeMap<String, Vec<u8>>,
+}
+
+impl Fields {
+    fn of(account: &Account) -> Self {
+        Self {
+            label: account.label.clone(),
+            issuer: account.issuer.clone(),
+            digits: account.digits,
+            otp: account.otp.clone(),
+            algorithm: account.algorithm,
+            tags: account.meta.tags.clone(),
+            extras: account.extras.clone(),
+        }
+    }
+
+    fn apply(self, account: &mut Account) {
+        account.label = self.label;
+        account.issuer = self.issuer;
+        account.digits = self.digits;
+        account.otp = self.otp;
+        account.algorithm = self.algorithm;
+        account.meta.tags = self.tags;
+        account.extras = self.extras;
+    }
+}
+
+fn merge_fields(
+    resolve: &mut impl FnMut(&Conflict) -> Result<Side>,
+    summary: &mut Summary,
+    account: &str,
+    base: Option<&Fields>,
+    local: &Fields,
+    remote: &Fields,
+) -> Result<Fields> {
+    let mut merger = Merger {
+        resolve,
+        summary,
+        account,
+        base,
+        local,
+        remote,
+    };
+
+    let label = merger.pick("label", |f| &f.label, Clone::clone)?;
+    let issuer = merger.pick("issuer", |f| &f.issuer, |i| show_issuer(i.as_deref()))?;
+    let digits = merger.pick("digits", |f| &f.digits, ToString::to_string)?;
+    let otp = match (&local.otp, &remote.otp) {
+        // Counters only ever increase, so the higher one is the most recent.
+        (Otp::Hotp { counter: l }, Otp::Hotp { counter: r }) if r > l => Side::Remote,
+        (Otp::Hotp { .. }, Otp::Hotp { .. }) => Side::Local,
+        _ => merger.pick("type", |f| &f.otp, show_otp)?,
+    };
+    let algorithm = merger.pick("algorithm", |f| &f.algorithm, |a| format!("{a:?}"))?;
+    let extras = merger.pick("extras", |f| &f.extras, show_extras)?;
+
+    let tags = merge_tags(
+        base.map_or(&[][..], |b| b.tags.as_slice()),
+        &local.tags,
+        &remote.tags,
+    );
+
+    let choose = |side: Side| match side {
+        Side::Local => local,
+        Side::Remote => remote,
+    };
+
+    Ok(Fields {
+        label: choose(label).label.clone(),
+        issuer: choose(issuer).issuer.clone(),
+        digits: choose(digits).digits,
+        otp: choose(otp).otp.clone(),
+        algorithm: choose(algorithm).algorithm,
+        tags,
+        extras: choose(extras).extras.clone(),
+    })
+}
+
+/// State of merging the fields of a single account.
+struct Merger<'a, F> {
+    resolve: &'a mut F,
+    summary: &'a mut Summary,
+    account: &'a str,
+    base: Option<&'a Fields>,
+    local: &'a Fields,
+    remote: &'a Fields,
+}
+
+impl<F: FnMut(&Conflict) -> Result<Side>> Merger<'_, F> {
+    /// Pick the side, whose value of a single field is kept. The side that changed the field from
+    /// the base wins, and if both did, the user decides.
+    fn pick<T: PartialEq>(
+        &mut self,
+        field: &'static str,
+        get: impl Fn(&Fields) -> &T,
+        show: impl Fn(&T) -> String,
+    ) -> Result<Side> {
+        let base = self.base.map(&get);
+        let local = get(self.local);
+        let remote = get(self.remote);
+
+        if local == remote || base == Some(remote) {
+            return Ok(Side::Local);
+        }
+        if base == Some(local) {
+            return Ok(Side::Remote);
+        }
+
+        self.summary.conflicts += 1;
+        (self.resolve)(&Conflict::Field {
+            account: self.account.to_owned(),
+            field,
+            local: show(local),
+            remote: show(remote),
+        })
+    }
+}
+
+/// Merge the tags like a set, where tags added on either side are kept and tags removed on
+/// either side are dropped.
+fn merge_tags(base: &[String], local: &[String], remote: &[String]) -> Vec<String> {
+    let keep = |tag: &String, other: &[String]| other.contains(tag) || !base.contains(tag);
+
+    local
+        .iter()
+        .filter(|tag| keep(tag, remote))
+        .chain(
+            remote
+                .iter()
+                .filter(|tag| !local.contains(tag) && keep(tag, local)),
+        )
+        .cloned()
+        .collect()
+}
+
+fn find<'a>(accounts: &'a [Account], account: &Account) -> Option<&'a Account> {
+    accounts.iter().find(|a| same_secret(a, account))
+}
+
+fn same_secret(a: &Account, b: &Account) -> bool {
+    a.secret.expose_secret() == b.secret.expose_secret()
+}
+
+/// Name of the account for display, along with its issuer if known.
+pub fn name(account: &Account) -> String {
+    match &account.issuer {
+        Some(issuer) => format!("{issuer} ({})", account.label),
+        None => account.label.clone(),
+    }
+}
+
+fn show_issuer(issuer: Option<&str>) -> String {
+    issuer.unwrap_or("<none>").to_owned()
+}
+
+fn show_otp(otp: &Otp) -> String {
+    match otp {
+        Otp::Hotp { counter } => format!("HOTP with counter {counter}"),
+        Otp::Totp { window } => format!("TOTP every {window}s"),
+        Otp::Steam { period } => format!("Steam every {period}s"),
+    }
+}
+
+fn show_extras(extras: &BTreeMap<String, Vec<u8>>) -> String {
+    if extras.is_empty() {
+        return "<none>".to_owned();
+    }
+
+    extras
+        .keys()
+        .map(String::as_str)
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+#[cfg(test)]
+mod tests {
+    use otti_core::{Key, Metadata};
+
+    use super::*;
+
+    fn account(secret: u8, label: &str, tags: &[&str]) -> Account {
+        Account {
+            label: label.to_owned(),
+            secret: Key::new(vec![secret; 10]),
+            digits: 6,
+            otp: Otp::Totp { window: 30 },
+            algorithm: Algorithm::Sha1,
+            issuer: Some("Provider".to_owned()),
+            meta: Metadata {
+                tags: tags.iter().map(|&t| t.to_owned()).collect(),
+            },
+            extras: BTreeMap::new(),
+        }
+    }
+
+    fn labels(accounts: &[Account]) -> Vec<&str> {
+        accounts.iter().map(|a| a.label.as_str()).collect()
+    }
+
+    fn no_conflicts(conflict: &Conflict) -> Result<Side> {
+        let (Conflict::Field { account, .. } | Conflict::Removal { account, .. }) = conflict;
+        panic!("unexpected conflict of {account}");
+    }
+
+    #[test]
+    fn merge_independent_changes() {
+        let base = vec![
+            account(1, "a", &["x"]),
+            account(2, "b", &[]),
+            account(3, "c", &[]),
+        ];
+
+        let mut local = vec![
+            account(1, "a", &["x", "y"]),
+            account(3, "c", &[]),
+            account(4, "d", &[]),
+        ];
+        local[1].otp = Otp::Hotp { counter: 5 };
+        let mut remote = vec![
+            account(1, "a2", &[]),
+            account(2, "b", &[]),
+            account(3, "c", &[]),
+            account(5, "e", &[]),
+        ];
+        remote[2].otp = Otp::Hotp { counter: 7 };
+
+        let (merged, summary) = merge(&base, local, remote, no_conflicts).unwrap();
+
+        assert_eq!(vec!["a2", "c", "d", "e"], labels(&merged));
+        assert_eq!(vec!["y"], merged[0].meta.tags);
+        assert_eq!(Otp::Hotp { counter: 7 }, merged[1].otp);
+        assert_eq!(
+            Summary {
+                added: 1,
+                removed: 0,
+                updated: 2,
+                conflicts: 0,
+            },
+            summary
+        );
+    }
+
+    #[test]
+    fn resolve_conflicts() {
+        let base = vec![account(1, "a", &[]), account(2, "b", &[])];
+        let local = vec![account(1, "local", &["x"]), account(2, "b2", &[])];
+        let remote = vec![account(1, "remote", &["y"])];
+
+        let mut conflicts = Vec::new();
+        let (merged, summary) = merge(&base, local, remote, |conflict| {
+            conflicts.push(match conflict {
+                Conflict::Field {
+                    field,
+                    local,
+                    remote,
+                    ..
+                } => format!("{field}: {local} / {remote}"),
+                Conflict::Removal { account, removed } => format!("{account}: removed {removed:?}"),
+            });
+            Ok(Side::Remote)
+        })
+        .unwrap();
+
+        assert_eq!(
+            vec!["label: local / remote", "Provider (b2): removed Remote",],
+            conflicts
+        );
+        assert_eq!(vec!["remote"], labels(&merged));
+        assert_eq!(vec!["x", "y"], merged[0].meta.tags);
+        assert_eq!(1, summary.removed);
+    }
+
+    #[test]
+    fn merge_without_base() {
+        let local = vec![account(1, "a", &["x"]), account(2, "b", &[])];
+        let remote = vec![account(1, "a", &["y"]), account(3, "c", &[])];
+
+        let (merged, summary) = merge(&[], local, remote, no_conflicts).unwrap();
+
+        assert_eq!(vec!["a", "b", "c"], labels(&merged));
+        assert_eq!(vec!["x", "y"], merged[0].meta.tags);
+        assert_eq!((1, 0, 1), (summary.added, summary.removed, summary.updated));
+    }
+}
@@ -0,0 +1,70 @@
+//! Global allocator for tests, that scans allocations right before they're freed. It allows to
+//! check, that secrets are cleared from memory in code paths with many intermediate allocations,
+//! which can't be watched one by one.
+
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    ptr, slice,
+    sync::{
+        atomic::{AtomicBool, AtomicPtr, AtomicUsize, Ordering},
+        Mutex, PoisonError,
+    },
+};
+
+#[global_allocator]
+static ALLOCATOR: Scanner = Scanner;
+
+/// Content, that freed allocations are scanned for, or null if scanning is disabled.
+static MARKER: AtomicPtr<u8> = AtomicPtr::new(ptr::null_mut());
+static MARKER_LEN: AtomicUsize = AtomicUsize::new(0);
+/// Whether any freed allocation contained the marker.
+static FOUND: AtomicBool = AtomicBool::new(false);
+
+struct Scanner;
+
+unsafe impl GlobalAlloc for Scanner {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        let marker = MARKER.load(Ordering::SeqCst);
+        if !marker.is_null() {
+            // Both the marker and the allocation are valid at this point, as the marker is only
+            // released after scanning stopped and the allocation is only released below.
+            let marker = slice::from_raw_parts(marker, MARKER_LEN.load(Ordering::SeqCst));
+            let content = slice::from_raw_parts(ptr, layout.size());
+
+            if content.windows(marker.len()).any(|w| w == marker) {
+                FOUND.store(true, Ordering::SeqCst);
+            }
+        }
+
+        System.dealloc(ptr, layout);
+    }
+}
+
+/// Run the function and tell whether any allocation, that was freed in the meantime, still
+/// contained the marker. Allocations of other threads are scanned as well, so the marker should
+/// be unique to the test.
+pub fn freed_containing(marker: &[u8], f: impl FnOnce()) -> bool {
+    static LOCK: Mutex<()> = Mutex::new(());
+    let _guard = LOCK.lock().unwrap_or_else(PoisonError::into_inner);
+    assert!(!marker.is_empty());
+
+    FOUND.store(false, Ordering::SeqCst);
+    MARKER_LEN.store(marker.len(), Ordering::SeqCst);
+    MARKER.store(marker.as_ptr().cast_mut(), Ordering::SeqCst);
+    f();
+    MARKER.store(ptr::null_mut(), Ordering::SeqCst);
+
+    FOUND.load(Ordering::SeqCst)
+}
+
+#[test]
+fn detect_leftover_content() {
+    assert!(freed_containing(b"leftover", || drop(
+        b"some leftover".to_vec()
+    )));
+    assert!(!freed_containing(b"leftover", || drop(vec![0_u8; 16])));
+}
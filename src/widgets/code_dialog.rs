@@ -6,20 +6,32 @@ use ratatui::{
 
 pub struct CodeDialog<'a> {
     code: &'a str,
+    width: u16,
+    height: u16,
 }
 
 impl<'a> CodeDialog<'a> {
     pub fn new(code: &'a str) -> Self {
-        Self { code }
+        Self {
+            code,
+            width: 20,
+            height: 5,
+        }
+    }
+
+    pub fn size(mut self, width: u16, height: u16) -> Self {
+        self.width = width;
+        self.height = height;
+        self
     }
 }
 
-impl<'a> Widget for CodeDialog<'a> {
+impl Widget for CodeDialog<'_> {
     fn render(self, area: Rect, buf: &mut Buffer) {
         let area = {
             let mut draw_area = area;
-            draw_area.width = 20.min(area.width);
-            draw_area.height = 5.min(area.height);
+            draw_area.width = self.width.min(area.width);
+            draw_area.height = self.height.min(area.height);
             draw_area.y = area.y + (area.height - draw_area.height) / 2;
             draw_area.x = area.x + (area.width - draw_area.width) / 2;
             draw_area
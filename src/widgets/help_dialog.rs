@@ -5,14 +5,34 @@ use ratatui::{
     widgets::{Block, Borders, Clear, Paragraph, Widget, Wrap},
 };
 
-pub struct HelpDialog;
+pub struct HelpDialog {
+    width: u16,
+    height: u16,
+}
+
+impl Default for HelpDialog {
+    fn default() -> Self {
+        Self {
+            width: 70,
+            height: 10,
+        }
+    }
+}
+
+impl HelpDialog {
+    pub fn size(mut self, width: u16, height: u16) -> Self {
+        self.width = width;
+        self.height = height;
+        self
+    }
+}
 
 impl Widget for HelpDialog {
     fn render(self, area: Rect, buf: &mut Buffer) {
         let area = {
             let mut draw_area = area;
-            draw_area.width = 70.min(area.width);
-            draw_area.height = 10.min(area.height);
+            draw_area.width = self.width.min(area.width);
+            draw_area.height = self.height.min(area.height);
             draw_area.y = area.y + (area.height - draw_area.height) / 2;
             draw_area.x = area.x + (area.width - draw_area.width) / 2;
             draw_area
@@ -14,6 +14,7 @@ pub struct List<'a> {
     block: Option<Block<'a>>,
     scrollbar: Option<(ScrollBar, u16)>,
     items: &'a [Account],
+    selection_color: Color,
 }
 
 impl<'a> List<'a> {
@@ -22,6 +23,7 @@ impl<'a> List<'a> {
             block: None,
             scrollbar: None,
             items,
+            selection_color: Color::Blue,
         }
     }
 
@@ -34,9 +36,14 @@ impl<'a> List<'a> {
         self.scrollbar = Some((scrollbar, width));
         self
     }
+
+    pub fn selection_color(mut self, color: Color) -> Self {
+        self.selection_color = color;
+        self
+    }
 }
 
-impl<'a> StatefulWidget for List<'a> {
+impl StatefulWidget for List<'_> {
     type State = State;
 
     fn render(mut self, area: Rect, buf: &mut Buffer, state: &mut Self::State) {
@@ -65,7 +72,7 @@ impl<'a> StatefulWidget for List<'a> {
             // Draw current selection indicator
             if state.position() == i {
                 for y in area.y..list_area.bottom().min(area.y + 3) {
-                    buf.get_mut(area.x, y).set_bg(Color::Blue);
+                    buf.get_mut(area.x, y).set_bg(self.selection_color);
                 }
             }
 
@@ -3,11 +3,18 @@ use ratatui::{buffer::Buffer, layout::Rect, style::Color, widgets::Widget};
 pub struct ScrollBar {
     value: usize,
     max: usize,
+    color: Color,
+    track_color: Color,
 }
 
 impl Default for ScrollBar {
     fn default() -> Self {
-        Self { value: 0, max: 1 }
+        Self {
+            value: 0,
+            max: 1,
+            color: Color::White,
+            track_color: Color::DarkGray,
+        }
     }
 }
 
@@ -17,6 +24,12 @@ impl ScrollBar {
         self.max = max;
         self
     }
+
+    pub fn colors(mut self, color: Color, track_color: Color) -> Self {
+        self.color = color;
+        self.track_color = track_color;
+        self
+    }
 }
 
 impl Widget for ScrollBar {
@@ -28,9 +41,9 @@ impl Widget for ScrollBar {
         for x in area.left()..area.right() {
             for y in area.top()..area.bottom() {
                 buf.get_mut(x, y).set_bg(if (start..end).contains(&y) {
-                    Color::White
+                    self.color
                 } else {
-                    Color::DarkGray
+                    self.track_color
                 });
             }
         }